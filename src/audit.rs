@@ -0,0 +1,838 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rocket::request::{FromRequest, Outcome as RequestOutcome, Request};
+use rocket::serde::json::Json;
+use rocket::tokio::{self, sync::Mutex as AsyncMutex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use sha256::digest;
+use ssi::did::{Document, VerificationRelationship};
+use ssi::vc::{Presentation, ProofPurpose};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{Instrument, Span};
+
+use crate::config::Config;
+use crate::did::ProofParameters;
+use crate::error::DIDError;
+use crate::logging::{self, RequestId};
+use crate::utils::{verify_issuer, verify_presentation_proof};
+
+/// Path of the audit log export.
+pub static AUDIT_LOG_PATH: &str = "/.well-known/audit-log";
+
+/// Hash that the first entry of the audit log is chained to.
+pub static GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Maximum number of rejected operations per source that are recorded individually per [`REJECTED_WINDOW`]. Further
+/// rejected operations of the source are counted and recorded as a single entry with the outcome
+/// [`Outcome::Suppressed`], so that unauthenticated requests can't flood the audit log.
+const REJECTED_LIMIT: u32 = 60;
+
+/// Window that [`REJECTED_LIMIT`] applies to.
+const REJECTED_WINDOW: Duration = Duration::from_secs(60);
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Operations that are recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+    /// Export of the audit log itself.
+    Export,
+}
+
+/// Outcome of a recorded operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Success,
+    Rejected,
+    /// Aggregate of rejected operations that exceeded [`REJECTED_LIMIT`] and weren't recorded individually.
+    Suppressed,
+}
+
+/// Network address that a request has been received from. The address of the peer is used instead of the
+/// `X-Real-IP` header since clients set the header themselves. IPv6 addresses are reduced to their /64 prefix, since
+/// clients usually control the whole prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Source(pub String);
+
+impl Source {
+    /// Derives the source from the address of the peer, or `unknown` if the address isn't known.
+    pub fn from_ip(ip: Option<IpAddr>) -> Source {
+        Source(match ip {
+            Some(IpAddr::V4(ip)) => ip.to_string(),
+            Some(IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+                Some(ip) => ip.to_string(),
+                None => {
+                    let segments = ip.segments();
+                    format!(
+                        "{:x}:{:x}:{:x}:{:x}::/64",
+                        segments[0], segments[1], segments[2], segments[3]
+                    )
+                }
+            },
+            None => "unknown".to_string(),
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Source {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> RequestOutcome<Self, Self::Error> {
+        RequestOutcome::Success(Source::from_ip(request.remote().map(|remote| remote.ip())))
+    }
+}
+
+/// Information about an operation that is collected while the operation is processed.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub operation: Operation,
+    pub request_id: String,
+    /// Source of the request, see [`Source`].
+    pub source: String,
+    /// Requested id, e.g. `alice/did.json`.
+    pub id: String,
+    pub did: Option<String>,
+    /// DID that authorised the operation.
+    pub authorizing_did: Option<String>,
+    /// Verification method of the proof that authorised the operation.
+    pub verification_method: Option<String>,
+    pub challenge: Option<String>,
+    pub old_document: Option<Document>,
+    pub new_document: Option<Document>,
+    /// Submitted presentation.
+    pub presentation: Value,
+}
+
+impl AuditRecord {
+    /// Create a new record for an operation.
+    ///
+    /// - `operation` - recorded operation.
+    /// - `request_id` - ID of the request.
+    /// - `source` - source of the request.
+    /// - `id` - requested id, e.g. `alice/did.json`.
+    /// - `presentation` - submitted presentation.
+    pub fn new(
+        operation: Operation,
+        request_id: &RequestId,
+        source: &Source,
+        id: &Path,
+        presentation: &Presentation,
+    ) -> AuditRecord {
+        AuditRecord {
+            operation,
+            request_id: request_id.to_string(),
+            source: source.0.to_string(),
+            id: id.display().to_string(),
+            did: None,
+            authorizing_did: None,
+            verification_method: None,
+            challenge: None,
+            old_document: None,
+            new_document: None,
+            presentation: serde_json::to_value(presentation).unwrap_or_default(),
+        }
+    }
+}
+
+/// Entry of the audit log. Each entry is chained to its predecessor via `previous_hash`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AuditEntry {
+    pub sequence: u64,
+    pub timestamp: String,
+    pub request_id: String,
+    pub operation: Operation,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Source of the request, see [`Source`]. Entries that have been recorded before sources were recorded have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Number of rejected operations that an entry with the outcome [`Outcome::Suppressed`] aggregates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppressed: Option<u64>,
+    pub id: String,
+    pub did: Option<String>,
+    pub authorizing_did: Option<String>,
+    pub verification_method: Option<String>,
+    pub challenge: Option<String>,
+    /// sha256 hash of the DID document before the operation.
+    pub old_document_hash: Option<String>,
+    /// sha256 hash of the DID document after the operation.
+    pub new_document_hash: Option<String>,
+    /// Submitted presentation. The proof values of rejected operations are redacted, see [`logging::redact`].
+    pub presentation: Value,
+    /// Hash of the previous entry, or [`GENESIS_HASH`] for the first entry.
+    pub previous_hash: String,
+    /// sha256 hash of this entry without `hash` and `signature`.
+    pub hash: String,
+    /// Hex encoded HMAC-SHA256 of `hash`, present if a key is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl AuditEntry {
+    /// Computes the hash of the entry, excluding the fields `hash` and `signature`.
+    fn compute_hash(&self) -> Result<String, DIDError> {
+        serde_json::to_string(&AuditEntry {
+            hash: String::new(),
+            signature: None,
+            ..self.clone()
+        })
+        .map(digest)
+        .map_err(|e| DIDError::ContentConversion(e.to_string()))
+    }
+}
+
+/// Position of the most recent entry of the audit log.
+///
+/// * `challenge` - Hash of the most recent successful operation. It's the challenge for exporting the audit log, so
+///   that rejected requests can't invalidate the owner's export presentation.
+#[derive(Debug, Clone)]
+struct Head {
+    sequence: u64,
+    hash: String,
+    challenge: String,
+}
+
+/// Number of rejected operations of a source that were recorded in the current window, and the number of rejected
+/// operations per operation that were suppressed.
+#[derive(Debug)]
+struct RejectedWindow {
+    start: Instant,
+    count: u32,
+    suppressed: HashMap<Operation, u64>,
+}
+
+/// Rejected operations per source, and the suppressed operations of elapsed windows that haven't been recorded yet.
+#[derive(Debug, Default)]
+struct Rejections {
+    windows: HashMap<String, RejectedWindow>,
+    pending: Vec<(String, Operation, u64)>,
+}
+
+impl Rejections {
+    /// Moves the suppressed operations of elapsed windows to the pending operations and removes the windows.
+    fn expire(&mut self) {
+        let pending = &mut self.pending;
+        self.windows.retain(|source, window| {
+            if window.start.elapsed() < REJECTED_WINDOW {
+                return true;
+            }
+            pending.extend(
+                window
+                    .suppressed
+                    .drain()
+                    .map(|(operation, count)| (source.to_string(), operation, count)),
+            );
+            false
+        });
+    }
+}
+
+/// Append-only, hash-chained log of all mutating operations. Entries are kept in memory or appended to a file with
+/// one JSON encoded entry per line.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: Option<PathBuf>,
+    key: Option<String>,
+    entries: Mutex<Vec<AuditEntry>>,
+    head: Mutex<Head>,
+    /// Held while an entry is written or the entries are read, so that entries are written in order and never read
+    /// partially.
+    writer: AsyncMutex<()>,
+    rejected: Mutex<Rejections>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self {
+            file: None,
+            key: None,
+            entries: Mutex::new(vec![]),
+            head: Mutex::new(Head {
+                sequence: 0,
+                hash: GENESIS_HASH.to_string(),
+                challenge: GENESIS_HASH.to_string(),
+            }),
+            writer: AsyncMutex::new(()),
+            rejected: Mutex::new(Rejections::default()),
+        }
+    }
+}
+
+impl AuditLog {
+    /// Opens the audit log. Existing entries are verified and the log is continued.
+    ///
+    /// - `file` - file that entries are appended to, entries are kept in memory if not set.
+    /// - `key` - key for signing entries with HMAC-SHA256.
+    pub fn new(file: Option<PathBuf>, key: Option<String>) -> Result<AuditLog, DIDError> {
+        let audit_log = AuditLog {
+            file,
+            key,
+            ..AuditLog::default()
+        };
+        let entries = match &audit_log.file {
+            Some(file) => read_entries(file)?,
+            None => vec![],
+        };
+        verify_chain(&entries, audit_log.key.as_deref())?;
+        if let Some(last) = entries.last() {
+            let mut head = audit_log.head.lock().unwrap();
+            head.sequence = last.sequence;
+            head.hash = last.hash.to_string();
+            if let Some(success) = entries
+                .iter()
+                .rev()
+                .find(|entry| entry.outcome == Outcome::Success)
+            {
+                head.challenge = success.hash.to_string();
+            }
+        }
+        Ok(audit_log)
+    }
+
+    /// Hash of the most recent entry.
+    pub fn head(&self) -> String {
+        self.head.lock().unwrap().hash.to_string()
+    }
+
+    /// Challenge for exporting the audit log, i.e. the hash of the most recent successful operation.
+    pub fn challenge(&self) -> String {
+        self.head.lock().unwrap().challenge.to_string()
+    }
+
+    /// Records the outcome of an operation. Rejected operations that exceed [`REJECTED_LIMIT`] are only counted. The
+    /// count is recorded as a single entry per source and operation once the window has elapsed, at the latest
+    /// together with the next recorded operation.
+    ///
+    /// - `record` - information about the operation.
+    /// - `result` - result of the operation.
+    /// - returns the recorded entry, or `None` if too many operations of the source were rejected recently
+    pub async fn record<T>(
+        &self,
+        record: AuditRecord,
+        result: &Result<T, DIDError>,
+    ) -> Result<Option<AuditEntry>, DIDError> {
+        let admitted = result.is_ok() || self.admit_rejected(&record);
+        let hash_document = |document: &Option<Document>| {
+            document
+                .as_ref()
+                .and_then(|document| serde_json::to_string(document).ok())
+                .map(digest)
+        };
+        // the writer is locked until the entry has been written to preserve the order of entries
+        let _writer = self.writer.lock().await;
+        self.append_suppressed().await?;
+        if !admitted {
+            return Ok(None);
+        }
+        let entry = AuditEntry {
+            sequence: 0,
+            timestamp: Utc::now().to_rfc3339(),
+            request_id: record.request_id,
+            operation: record.operation,
+            outcome: if result.is_ok() {
+                Outcome::Success
+            } else {
+                Outcome::Rejected
+            },
+            error: result.as_ref().err().map(|e| e.to_string()),
+            source: Some(record.source),
+            suppressed: None,
+            id: record.id,
+            did: record.did,
+            authorizing_did: record.authorizing_did,
+            verification_method: record.verification_method,
+            challenge: record.challenge,
+            old_document_hash: hash_document(&record.old_document),
+            new_document_hash: hash_document(&record.new_document),
+            // a rejected presentation might still be valid for the current challenge, e.g. if the store failed, so its
+            // proofs aren't recorded
+            presentation: match result {
+                Ok(_) => record.presentation,
+                Err(_) => logging::redact(record.presentation),
+            },
+            previous_hash: String::new(),
+            hash: String::new(),
+            signature: None,
+        };
+        self.chain(entry).await.map(Some)
+    }
+
+    /// Chains an entry to the most recent entry, signs and persists it. The writer must be locked.
+    async fn chain(&self, mut entry: AuditEntry) -> Result<AuditEntry, DIDError> {
+        let head = self.head.lock().unwrap().clone();
+        entry.sequence = head.sequence + 1;
+        entry.previous_hash = head.hash.to_string();
+        entry.hash = entry.compute_hash()?;
+        entry.signature = self.key.as_deref().map(|key| sign(key, &entry.hash));
+        self.append(&entry).await?;
+        let mut head = self.head.lock().unwrap();
+        head.sequence = entry.sequence;
+        head.hash = entry.hash.to_string();
+        if entry.outcome == Outcome::Success {
+            head.challenge = entry.hash.to_string();
+        }
+        Ok(entry)
+    }
+
+    /// Records the suppressed operations of elapsed windows. The writer must be locked.
+    async fn append_suppressed(&self) -> Result<(), DIDError> {
+        let pending = {
+            let mut rejected = self.rejected.lock().unwrap();
+            rejected.expire();
+            std::mem::take(&mut rejected.pending)
+        };
+        for (source, operation, count) in pending {
+            self.chain(AuditEntry {
+                sequence: 0,
+                timestamp: Utc::now().to_rfc3339(),
+                request_id: String::new(),
+                operation,
+                outcome: Outcome::Suppressed,
+                error: Some(format!("{} rejected operations suppressed", count)),
+                source: Some(source),
+                suppressed: Some(count),
+                id: String::new(),
+                did: None,
+                authorizing_did: None,
+                verification_method: None,
+                challenge: None,
+                old_document_hash: None,
+                new_document_hash: None,
+                presentation: Value::Null,
+                previous_hash: String::new(),
+                hash: String::new(),
+                signature: None,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Counts a rejected operation of a source and determines whether it's recorded individually.
+    fn admit_rejected(&self, record: &AuditRecord) -> bool {
+        let mut rejected = self.rejected.lock().unwrap();
+        rejected.expire();
+        let window = rejected
+            .windows
+            .entry(record.source.to_string())
+            .or_insert_with(|| RejectedWindow {
+                start: Instant::now(),
+                count: 0,
+                suppressed: HashMap::new(),
+            });
+        window.count += 1;
+        if window.count > REJECTED_LIMIT {
+            *window.suppressed.entry(record.operation).or_insert(0) += 1;
+            tracing::warn!(
+                source = record.source.as_str(),
+                "too many rejected operations, operation only counted in audit log"
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Records the outcome of an operation. Failures to record the entry are logged since the operation has already
+    /// been performed.
+    pub async fn record_or_log<T>(&self, record: AuditRecord, result: &Result<T, DIDError>) {
+        if let Err(e) = self.record(record, result).await {
+            tracing::error!(
+                error = e.name(),
+                reason = e.message(),
+                "operation not recorded in audit log"
+            );
+        }
+    }
+
+    /// Retrieves all entries of the audit log.
+    pub async fn entries(&self) -> Result<Vec<AuditEntry>, DIDError> {
+        // entries aren't read while an entry is written
+        let _writer = self.writer.lock().await;
+        match &self.file {
+            Some(file) => {
+                let file = file.to_path_buf();
+                tokio::task::spawn_blocking(move || read_entries(&file))
+                    .await
+                    .map_err(|e| DIDError::NoFileRead(e.to_string()))?
+            }
+            None => Ok(self.entries.lock().unwrap().clone()),
+        }
+    }
+
+    /// Persists an entry.
+    async fn append(&self, entry: &AuditEntry) -> Result<(), DIDError> {
+        match &self.file {
+            Some(file) => {
+                let line = serde_json::to_string(entry)
+                    .map_err(|e| DIDError::ContentConversion(e.to_string()))?;
+                let file = file.to_path_buf();
+                tokio::task::spawn_blocking(move || append_line(&file, &line))
+                    .await
+                    .map_err(|e| DIDError::NoFileWrite(e.to_string()))?
+            }
+            None => {
+                self.entries.lock().unwrap().push(entry.clone());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reads the entries of an audit log file.
+fn read_entries(file: &Path) -> Result<Vec<AuditEntry>, DIDError> {
+    if !file.exists() {
+        return Ok(vec![]);
+    }
+    fs::read_to_string(file)
+        .map_err(|e| DIDError::NoFileRead(e.to_string()))
+        .and_then(|content| {
+            content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    serde_json::from_str::<AuditEntry>(line)
+                        .map_err(|e| DIDError::ContentConversion(e.to_string()))
+                })
+                .collect()
+        })
+}
+
+/// Appends a line to a file and waits until it has been written to disk.
+pub(crate) fn append_line(file: &Path, line: &str) -> Result<(), DIDError> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)
+        .and_then(|mut f| {
+            writeln!(f, "{}", line)?;
+            f.sync_data()
+        })
+        .map_err(|e| DIDError::NoFileWrite(e.to_string()))
+}
+
+/// Computes the hex encoded HMAC-SHA256 of `message`.
+pub(crate) fn sign(key: &str, message: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(message.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Verifies that the entries form an unbroken chain and haven't been modified.
+///
+/// - `entries` - entries of the audit log, starting with the first entry.
+/// - `key` - key for verifying the signatures of the entries. Signatures aren't verified if not set.
+pub fn verify_chain(entries: &[AuditEntry], key: Option<&str>) -> Result<(), DIDError> {
+    let mut previous: (u64, &str) = (0, GENESIS_HASH);
+    for entry in entries {
+        let tampered = |reason: &str| {
+            DIDError::AuditLogTampered(format!("audit log entry {} {}", entry.sequence, reason))
+        };
+        if entry.sequence != previous.0 + 1 {
+            return Err(tampered("is out of sequence"));
+        }
+        if entry.previous_hash != previous.1 {
+            return Err(tampered("isn't chained to its predecessor"));
+        }
+        if entry.compute_hash()? != entry.hash {
+            return Err(tampered("has been modified"));
+        }
+        if let Some(key) = key {
+            if entry.signature.as_deref() != Some(sign(key, &entry.hash).as_str()) {
+                return Err(tampered("has an invalid signature"));
+            }
+        }
+        previous = (entry.sequence, &entry.hash);
+    }
+    Ok(())
+}
+
+/// Proof parameters for exporting the audit log. Only the server's owner is allowed to export the audit log. The
+/// challenge is the hash of the most recent successful operation. Since every successful export is recorded, a
+/// presentation can't be reused, and rejected requests don't change the challenge.
+fn proof_parameters(config: &Config) -> ProofParameters {
+    ProofParameters {
+        did: config.policy.load().owner.to_string(),
+        challenge: Some(config.audit_log.challenge()),
+        domain: config.external_hostname.to_string(),
+        proof_purpose: ProofPurpose::Authentication,
+    }
+}
+
+/// Retrieve the proof parameters for exporting the audit log.
+///
+/// - `config` Global Rocket configuration
+/// - returns ProofParameters
+#[get("/.well-known/audit-log?proofParameters")]
+pub fn get_audit_log_proof_parameters(config: &rocket::State<Config>) -> Json<ProofParameters> {
+    Json(proof_parameters(config))
+}
+
+/// Export all entries of the audit log. Only the server's owner is allowed to export the audit log.
+///
+/// * `config` - the server configuration.
+/// * `request_id` - ID of the request.
+/// * `source` - source of the request.
+/// * `presentation` - verifable presentation signed by the owner with the audit log's proof parameters.
+/// - returns all entries of the audit log including the entry that records this export
+#[post("/.well-known/audit-log", data = "<presentation>")]
+pub async fn export_audit_log(
+    config: &rocket::State<Config>,
+    request_id: RequestId,
+    source: Source,
+    presentation: Json<Presentation>,
+) -> Result<Json<Vec<AuditEntry>>, DIDError> {
+    let id = PathBuf::from(AUDIT_LOG_PATH);
+    let span = logging::operation_span(&request_id, "export", &id);
+    let mut audit = AuditRecord::new(Operation::Export, &request_id, &source, &id, &presentation);
+    let result = async {
+        logging::log_presentation(&presentation);
+        let proof_parameters = proof_parameters(config);
+        audit.authorizing_did = Some(proof_parameters.did.to_string());
+        audit.challenge = proof_parameters.challenge.clone();
+        let verification_method = verify_issuer(
+            config,
+            &proof_parameters.did,
+            VerificationRelationship::Authentication,
+            &presentation,
+        )
+        .await?;
+        Span::current().record("verification_method", verification_method.as_str());
+        audit.verification_method = Some(verification_method);
+        verify_presentation_proof(config, &proof_parameters, &presentation)
+            .await
+            .map(|_| ())
+    }
+    .instrument(span.clone())
+    .await;
+    span.in_scope(|| logging::log_outcome(&result));
+    config
+        .audit_log
+        .record_or_log(audit, &result)
+        .instrument(span)
+        .await;
+    result?;
+    config.audit_log.entries().await.map(Json)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::audit::*;
+    use ssi::vc::Presentation;
+
+    fn record(operation: Operation) -> AuditRecord {
+        AuditRecord {
+            did: Some("did:web:example.com:alice".to_string()),
+            authorizing_did: Some("did:key:z6Mk".to_string()),
+            verification_method: Some("did:key:z6Mk#z6Mk".to_string()),
+            ..AuditRecord::new(
+                operation,
+                &RequestId("1".to_string()),
+                &Source("192.0.2.1".to_string()),
+                &PathBuf::from("alice/did.json"),
+                &Presentation::default(),
+            )
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_audit_log_chain() {
+        let audit_log = AuditLog::default();
+        let first = audit_log
+            .record(record(Operation::Create), &Ok::<(), DIDError>(()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            first.previous_hash, GENESIS_HASH,
+            "When the first entry is recorded, then it's chained to the genesis hash"
+        );
+        let second = audit_log
+            .record(
+                AuditRecord {
+                    presentation: serde_json::json!({"proof": {"jws": "signature"}}),
+                    ..record(Operation::Delete)
+                },
+                &Err::<(), DIDError>(DIDError::PresentationInvalid("invalid".to_string())),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            second.previous_hash, first.hash,
+            "When an entry is recorded, then it's chained to its predecessor"
+        );
+        assert_eq!(
+            second.outcome,
+            Outcome::Rejected,
+            "When a rejected operation is recorded, then its outcome is rejected"
+        );
+        assert_eq!(audit_log.head(), second.hash);
+        assert_eq!(
+            audit_log.challenge(),
+            first.hash,
+            "When an operation is rejected, then the export challenge doesn't change"
+        );
+        assert_eq!(
+            second.presentation,
+            serde_json::json!({"proof": {"jws": "[REDACTED]"}}),
+            "When an operation is rejected, then the presentation is recorded without its proof values"
+        );
+        assert_eq!(
+            second.source.as_deref(),
+            Some("192.0.2.1"),
+            "When an operation is recorded, then its source is recorded"
+        );
+
+        let mut entries = audit_log.entries().await.unwrap();
+        assert!(
+            verify_chain(&entries, None).is_ok(),
+            "When the entries haven't been modified, then the chain is valid"
+        );
+        entries[0].authorizing_did = Some("did:key:attacker".to_string());
+        assert!(
+            verify_chain(&entries, None).is_err(),
+            "When an entry has been modified, then the chain is invalid"
+        );
+
+        let mut entries = audit_log.entries().await.unwrap();
+        entries.remove(0);
+        assert!(
+            verify_chain(&entries, None).is_err(),
+            "When an entry has been removed, then the chain is invalid"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_audit_log_rejected_limit() {
+        let audit_log = AuditLog::default();
+        let rejected = Err::<(), DIDError>(DIDError::PresentationInvalid("invalid".to_string()));
+        for _ in 0..REJECTED_LIMIT {
+            audit_log
+                .record(record(Operation::Create), &rejected)
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        for _ in 0..3 {
+            assert!(
+                audit_log
+                    .record(record(Operation::Create), &rejected)
+                    .await
+                    .unwrap()
+                    .is_none(),
+                "When too many operations of a source are rejected, then further rejections aren't recorded individually"
+            );
+        }
+        assert!(
+            audit_log
+                .record(
+                    AuditRecord {
+                        source: "192.0.2.2".to_string(),
+                        ..record(Operation::Create)
+                    },
+                    &rejected
+                )
+                .await
+                .unwrap()
+                .is_some(),
+            "When too many operations of a source are rejected, then rejections of other sources are still recorded"
+        );
+        assert!(
+            audit_log
+                .record(record(Operation::Create), &Ok::<(), DIDError>(()))
+                .await
+                .unwrap()
+                .is_some(),
+            "When too many operations are rejected, then successful operations are still recorded"
+        );
+
+        // let the window elapse
+        for window in audit_log.rejected.lock().unwrap().windows.values_mut() {
+            window.start = Instant::now()
+                .checked_sub(REJECTED_WINDOW)
+                .unwrap_or(window.start);
+        }
+        let entry = audit_log
+            .record(record(Operation::Create), &rejected)
+            .await
+            .unwrap()
+            .unwrap();
+        let entries = audit_log.entries().await.unwrap();
+        let suppressed = &entries[entries.len() - 2];
+        assert_eq!(
+            (suppressed.outcome, suppressed.suppressed, suppressed.source.as_deref()),
+            (Outcome::Suppressed, Some(3), Some("192.0.2.1")),
+            "When the window has elapsed, then the suppressed rejections are recorded as a single entry"
+        );
+        assert_eq!(
+            entry.previous_hash, suppressed.hash,
+            "When suppressed rejections are recorded, then the entry is part of the chain"
+        );
+        assert!(verify_chain(&entries, None).is_ok());
+    }
+
+    #[rocket::async_test]
+    async fn test_audit_log_signature() {
+        let audit_log = AuditLog::new(None, Some("secret".to_string())).unwrap();
+        audit_log
+            .record(record(Operation::Update), &Ok::<(), DIDError>(()))
+            .await
+            .unwrap()
+            .unwrap();
+        let entries = audit_log.entries().await.unwrap();
+        assert!(
+            verify_chain(&entries, Some("secret")).is_ok(),
+            "When entries are verified with the signing key, then the signatures are valid"
+        );
+        assert!(
+            verify_chain(&entries, Some("other")).is_err(),
+            "When entries are verified with a different key, then the signatures are invalid"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_audit_log_file() {
+        let file = std::env::temp_dir().join(format!("dws-audit-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&file);
+        let audit_log = AuditLog::new(Some(file.clone()), None).unwrap();
+        let first = audit_log
+            .record(record(Operation::Create), &Ok::<(), DIDError>(()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let audit_log = AuditLog::new(Some(file.clone()), None).unwrap();
+        assert_eq!(
+            audit_log.head(),
+            first.hash,
+            "When an existing audit log is opened, then it's continued"
+        );
+        let second = audit_log
+            .record(record(Operation::Update), &Ok::<(), DIDError>(()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.sequence, 2);
+        assert_eq!(audit_log.entries().await.unwrap().len(), 2);
+        fs::remove_file(&file).unwrap();
+    }
+}
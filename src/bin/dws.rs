@@ -0,0 +1,486 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use chrono::Utc;
+use did_method_key::DIDKey;
+use did_web_server::{did_methods, ProofParameters};
+use ssi::did::{DIDMethod, Document, Source, VerificationMethod, VerificationRelationship};
+use ssi::did_resolve::{DIDResolver, ResolutionInputMetadata};
+use ssi::jwk::JWK;
+use ssi::one_or_many::OneOrMany;
+use ssi::vc::{
+    Context, Contexts, Credential, CredentialOrJWT, CredentialSubject, Issuer,
+    LinkedDataProofOptions, Presentation, ProofPurpose, VCDateTime, URI,
+};
+use ssi_json_ld::ContextLoader;
+use std::collections::HashMap;
+use std::process;
+use url::Url;
+
+static USAGE: &str = "Usage: dws <create|update|deactivate> <url> --key <jwk-file> [--document <did-document-file>] [--issuer <did>] [--verification-method <id>]
+
+Commands:
+  create      Register the DID document at <url>, e.g. https://example.com/alice/did.json. Requires --document
+  update      Replace the DID document at <url>. Requires --document
+  deactivate  Remove the DID document at <url>
+
+Options:
+  --key                  JWK file of the private key that signs the presentation
+  --document             DID document to register or update
+  --issuer               DID that signs the presentation. Defaults to the did:key of --key for create and deactivate,
+                         and to the DID at <url> for update
+  --verification-method  Verification method of the issuer that matches --key. Defaults to the issuer's first
+                         verification method with the public key of --key for the proof purpose, i.e.
+                         assertionMethod for the credential and authentication for the presentation";
+
+/// Operation that is performed on the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Create,
+    Update,
+    Deactivate,
+}
+
+/// Options of the command-line client.
+///
+/// * `command` - operation that is performed
+/// * `url` - location of the DID document, e.g. `https://example.com/alice/did.json`
+/// * `key` - private key that signs the credential and presentation
+/// * `document` - DID document that is created or updated
+/// * `issuer` - DID that signs the credential and presentation
+/// * `verification_method` - verification method of the issuer that corresponds to `key`
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub url: Url,
+    pub key: JWK,
+    pub document: Option<Document>,
+    pub issuer: Option<String>,
+    pub verification_method: Option<String>,
+}
+
+impl Options {
+    /// Parses the command-line arguments without the program name.
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut args = args.iter();
+        let command = match args.next().map(String::as_str) {
+            Some("create") => Command::Create,
+            Some("update") => Command::Update,
+            Some("deactivate") => Command::Deactivate,
+            Some(command) => return Err(format!("Unknown command {}", command)),
+            None => return Err("Command missing".to_string()),
+        };
+        let url = args
+            .next()
+            .ok_or_else(|| "URL missing".to_string())
+            .and_then(|url| Url::parse(url).map_err(|e| format!("URL invalid: {}", e)))?;
+        let mut flags = HashMap::new();
+        while let Some(flag) = args.next() {
+            let name = match flag.as_str() {
+                "--key" | "--document" | "--issuer" | "--verification-method" => flag.as_str(),
+                _ => return Err(format!("Unknown option {}", flag)),
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Value of {} missing", name))?;
+            flags.insert(name, value.to_string());
+        }
+        let read_json = |name: &str| -> Result<Option<String>, String> {
+            flags
+                .get(name)
+                .map(|file| {
+                    std::fs::read_to_string(file)
+                        .map_err(|e| format!("{} not readable: {}", file, e))
+                })
+                .transpose()
+        };
+        let key = read_json("--key")?
+            .ok_or_else(|| "--key missing".to_string())
+            .and_then(|key| {
+                serde_json::from_str::<JWK>(&key).map_err(|e| format!("Key invalid: {}", e))
+            })?;
+        let document = read_json("--document")?
+            .map(|document| {
+                serde_json::from_str::<Document>(&document)
+                    .map_err(|e| format!("DID document invalid: {}", e))
+            })
+            .transpose()?;
+        if document.is_none() && command != Command::Deactivate {
+            return Err("--document missing".to_string());
+        }
+        Ok(Options {
+            command,
+            url,
+            key,
+            document,
+            issuer: flags.get("--issuer").cloned(),
+            verification_method: flags.get("--verification-method").cloned(),
+        })
+    }
+}
+
+/// Finds the verification method of a DID that proofs the given relationship with the given key.
+///
+/// - `resolver` - resolver for the issuer's DID
+/// - `did` - DID of the issuer
+/// - `key` - private key of the issuer
+/// - `relationship` - verification relationship that matches the proof purpose, e.g. `assertionMethod`
+/// - returns the ID of the first verification method of the relationship whose public key matches `key`
+async fn find_verification_method(
+    resolver: &dyn DIDResolver,
+    did: &str,
+    key: &JWK,
+    relationship: VerificationRelationship,
+) -> Result<String, String> {
+    let (metadata, document, _) = resolver
+        .resolve(did, &ResolutionInputMetadata::default())
+        .await;
+    let document = match (metadata.error, document) {
+        (None, Some(document)) => document,
+        (error, _) => {
+            return Err(format!(
+                "DID {} couldn't be resolved: {}",
+                did,
+                error.unwrap_or_default()
+            ))
+        }
+    };
+    verification_method(&document, did, key, relationship.clone()).ok_or_else(|| {
+        format!(
+            "DID {} has no {:?} method for the key, set --verification-method",
+            did, relationship
+        )
+    })
+}
+
+/// Selects the verification method of a DID document that matches the key and relationship.
+///
+/// - `document` - DID document of the issuer
+/// - `did` - DID of the issuer
+/// - `key` - private key of the issuer
+/// - `relationship` - verification relationship that matches the proof purpose
+/// - returns the ID of the first verification method of the relationship whose public key matches `key`
+fn verification_method(
+    document: &Document,
+    did: &str,
+    key: &JWK,
+    relationship: VerificationRelationship,
+) -> Option<String> {
+    let ids = document
+        .get_verification_method_ids(relationship)
+        .unwrap_or_default();
+    document
+        .verification_method
+        .iter()
+        .flatten()
+        .filter_map(|method| match method {
+            VerificationMethod::Map(method) => Some(method),
+            _ => None,
+        })
+        .filter(|method| {
+            method
+                .public_key_jwk
+                .as_ref()
+                .map_or(false, |jwk| jwk.equals_public(key))
+        })
+        .map(|method| {
+            // verification methods might be identified relative to the DID
+            if method.id.starts_with('#') {
+                format!("{}{}", did, method.id)
+            } else {
+                method.id.to_string()
+            }
+        })
+        .find(|id| ids.contains(id))
+}
+
+/// Builds the presentation that's submitted to the server. The DID document is wrapped in a credential that's issued
+/// for the DID. Both credential and presentation are signed by the issuer.
+///
+/// - `options` - client options
+/// - `proof_parameters` - proof parameters retrieved from the server
+async fn presentation(
+    options: &Options,
+    proof_parameters: &ProofParameters,
+) -> Result<Presentation, String> {
+    // resolve the issuer with the same DID methods as the server
+    let resolver = did_methods().to_resolver();
+    let issuer = match (&options.issuer, options.command) {
+        (Some(issuer), _) => issuer.to_string(),
+        // the DID's controller updates the DID document
+        (None, Command::Update) => proof_parameters.did.to_string(),
+        (None, _) => DIDKey
+            .generate(&Source::Key(&options.key))
+            .ok_or_else(|| "did:key couldn't be derived from the key".to_string())?,
+    };
+    // the credential asserts the DID document while the presentation proofs the purpose requested by the server
+    let (credential_method, presentation_method) = match &options.verification_method {
+        Some(verification_method) => (
+            verification_method.to_string(),
+            verification_method.to_string(),
+        ),
+        None => (
+            find_verification_method(
+                resolver,
+                &issuer,
+                &options.key,
+                VerificationRelationship::AssertionMethod,
+            )
+            .await?,
+            find_verification_method(
+                resolver,
+                &issuer,
+                &options.key,
+                proof_parameters.proof_purpose.clone(),
+            )
+            .await?,
+        ),
+    };
+    let attributes = match &options.document {
+        Some(document) if document.id != proof_parameters.did => {
+            return Err(format!(
+                "DID document id {} doesn't match DID {}",
+                document.id, proof_parameters.did
+            ))
+        }
+        Some(document) => {
+            let mut attributes = serde_json::to_value(document)
+                .ok()
+                .and_then(|document| document.as_object().cloned())
+                .ok_or_else(|| "DID document couldn't be converted".to_string())?;
+            attributes.remove("id");
+            Some(attributes.into_iter().collect::<HashMap<_, _>>())
+        }
+        None => None,
+    };
+
+    let mut context_loader = ContextLoader::default();
+    let mut credential = Credential {
+        context: Contexts::One(Context::URI(URI::String(
+            "https://www.w3.org/2018/credentials/v1".to_string(),
+        ))),
+        id: None,
+        type_: OneOrMany::One("VerifiableCredential".to_string()),
+        credential_subject: OneOrMany::One(CredentialSubject {
+            id: Some(URI::String(proof_parameters.did.to_string())),
+            property_set: attributes,
+        }),
+        issuer: Some(Issuer::URI(URI::String(issuer.to_string()))),
+        issuance_date: Some(VCDateTime::from(Utc::now())),
+        proof: None,
+        expiration_date: None,
+        credential_status: None,
+        terms_of_use: None,
+        evidence: None,
+        credential_schema: None,
+        refresh_service: None,
+        property_set: None,
+    };
+    let proof = credential
+        .generate_proof(
+            &options.key,
+            &LinkedDataProofOptions {
+                proof_purpose: Some(ProofPurpose::AssertionMethod),
+                verification_method: Some(URI::String(credential_method)),
+                ..LinkedDataProofOptions::default()
+            },
+            resolver,
+            &mut context_loader,
+        )
+        .await
+        .map_err(|e| format!("Credential couldn't be signed: {}", e))?;
+    credential.add_proof(proof);
+
+    let mut presentation = Presentation {
+        holder: Some(URI::String(issuer)),
+        verifiable_credential: Some(OneOrMany::One(CredentialOrJWT::Credential(credential))),
+        ..Presentation::default()
+    };
+    let proof = presentation
+        .generate_proof(
+            &options.key,
+            &LinkedDataProofOptions {
+                domain: Some(proof_parameters.domain.to_string()),
+                challenge: proof_parameters.challenge.clone(),
+                proof_purpose: Some(proof_parameters.proof_purpose.clone()),
+                verification_method: Some(URI::String(presentation_method)),
+                ..LinkedDataProofOptions::default()
+            },
+            resolver,
+            &mut context_loader,
+        )
+        .await
+        .map_err(|e| format!("Presentation couldn't be signed: {}", e))?;
+    presentation.add_proof(proof);
+    Ok(presentation)
+}
+
+/// Retrieves the proof parameters, signs the presentation and submits it to the server.
+///
+/// - returns the response of the server
+async fn execute(options: &Options) -> Result<String, String> {
+    let client = reqwest::Client::new();
+    let mut url = options.url.clone();
+    url.set_query(Some("proofParameters"));
+    let proof_parameters = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Proof parameters couldn't be retrieved: {}", e))?
+        .json::<ProofParameters>()
+        .await
+        .map_err(|e| format!("Proof parameters invalid: {}", e))?;
+    let presentation = presentation(options, &proof_parameters).await?;
+    let request = match options.command {
+        Command::Create => client.post(options.url.clone()),
+        Command::Update => client.put(options.url.clone()),
+        Command::Deactivate => client.delete(options.url.clone()),
+    };
+    let response = request
+        .json(&presentation)
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(format!("{}: {}", status, body))
+    }
+}
+
+/// Runs the command-line client and exits with its result.
+#[rocket::main]
+async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    process::exit(run(&args).await);
+}
+
+/// Runs the command-line client.
+///
+/// - `args` - command-line arguments without the program name
+/// - returns the exit code of the process
+async fn run(args: &[String]) -> i32 {
+    match Options::parse(args) {
+        Ok(options) => match execute(&options).await {
+            Ok(body) => {
+                println!("{}", body);
+                0
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                1
+            }
+        },
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_options_parse() {
+        let options = Options::parse(&args(&[
+            "deactivate",
+            "https://example.com/alice/did.json",
+            "--key",
+            "./src/__fixtures__/owner.jwk",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, Command::Deactivate);
+        assert_eq!(options.url.path(), "/alice/did.json");
+        assert!(
+            Options::parse(&args(&[
+                "create",
+                "https://example.com/alice/did.json",
+                "--key",
+                "./src/__fixtures__/owner.jwk",
+            ]))
+            .is_err(),
+            "When a DID is created without document, then an error is returned"
+        );
+        assert!(
+            Options::parse(&args(&["remove", "https://example.com/alice/did.json"])).is_err(),
+            "When the command is unknown, then an error is returned"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_presentation() {
+        let options = Options::parse(&args(&[
+            "create",
+            "http://localhost:8000/valid-did/did.json",
+            "--key",
+            "./src/__fixtures__/owner.jwk",
+            "--document",
+            "./src/__fixtures__/valid-did.json",
+        ]))
+        .unwrap();
+        let proof_parameters = ProofParameters {
+            did: "did:web:localhost%3A8000:valid-did".to_string(),
+            challenge: Some("challenge".to_string()),
+            domain: "localhost".to_string(),
+            proof_purpose: ProofPurpose::Authentication,
+        };
+        let presentation = presentation(&options, &proof_parameters).await.unwrap();
+        let proof = presentation.proof.unwrap().first().cloned().unwrap();
+        assert_eq!(
+            (proof.challenge.as_deref(), proof.domain.as_deref()),
+            (Some("challenge"), Some("localhost")),
+            "When a presentation is built, then the proof parameters of the server are used"
+        );
+        assert_eq!(
+            proof.proof_purpose,
+            Some(ProofPurpose::Authentication),
+            "When a presentation is built, then it's signed for the proof purpose requested by the server"
+        );
+        assert!(
+            proof.verification_method.unwrap().starts_with("did:key:"),
+            "When no issuer is set for create, then the key's did:key is used"
+        );
+    }
+
+    #[test]
+    fn test_verification_method() {
+        let mut document = serde_json::from_str::<Document>(
+            &std::fs::read_to_string("./src/__fixtures__/valid-did.json").unwrap(),
+        )
+        .unwrap();
+        let key = serde_json::from_str::<JWK>(
+            &std::fs::read_to_string("./src/__fixtures__/valid-did.jwk").unwrap(),
+        )
+        .unwrap();
+        let did = "did:web:localhost%3A8000:valid-did";
+        assert_eq!(
+            verification_method(
+                &document,
+                did,
+                &key,
+                VerificationRelationship::AssertionMethod
+            ),
+            Some(format!("{}#controller", did)),
+            "When the key is an assertion method, then it's selected for the credential"
+        );
+        document.assertion_method = None;
+        assert_eq!(
+            verification_method(&document, did, &key, VerificationRelationship::AssertionMethod),
+            None,
+            "When the key is only an authentication method, then it's not selected for the credential"
+        );
+        assert_eq!(
+            verification_method(&document, did, &key, VerificationRelationship::Authentication),
+            Some(format!("{}#controller", did)),
+            "When the key is only an authentication method, then it's selected for the presentation"
+        );
+    }
+}
@@ -0,0 +1,344 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use async_trait::async_trait;
+use ssi::did::Document;
+use ssi::did_resolve::{
+    DIDResolver, DocumentMetadata, ResolutionInputMetadata, ResolutionMetadata,
+    ERROR_METHOD_NOT_SUPPORTED, ERROR_NOT_FOUND,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, Policy};
+use crate::metrics;
+use crate::resolution::StoreResolver;
+
+type Resolution = (
+    ResolutionMetadata,
+    Option<Document>,
+    Option<DocumentMetadata>,
+);
+
+/// Cached resolution of a DID.
+///
+/// * `used` - Position of the most recent use in [`Entries::order`]
+struct CacheEntry {
+    resolution: Resolution,
+    expires: Instant,
+    used: u64,
+}
+
+/// Cached resolutions and the order of their most recent use, so that the least recently used entry is evicted
+/// without scanning all entries.
+#[derive(Default)]
+struct Entries {
+    resolutions: HashMap<String, CacheEntry>,
+    order: BTreeMap<u64, String>,
+    uses: u64,
+}
+
+impl Entries {
+    /// Marks an entry as most recently used.
+    fn touch(&mut self, did: &str) {
+        if let Some(entry) = self.resolutions.get_mut(did) {
+            self.order.remove(&entry.used);
+            self.uses += 1;
+            entry.used = self.uses;
+            self.order.insert(self.uses, did.to_string());
+        }
+    }
+
+    fn remove(&mut self, did: &str) {
+        if let Some(entry) = self.resolutions.remove(did) {
+            self.order.remove(&entry.used);
+        }
+    }
+}
+
+/// Cache for resolutions of remote DIDs.
+///
+/// * `ttl` - Duration that successful resolutions are cached for. Caching is disabled if zero.
+/// * `negative_ttl` - Duration that DIDs which couldn't be found are cached for. Negative caching is disabled if zero.
+/// * `max_stale` - Duration after expiry that a successful resolution is still used if the resolver fails temporarily
+/// * `capacity` - Maximum number of cached DIDs. The least recently used DID is evicted first.
+///
+/// Clones share the cached entries.
+#[derive(Clone)]
+pub struct ResolverCache {
+    pub ttl: Duration,
+    pub negative_ttl: Duration,
+    pub max_stale: Duration,
+    pub capacity: usize,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl Default for ResolverCache {
+    fn default() -> Self {
+        ResolverCache::new(
+            Duration::from_secs(300),
+            Duration::from_secs(30),
+            Duration::from_secs(3600),
+            1000,
+        )
+    }
+}
+
+impl ResolverCache {
+    pub fn new(
+        ttl: Duration,
+        negative_ttl: Duration,
+        max_stale: Duration,
+        capacity: usize,
+    ) -> ResolverCache {
+        ResolverCache {
+            ttl,
+            negative_ttl,
+            max_stale,
+            capacity,
+            entries: Arc::default(),
+        }
+    }
+
+    /// Retrieves a resolution from the cache.
+    ///
+    /// - `did` - DID that is resolved
+    /// - returns the cached resolution and whether it's still valid. Expired successful resolutions are retained for
+    ///   `max_stale` so that they can be used if the resolver fails temporarily.
+    fn get(&self, did: &str) -> Option<(Resolution, bool)> {
+        let mut entries = self.entries.lock().ok()?;
+        let now = Instant::now();
+        let expires = entries.resolutions.get(did)?.expires;
+        if expires + self.max_stale <= now {
+            entries.remove(did);
+            return None;
+        }
+        entries.touch(did);
+        entries
+            .resolutions
+            .get(did)
+            .map(|entry| (entry.resolution.clone(), expires > now))
+    }
+
+    /// Stores a resolution in the cache. Only successful resolutions and DIDs that couldn't be found are cached.
+    fn insert(&self, did: &str, resolution: &Resolution) {
+        let ttl = match (&resolution.0.error, &resolution.1) {
+            (None, Some(_)) => self.ttl,
+            (Some(error), _) if error == ERROR_NOT_FOUND => self.negative_ttl,
+            _ => return,
+        };
+        if ttl.is_zero() || self.capacity == 0 {
+            return;
+        }
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(did);
+            entries.resolutions.insert(
+                did.to_string(),
+                CacheEntry {
+                    resolution: resolution.clone(),
+                    expires: Instant::now() + ttl,
+                    used: 0,
+                },
+            );
+            entries.touch(did);
+            // evict the least recently used entries so that the cache doesn't grow unbounded
+            while entries.resolutions.len() > self.capacity {
+                match entries.order.pop_first() {
+                    Some((_, evicted)) => {
+                        entries.resolutions.remove(&evicted);
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Removes a DID from the cache, e.g. after its DID document changed.
+    pub fn invalidate(&self, did: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(did);
+        }
+    }
+
+    /// Removes all DIDs from the cache, e.g. after the resolvers changed.
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            *entries = Entries::default();
+        }
+    }
+}
+
+/// Resolver that caches the resolutions of the configured resolvers. DIDs that are hosted by this server aren't cached
+/// but resolved from the store via [`StoreResolver`] ahead of the configured resolvers.
+///
+/// * `config` - Server configuration
+/// * `policy` - Snapshot of the policy whose resolvers are used, so that a reload doesn't affect running resolutions
+pub struct CachingResolver<'a> {
+    pub config: &'a Config,
+    pub policy: Arc<Policy>,
+}
+
+impl Config {
+    /// Retrieves the configured resolvers wrapped in the resolver cache.
+    pub(crate) fn get_resolver(&self) -> CachingResolver<'_> {
+        CachingResolver {
+            config: self,
+            policy: self.policy.load(),
+        }
+    }
+}
+
+#[async_trait]
+impl DIDResolver for CachingResolver<'_> {
+    async fn resolve(
+        &self,
+        did: &str,
+        input_metadata: &ResolutionInputMetadata,
+    ) -> (
+        ResolutionMetadata,
+        Option<Document>,
+        Option<DocumentMetadata>,
+    ) {
+        // locally hosted DIDs are always resolved from the store and never cached
+        let local = StoreResolver {
+            config: self.config,
+        }
+        .resolve(did, input_metadata)
+        .await;
+        if local.0.error.as_deref() != Some(ERROR_METHOD_NOT_SUPPORTED) {
+            return local;
+        }
+        let cache = &self.config.resolver_cache;
+        let stale = match cache.get(did) {
+            Some((resolution, true)) => {
+                metrics::RESOLVER_CACHE.with_label_values(&["hit"]).inc();
+                return resolution;
+            }
+            Some((resolution, false)) => Some(resolution),
+            None => None,
+        };
+        metrics::RESOLVER_CACHE.with_label_values(&["miss"]).inc();
+        let resolution = self
+            .policy
+            .reslover_options
+            .get_resolver()
+            .resolve(did, input_metadata)
+            .await;
+        match (&resolution.0.error, stale) {
+            // the last successful resolution is used if the resolver fails temporarily, at most `max_stale` after expiry
+            (Some(error), Some(stale)) if error != ERROR_NOT_FOUND && stale.1.is_some() => {
+                tracing::warn!(did = %did, error = %error, "resolution failed, using cached DID document");
+                stale
+            }
+            _ => {
+                cache.insert(did, &resolution);
+                resolution
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cache::*;
+
+    fn resolution(error: Option<&str>) -> Resolution {
+        (
+            error
+                .map(ResolutionMetadata::from_error)
+                .unwrap_or_default(),
+            error.map_or_else(|| Some(Document::new("did:example:alice")), |_| None),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_resolver_cache() {
+        let cache = ResolverCache::new(
+            Duration::from_secs(60),
+            Duration::ZERO,
+            Duration::from_secs(60),
+            10,
+        );
+        cache.insert("did:example:alice", &resolution(None));
+        assert!(
+            matches!(cache.get("did:example:alice"), Some((_, true))),
+            "When a DID has been resolved, then the resolution is cached"
+        );
+        cache.invalidate("did:example:alice");
+        assert!(
+            cache.get("did:example:alice").is_none(),
+            "When a DID is invalidated, then it's removed from the cache"
+        );
+        cache.insert("did:example:bob", &resolution(Some(ERROR_NOT_FOUND)));
+        assert!(
+            cache.get("did:example:bob").is_none(),
+            "When negative caching is disabled, then unknown DIDs aren't cached"
+        );
+        cache.insert("did:example:carol", &resolution(Some("internalError")));
+        assert!(
+            cache.get("did:example:carol").is_none(),
+            "When the resolution failed, then it isn't cached"
+        );
+
+        let cache = ResolverCache::new(
+            Duration::ZERO,
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+            10,
+        );
+        cache.insert("did:example:alice", &resolution(None));
+        assert!(
+            cache.get("did:example:alice").is_none(),
+            "When caching is disabled, then resolutions aren't cached"
+        );
+        cache.insert("did:example:bob", &resolution(Some(ERROR_NOT_FOUND)));
+        assert!(
+            matches!(cache.get("did:example:bob"), Some((_, true))),
+            "When negative caching is enabled, then unknown DIDs are cached"
+        );
+    }
+
+    #[test]
+    fn test_resolver_cache_limits() {
+        let cache = ResolverCache::new(
+            Duration::from_secs(60),
+            Duration::ZERO,
+            Duration::from_secs(60),
+            2,
+        );
+        cache.insert("did:example:alice", &resolution(None));
+        cache.insert("did:example:bob", &resolution(None));
+        cache.get("did:example:alice");
+        cache.insert("did:example:carol", &resolution(None));
+        assert!(
+            cache.get("did:example:bob").is_none(),
+            "When the cache is full, then the least recently used DID is evicted"
+        );
+        assert!(
+            cache.get("did:example:alice").is_some() && cache.get("did:example:carol").is_some(),
+            "When the cache is full, then recently used DIDs are retained"
+        );
+
+        let cache = ResolverCache::new(Duration::from_millis(1), Duration::ZERO, Duration::ZERO, 2);
+        cache.insert("did:example:alice", &resolution(None));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(
+            cache.get("did:example:alice").is_none(),
+            "When a resolution expired longer than the maximum staleness ago, then it's dropped"
+        );
+
+        let cache = ResolverCache::new(
+            Duration::from_millis(1),
+            Duration::ZERO,
+            Duration::from_secs(60),
+            2,
+        );
+        cache.insert("did:example:alice", &resolution(None));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(
+            matches!(cache.get("did:example:alice"), Some((_, false))),
+            "When a resolution expired within the maximum staleness, then it's retained as stale"
+        );
+    }
+}
@@ -0,0 +1,549 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use chrono::Utc;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::json::Json;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::tokio::sync::{Mutex as AsyncMutex, MutexGuard as AsyncMutexGuard};
+use rocket::tokio::task;
+use rocket::Shutdown;
+use serde::{Deserialize, Serialize};
+use ssi::did::Document;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::audit::{append_line, Operation};
+use crate::config::Config;
+use crate::error::DIDError;
+use crate::replica::bearer_token_matches;
+use crate::store::DIDWebStore;
+
+/// Number of changes that are buffered for slow subscribers of the event stream before they have to catch up from
+/// the feed.
+const STREAM_CAPACITY: usize = 256;
+
+/// Number of changes that are returned by the change feed endpoint if the client doesn't set a limit.
+pub(crate) const PAGE_SIZE: usize = 100;
+
+/// Maximum number of changes that are returned by the change feed endpoint.
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Number of most recent changes that are kept by default, older changes are compacted.
+pub const RETENTION: usize = 10000;
+
+/// Change of a DID document.
+///
+/// * `sequence` - Position in the change feed, starting at 1 and increasing monotonically. Backends that are shared
+///   between instances allocate the sequence numbers, so that they're unique across instances, see
+///   [`DIDWebStore::next_sequence`]
+/// * `timestamp` - Time of the change in RFC 3339 format
+/// * `operation` - `create`, `update` or `delete`
+/// * `did` - DID whose document changed
+/// * `id` - id of the DID document in the store, e.g. `example.com/alice/did.json`
+/// * `document` - DID document after the change, missing if the DID has been deactivated
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Change {
+    pub sequence: u64,
+    pub timestamp: String,
+    pub operation: Operation,
+    pub did: String,
+    pub id: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<Document>,
+}
+
+/// Feed of all changes of DID documents. The most recent changes are kept in memory and appended to a file with one
+/// JSON encoded change per line, so that sequence numbers continue after a restart. Older changes are compacted, i.e.
+/// removed from memory and the file, once the number of changes exceeds the retention.
+///
+/// * `file` - file that changes are appended to, changes are kept in memory only if not set
+/// * `retention` - number of most recent changes that are kept
+/// * `changes` - retained changes and the sequence number of the most recent change
+/// * `writer` - serializes the writes to the store and the publication of the resulting changes, so that changes are
+///   published in the order in which the store has been written, see [`ChangeFeed::lock`]
+/// * `sender` - broadcasts published changes to subscribers
+#[derive(Debug)]
+pub struct ChangeFeed {
+    file: Option<PathBuf>,
+    retention: usize,
+    changes: Mutex<Changes>,
+    writer: AsyncMutex<()>,
+    sender: broadcast::Sender<Change>,
+}
+
+/// Retained changes of the feed.
+///
+/// * `changes` - changes in the order of their sequence numbers
+/// * `head` - sequence number of the most recent change, 0 if nothing changed yet
+/// * `compacted` - sequence number of the most recent compacted change, 0 if nothing has been compacted. It's tracked
+///   separately from the oldest retained change since sequence numbers have gaps if they're allocated by the backend.
+/// * `lines` - number of changes in the file, including changes that are compacted with the next publication
+#[derive(Debug, Default)]
+struct Changes {
+    changes: VecDeque<Change>,
+    head: u64,
+    compacted: u64,
+    lines: usize,
+}
+
+impl Changes {
+    /// Sequence number of the oldest retained change, or the next sequence number if no change is retained.
+    fn oldest(&self) -> u64 {
+        self.changes
+            .front()
+            .map_or(self.head + 1, |change| change.sequence)
+    }
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self {
+            file: None,
+            retention: RETENTION,
+            changes: Mutex::default(),
+            writer: AsyncMutex::new(()),
+            sender: broadcast::channel(STREAM_CAPACITY).0,
+        }
+    }
+}
+
+impl ChangeFeed {
+    /// Opens the change feed and loads the existing changes.
+    ///
+    /// - `file` - file that changes are appended to, changes are kept in memory only if not set.
+    /// - `retention` - number of most recent changes that are kept, at least 1
+    pub fn new(file: Option<PathBuf>, retention: usize) -> Result<ChangeFeed, DIDError> {
+        if retention == 0 {
+            return Err(DIDError::ConfigInvalid(
+                "Retention of the change feed must be at least 1".to_string(),
+            ));
+        }
+        let changes = match &file {
+            Some(file) if file.exists() => read_changes(file)?,
+            Some(file) => {
+                if let Some(directory) = file.parent() {
+                    fs::create_dir_all(directory)
+                        .map_err(|e| DIDError::NoFileWrite(e.to_string()))?;
+                }
+                vec![]
+            }
+            None => vec![],
+        };
+        let lines = changes.len();
+        let head = changes.last().map_or(0, |change| change.sequence);
+        let skipped = lines.saturating_sub(retention);
+        // the file doesn't tell whether changes before the first one have been compacted, so it's assumed they were
+        let compacted = match skipped {
+            0 => changes
+                .first()
+                .map_or(0, |change| change.sequence.saturating_sub(1)),
+            skipped => changes[skipped - 1].sequence,
+        };
+        let changes = changes.into_iter().skip(skipped).collect();
+        Ok(ChangeFeed {
+            file,
+            retention,
+            changes: Mutex::new(Changes {
+                changes,
+                head,
+                compacted,
+                lines,
+            }),
+            ..ChangeFeed::default()
+        })
+    }
+
+    /// Number of most recent changes that are kept.
+    pub fn retention(&self) -> usize {
+        self.retention
+    }
+
+    /// Sequence number of the most recent change, 0 if nothing changed yet.
+    pub fn head(&self) -> u64 {
+        self.changes.lock().unwrap().head
+    }
+
+    /// Sequence number of the oldest retained change, or the next sequence number if no change is retained.
+    pub fn oldest(&self) -> u64 {
+        self.changes.lock().unwrap().oldest()
+    }
+
+    /// Retrieves the changes after a sequence number.
+    ///
+    /// - `sequence` - sequence number of the last change that the client has seen, `0` to start with the oldest
+    ///   retained change
+    /// - `limit` - maximum number of changes that are returned
+    /// - returns the changes in the order of their sequence numbers, or ChangesCompacted if changes after `sequence`
+    ///   have been compacted
+    pub fn since(&self, sequence: u64, limit: usize) -> Result<Vec<Change>, DIDError> {
+        let changes = self.changes.lock().unwrap();
+        if sequence > 0 && sequence < changes.compacted {
+            return Err(DIDError::ChangesCompacted(format!(
+                "Changes before sequence number {} have been compacted",
+                changes.oldest()
+            )));
+        }
+        // changes are ordered by sequence number, so the first change after `sequence` is found by a binary search
+        let start = changes
+            .changes
+            .partition_point(|change| change.sequence <= sequence);
+        Ok(changes
+            .changes
+            .range(start..)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    /// Subscribes to all changes that are published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.sender.subscribe()
+    }
+
+    /// Locks the change feed until the returned publisher is dropped. The store is written while the lock is held, so
+    /// that concurrent writes are published in the order in which they've been performed.
+    pub async fn lock(&self) -> Publisher<'_> {
+        Publisher {
+            feed: self,
+            _writer: self.writer.lock().await,
+        }
+    }
+}
+
+/// Exclusive right to publish the next change of a [`ChangeFeed`].
+pub struct Publisher<'a> {
+    feed: &'a ChangeFeed,
+    _writer: AsyncMutexGuard<'a, ()>,
+}
+
+impl Publisher<'_> {
+    /// Records and publishes a change.
+    ///
+    /// - `store` - store that has been written, it allocates the sequence number if it's shared between instances
+    /// - `operation` - operation that changed the DID document
+    /// - `did` - DID whose document changed
+    /// - `id` - id of the DID document in the store
+    /// - `document` - DID document after the change, `None` if the DID has been deactivated
+    /// - returns the recorded change, or an error if the change couldn't be recorded
+    pub async fn publish(
+        &self,
+        store: &(dyn DIDWebStore + Sync + Send),
+        operation: Operation,
+        did: &str,
+        id: &Path,
+        document: Option<Document>,
+    ) -> Result<Change, DIDError> {
+        let feed = self.feed;
+        let head = feed.head();
+        let sequence = match store.next_sequence().await? {
+            Some(sequence) if sequence > head => sequence,
+            // the counter of the backend has been reset, the sequence numbers of the feed still have to increase
+            Some(sequence) => {
+                tracing::warn!(
+                    sequence,
+                    head,
+                    "sequence number of the backend is behind the change feed"
+                );
+                head + 1
+            }
+            None => head + 1,
+        };
+        let change = Change {
+            sequence,
+            timestamp: Utc::now().to_rfc3339(),
+            operation,
+            did: did.to_string(),
+            id: id.to_path_buf(),
+            document,
+        };
+        if let Some(file) = &feed.file {
+            let line = serde_json::to_string(&change)
+                .map_err(|e| DIDError::ContentConversion(e.to_string()))?;
+            let file = file.to_path_buf();
+            task::spawn_blocking(move || append_line(&file, &line))
+                .await
+                .map_err(|e| DIDError::NoFileWrite(e.to_string()))??;
+        }
+        let compacted = {
+            let mut changes = feed.changes.lock().unwrap();
+            changes.changes.push_back(change.clone());
+            changes.head = change.sequence;
+            changes.lines += 1;
+            while changes.changes.len() > feed.retention {
+                if let Some(compacted) = changes.changes.pop_front() {
+                    changes.compacted = compacted.sequence;
+                }
+            }
+            // the file is rewritten once it holds twice the retained changes, so compaction is amortized
+            (changes.lines > 2 * feed.retention)
+                .then(|| changes.changes.iter().cloned().collect::<Vec<Change>>())
+        };
+        if let (Some(file), Some(retained)) = (&feed.file, compacted) {
+            let file = file.to_path_buf();
+            let lines = retained.len();
+            match task::spawn_blocking(move || rewrite_changes(&file, &retained)).await {
+                Ok(Ok(())) => feed.changes.lock().unwrap().lines = lines,
+                Ok(Err(e)) => tracing::warn!(
+                    error = e.name(),
+                    reason = e.message(),
+                    "change feed not compacted"
+                ),
+                Err(e) => tracing::warn!(reason = %e, "change feed not compacted"),
+            }
+        }
+        // sending only fails if nobody is subscribed
+        let _ = feed.sender.send(change.clone());
+        Ok(change)
+    }
+}
+
+/// Reads the changes of a change feed file.
+fn read_changes(file: &Path) -> Result<Vec<Change>, DIDError> {
+    fs::read_to_string(file)
+        .map_err(|e| DIDError::NoFileRead(e.to_string()))
+        .and_then(|content| {
+            content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    serde_json::from_str::<Change>(line)
+                        .map_err(|e| DIDError::ContentConversion(e.to_string()))
+                })
+                .collect()
+        })
+}
+
+/// Replaces the content of a change feed file with the retained changes. The changes are written to a temporary file
+/// that's moved into place, so that a crash never leaves a partial feed behind.
+fn rewrite_changes(file: &Path, changes: &[Change]) -> Result<(), DIDError> {
+    let temporary = file.with_extension("compacting");
+    File::create(&temporary)
+        .and_then(|mut f| {
+            for change in changes {
+                writeln!(f, "{}", serde_json::to_string(change)?)?;
+            }
+            f.sync_all()
+        })
+        .and_then(|_| fs::rename(&temporary, file))
+        .map_err(|e| DIDError::NoFileWrite(e.to_string()))
+}
+
+/// Request guard that ensures that the request carries the change feed token, i.e. `Authorization: Bearer <token>`.
+/// The feed has its own token, so that consumers of the changes aren't able to replicate the server.
+pub struct ChangesReader;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ChangesReader {
+    type Error = DIDError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request
+            .rocket()
+            .state::<Config>()
+            .and_then(|config| config.change_feed_token.as_deref());
+        match bearer_token_matches(request, token) {
+            Some(true) => Outcome::Success(ChangesReader),
+            None => {
+                let e = DIDError::ChangesDenied("Change feed is disabled".to_string());
+                Outcome::Error((e.status(), e))
+            }
+            Some(false) => {
+                let e = DIDError::ChangesDenied("Change feed token invalid".to_string());
+                Outcome::Error((e.status(), e))
+            }
+        }
+    }
+}
+
+/// Retrieve the changes of DID documents after a sequence number. Requires the change feed token.
+///
+/// - `config` Global Rocket configuration
+/// - `since` - sequence number of the last change that the client has seen. The changes start with the oldest
+///   retained change if not set or `0`.
+/// - `limit` - maximum number of changes that are returned, by default 100 and at most 1000
+/// - returns the changes in the order of their sequence numbers, or `410 - Gone` if changes after `since` have been
+///   compacted
+#[get("/.well-known/changes?<since>&<limit>")]
+pub fn get_changes(
+    config: &rocket::State<Config>,
+    _reader: ChangesReader,
+    since: Option<u64>,
+    limit: Option<usize>,
+) -> Result<Json<Vec<Change>>, DIDError> {
+    config
+        .changes
+        .since(
+            since.unwrap_or(0),
+            limit.unwrap_or(PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+        )
+        .map(Json)
+}
+
+/// Stream the changes of DID documents as Server-Sent Events. Requires the change feed token. Each event carries the
+/// change's sequence number as id.
+///
+/// - `config` Global Rocket configuration
+/// - `since` - sequence number of the last change that the client has seen. Only new changes are streamed if not set.
+/// - `shutdown` - ends the stream when the server shuts down
+/// - returns the stream, or `410 - Gone` if changes after `since` have been compacted
+#[get("/.well-known/changes/stream?<since>")]
+pub fn stream_changes(
+    config: &rocket::State<Config>,
+    _reader: ChangesReader,
+    since: Option<u64>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![Event + '_], DIDError> {
+    let feed = &config.changes;
+    // subscribe before reading the feed so that no change is missed in between
+    let mut receiver = feed.subscribe();
+    let mut last = since.unwrap_or_else(|| feed.head());
+    let backlog = feed.since(last, usize::MAX)?;
+    let event = |change: &Change| {
+        Event::json(change)
+            .event("change")
+            .id(change.sequence.to_string())
+    };
+    Ok(EventStream! {
+        for change in backlog {
+            last = change.sequence;
+            yield event(&change);
+        }
+        loop {
+            let change = select! {
+                change = receiver.recv() => change,
+                _ = &mut shutdown => break,
+            };
+            match change {
+                Ok(change) if change.sequence > last => {
+                    last = change.sequence;
+                    yield event(&change);
+                }
+                Ok(_) => {}
+                // the subscriber fell behind, the missed changes are read from the feed
+                Err(RecvError::Lagged(_)) => match feed.since(last, usize::MAX) {
+                    Ok(changes) => for change in changes {
+                        last = change.sequence;
+                        yield event(&change);
+                    },
+                    // the client has to catch up via the change feed endpoint
+                    Err(_) => break,
+                },
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::changes::*;
+    use crate::store::mem::MemStore;
+
+    /// Publishes a change of alice's DID document, the change feed numbers the change itself.
+    async fn publish(feed: &ChangeFeed, operation: Operation) -> Change {
+        feed.lock()
+            .await
+            .publish(
+                &MemStore::default(),
+                operation,
+                "did:web:localhost:alice",
+                Path::new("alice/did.json"),
+                None,
+            )
+            .await
+            .unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn test_change_feed() {
+        let file = std::env::temp_dir().join(format!("dws-changes-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&file);
+        let feed = ChangeFeed::new(Some(file.clone()), RETENTION).unwrap();
+        let mut receiver = feed.subscribe();
+        publish(&feed, Operation::Create).await;
+        publish(&feed, Operation::Update).await;
+        assert_eq!(
+            receiver.try_recv().unwrap().sequence,
+            1,
+            "When a change is published, then subscribers receive it"
+        );
+        assert_eq!(
+            feed.since(1, PAGE_SIZE)
+                .unwrap()
+                .iter()
+                .map(|change| change.operation)
+                .collect::<Vec<Operation>>(),
+            vec![Operation::Update],
+            "When changes are requested since a sequence number, then only later changes are returned"
+        );
+        assert_eq!(
+            feed.since(0, 1).unwrap().len(),
+            1,
+            "When changes are requested with a limit, then at most limit changes are returned"
+        );
+
+        let feed = ChangeFeed::new(Some(file.clone()), RETENTION).unwrap();
+        assert_eq!(
+            feed.head(),
+            2,
+            "When the change feed is reopened, then the existing changes are loaded"
+        );
+        let change = publish(&feed, Operation::Delete).await;
+        assert_eq!(
+            change.sequence, 3,
+            "When the change feed is reopened, then the sequence numbers continue"
+        );
+        let _ = fs::remove_file(&file);
+    }
+
+    #[rocket::async_test]
+    async fn test_change_feed_compaction() {
+        let file = std::env::temp_dir().join(format!(
+            "dws-changes-compaction-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&file);
+        let feed = ChangeFeed::new(Some(file.clone()), 2).unwrap();
+        for _ in 0..5 {
+            publish(&feed, Operation::Update).await;
+        }
+        assert_eq!(
+            feed.since(3, PAGE_SIZE)
+                .unwrap()
+                .iter()
+                .map(|change| change.sequence)
+                .collect::<Vec<u64>>(),
+            vec![4, 5],
+            "When changes are compacted, then the most recent changes are retained"
+        );
+        assert!(
+            matches!(feed.since(1, PAGE_SIZE), Err(DIDError::ChangesCompacted(_))),
+            "When changes after the sequence number have been compacted, then an error is returned"
+        );
+        assert_eq!(
+            feed.since(0, PAGE_SIZE)
+                .unwrap()
+                .iter()
+                .map(|change| change.sequence)
+                .collect::<Vec<u64>>(),
+            vec![4, 5],
+            "When changes are requested from the start after a compaction, then the oldest retained change is first"
+        );
+        assert!(
+            read_changes(&file).unwrap().len() <= 4,
+            "When the file holds more than twice the retained changes, then it's compacted"
+        );
+        let feed = ChangeFeed::new(Some(file.clone()), 2).unwrap();
+        assert_eq!(
+            feed.head(),
+            5,
+            "When a compacted change feed is reopened, then the sequence numbers continue"
+        );
+        let _ = fs::remove_file(&file);
+    }
+}
@@ -1,138 +1,471 @@
 // SPDX-License-Identifier: AGPL-3.0
 
-use std::process;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
+use rocket::figment::providers::{Env, Format, Serialized, Toml};
+use rocket::figment::{Figment, Profile};
+use serde::{Deserialize, Serialize};
 use ssi::did_resolve::HTTPDIDResolver;
 
+use crate::audit::AuditLog;
+use crate::cache::ResolverCache;
+use crate::changes::ChangeFeed;
 use crate::error::DIDError;
+use crate::logging::LogFormat;
+use crate::reload::Reloadable;
+use crate::replica::Replica;
 use crate::resolver::ResolverOptions;
-use crate::store::file::FileStore;
-use crate::store::{mem::MemStore, DIDWebStore};
-use crate::utils::get_env;
+use crate::store::{mem::MemStore, DIDWebStore, StoreRegistry};
+use crate::tenant::Tenant;
+use crate::webhooks::{Webhook, Webhooks};
 
-/// Global configuration
+/// Global configuration, see [`Settings`] for the keys of the configuration file
 ///
+/// * `audit_log` - Log of all mutating operations. Set via DWS_AUDIT_LOG and DWS_AUDIT_LOG_KEY variables, e.g. `audit.jsonl`
+/// * `api_explorer` - Serve an interactive API explorer at `/.well-known/openapi.html`. Set via DWS_API_EXPLORER variable, e.g. `true`
+/// * `changes` - Feed of all changes of DID documents. Set via DWS_CHANGE_FEED variable, e.g. `changes.jsonl`, and DWS_CHANGE_FEED_RETENTION, e.g. `10000`. Kept in DWS_DATA_DIR by default
 /// * `external_hostname` - Hostname for `did:web:<hostname>`. Set via EXTERNAL_HOSTNAME variable, e.g. `example.com`
+/// * `change_feed_token` - Token that consumers of the change feed present to retrieve the changes. Set via DWS_CHANGE_FEED_TOKEN variable
 /// * `external_path` - Path to the identity `did:web:<domainname>:<path>/<id>. Set via SUBPATH variable, e.g. `users`
 /// * `external_port` - , e.g. `8000`
-/// * `owner_did` - DID of the server's owner
-/// * `reslover_options` - Directory to store the DID Documents at, default: `$PWD/did_store`
+/// * `lenient_startup` - Start, or apply a reloaded policy, even if an owner's DID can't be resolved or has no usable keys. Set via DWS_LENIENT_STARTUP variable, e.g. `true`
+/// * `log_format` - Output format of log messages, `text` or `json`. Set via DWS_LOG_FORMAT variable, e.g. `json`
+/// * `metrics_token` - Token that Prometheus presents to retrieve the metrics. Metrics are public if unset. Set via DWS_METRICS_TOKEN variable
+/// * `policy` - Settings that are reloaded without a restart, see [`Policy`]
+/// * `replica` - Primary that DID documents are mirrored from. Changes are rejected if set, see [`Replica`]
+/// * `replication_token` - Token that replicas present to retrieve the DID documents. Set via DWS_REPLICATION_TOKEN variable
+/// * `resolver_cache` - Cache for resolutions of remote DIDs. Set via DWS_RESOLVER_CACHE_TTL, DWS_RESOLVER_CACHE_NEGATIVE_TTL and DWS_RESOLVER_CACHE_MAX_STALE variables in seconds, e.g. `300`, and DWS_RESOLVER_CACHE_SIZE, e.g. `1000`
 /// * `store` - Store for DID Documents
+/// * `webhooks` - Endpoints that changes of DID documents are delivered to, see [`Webhooks`]
 pub struct Config {
-    pub external_path: String,
-    pub external_hostname: String,
-    pub external_port: String,
+    pub(crate) api_explorer: bool,
+    pub(crate) audit_log: AuditLog,
+    pub(crate) changes: Arc<ChangeFeed>,
+    pub(crate) change_feed_token: Option<String>,
+    pub(crate) external_path: String,
+    pub(crate) external_hostname: String,
+    pub(crate) external_port: String,
+    pub(crate) lenient_startup: bool,
+    pub(crate) log_format: LogFormat,
+    pub(crate) metrics_token: Option<String>,
+    pub(crate) policy: Reloadable<Policy>,
+    pub(crate) resolver_cache: ResolverCache,
+    pub(crate) replica: Option<Replica>,
+    pub(crate) replication_token: Option<String>,
+    pub(crate) store: Arc<dyn DIDWebStore + Sync + Send>,
+    pub(crate) webhooks: Arc<Webhooks>,
+}
+
+/// Settings that are swapped atomically when the configuration is reloaded.
+///
+/// * `owner` - DID of the server's owner. Set via DWS_OWNER variable
+/// * `reslover_options` - Resolvers for DIDs that aren't hosted by this server. Set via DWS_RESOLVER and DWS_RESOLVER_OVERRIDE variables
+/// * `resolver_allowed_hosts` - Hosts of did:web DIDs that the resolver endpoint resolves besides the DIDs hosted by this server. Set via DWS_RESOLVER_ALLOWED_HOSTS variable, e.g. `[example.com, "*.example.org"]`
+/// * `tenants` - Domains that DIDs are hosted for. Set via DWS_TENANTS variable as JSON list. If empty, DIDs are hosted for `external_hostname`
+#[derive(Debug, Clone)]
+pub struct Policy {
     pub owner: String,
     pub reslover_options: ResolverOptions,
-    pub store: Box<dyn DIDWebStore + Sync + Send>,
+    pub resolver_allowed_hosts: Vec<String>,
+    pub tenants: Vec<Tenant>,
 }
 
-impl Config {
-    // INFO: I wish I could get this to work somehow
-    /// get_resolver Retrieves the resolver from the configuration.
-    // pub fn get_resolver<'a>(&self, custom_resolver: Option<&'a dyn DIDResolver>) -> SeriesResolver {
-    //     let mut series_resolver = self.reslover_options.get_resolver();
-    //     if let Some(resolver) = custom_resolver {
-    //         series_resolver.resolvers.push(resolver);
-    //     }
-    //     series_resolver
-    //     // let mut resolvers: Vec<&dyn ssi::did_resolve::DIDResolver> = vec![];
-    //     // #[cfg(test)]
-    //     // let test_resolver = DIDWebTestResolver {
-    //     //     store: Some(&self.store),
-    //     //     ..DIDWebTestResolver::default()
-    //     // };
-    //     // #[cfg(test)]
-    //     // resolvers.push(test_resolver.as_ref());
-    //     // resolvers.push(&self.reslover_options.get_resolver());
-    //     // SeriesResolver { resolvers }
-    // }
-    // /// get_resolver Retrieves the resolver from the configuration.
-    // pub fn get_test_resolver(&self) -> Option<&dyn DIDResolver> {
-    //     #[cfg(test)]
-    //     return Some(&DIDWebTestResolver {
-    //         store: Some(&self.store),
-    //         ..DIDWebTestResolver::default()
-    //     });
-    //     // reason = "only in test cases this code isn't used"
-    //     #[allow(unreachable_code)]
-    //     return None;
-    // }
-    pub fn load_env_or_panic(config: Config) -> Config {
-        Config {
-            external_hostname: get_env("DWS_EXTERNAL_HOSTNAME", &config.external_hostname),
-            external_port: get_env("DWS_EXTERNAL_PORT", &config.external_port),
-            external_path: get_env("DWS_EXTERNAL_PATH", &config.external_path),
-            owner: std::env::var("DWS_OWNER")
-                .map_err(|_| DIDError::OwnerMissing("Owner not specified".to_string()))
-                .and_then(|owner| {
-                    if owner.is_empty() {
-                        Err(DIDError::OwnerMissing("Owner not specified".to_string()))
-                    } else {
-                        Ok(owner)
-                    }
-                })
-                .map_err(|e| {
-                    println!("Error: {}", e);
-                    process::exit(1)
-                })
-                .unwrap(),
+impl Policy {
+    /// Builds and validates the policy from the settings.
+    pub fn new(settings: &Settings) -> Result<Policy, DIDError> {
+        Ok(Policy {
+            owner: settings
+                .owner
+                .clone()
+                .filter(|owner| !owner.is_empty())
+                .ok_or_else(|| DIDError::OwnerMissing("Owner not specified".to_string()))?,
             reslover_options: ResolverOptions {
-                did_resolver: std::env::var("DWS_RESOLVER")
-                    .ok()
-                    .map(|ref url| HTTPDIDResolver::new(url)),
-                did_resolver_override: std::env::var("DWS_RESOLVER_OVERRIDE")
-                    .ok()
-                    .map(|ref url| HTTPDIDResolver::new(url)),
+                did_resolver: settings
+                    .resolver
+                    .as_ref()
+                    .map(|url| HTTPDIDResolver::new(url)),
+                did_resolver_override: settings
+                    .resolver_override
+                    .as_ref()
+                    .map(|url| HTTPDIDResolver::new(url)),
             },
-            store: Ok(get_env("DWS_BACKEND", "mem"))
-                .and_then(
-                    |backend| -> Result<Box<dyn DIDWebStore + Sync + Send>, DIDError> {
-                        match backend.as_str() {
-                            "file" => {
-                                let directory = get_env(
-                                    "DWS_BACKEND_FILE_STORE",
-                                    // by default store all files in $PWD/did_store/
-                                    &std::env::current_dir()
-                                        .map(|val| {
-                                            val.join("did_store")
-                                                .to_str()
-                                                .unwrap_or(".")
-                                                .to_string()
-                                        })
-                                        .unwrap_or_else(|_| ".".to_string()),
-                                );
-                                Ok(Box::new(FileStore::new(directory)))
-                            }
-                            "mem" => Ok(Box::new(MemStore::new())),
-                            _ => Err(DIDError::UnknownBackend(format!(
-                                "Backend is unknown: {}",
-                                backend
-                            ))),
-                        }
-                    },
-                )
-                .map_err(|e| {
-                    println!("Error: {}", e);
-                    process::exit(1)
-                })
-                .unwrap(),
+            resolver_allowed_hosts: Policy::validate_allowed_hosts(
+                settings.resolver_allowed_hosts.clone().unwrap_or_default(),
+            )?,
+            tenants: Tenant::validate_list(settings.tenants.clone().unwrap_or_default())?,
+        })
+    }
+
+    /// Rejects the wildcard `*`. Only IP literals are recognized as internal hosts, a public DNS name can still resolve
+    /// to an internal address, so every allowed host has to be trusted explicitly.
+    fn validate_allowed_hosts(hosts: Vec<String>) -> Result<Vec<String>, DIDError> {
+        if hosts.iter().any(|host| host == "*") {
+            return Err(DIDError::ConfigInvalid(
+                "DWS_RESOLVER_ALLOWED_HOSTS doesn't support `*`, list the allowed hosts or domains instead"
+                    .to_string(),
+            ));
         }
+        Ok(hosts)
     }
 }
 
-impl Default for Config {
+impl Default for Policy {
     fn default() -> Self {
-        Self {
-            external_hostname: "localhost".to_string(),
-            external_port: "8000".to_string(),
-            external_path: "/".to_string(),
+        Policy {
             owner: "<invalidDID>".to_string(),
             reslover_options: ResolverOptions {
                 did_resolver: None,
                 did_resolver_override: None,
             },
-            store: Box::new(MemStore::new()),
+            resolver_allowed_hosts: vec![],
+            tenants: vec![],
+        }
+    }
+}
+
+/// Default location of the configuration file. A different file can be set via the DWS_CONFIG variable.
+pub static CONFIG_FILE: &str = "Didwebserver.toml";
+
+/// Default data directory of the file backend, relative to the working directory. It's separate from the directory of
+/// the DID documents, which is often served as static web root.
+pub static DATA_DIR: &str = "dws_data";
+
+/// Default data directory of the file backend as absolute path, so that the store and the configuration agree on it.
+pub(crate) fn default_data_dir() -> PathBuf {
+    std::env::current_dir()
+        .map(|dir| dir.join(DATA_DIR))
+        .unwrap_or_else(|_| PathBuf::from(DATA_DIR))
+}
+
+/// Name of the change feed file in the data directory, unless DWS_CHANGE_FEED is set.
+pub static CHANGE_FEED_FILE: &str = "changes.jsonl";
+
+/// Name of the directory next to the change feed that the positions of the webhooks in the change feed are written to.
+pub static WEBHOOK_CURSORS_DIR: &str = "webhooks";
+
+/// Settings that are read from the configuration file and the `DWS_*` environment variables. The keys correspond
+/// to the environment variables without the `DWS_` prefix in lower case, e.g. `external_hostname`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub api_explorer: Option<bool>,
+    pub audit_log: Option<PathBuf>,
+    pub audit_log_key: Option<String>,
+    pub backend: Option<String>,
+    pub backend_file_store: Option<PathBuf>,
+    pub backend_redis_prefix: Option<String>,
+    pub backend_redis_url: Option<String>,
+    pub backend_s3_access_key_id: Option<String>,
+    pub backend_s3_bucket: Option<String>,
+    pub backend_s3_endpoint: Option<String>,
+    pub backend_s3_prefix: Option<String>,
+    pub backend_s3_region: Option<String>,
+    pub backend_s3_reservation_bucket: Option<String>,
+    pub backend_s3_reservation_prefix: Option<String>,
+    pub backend_s3_secret_access_key: Option<String>,
+    pub change_feed: Option<PathBuf>,
+    pub change_feed_retention: Option<usize>,
+    pub change_feed_token: Option<String>,
+    pub data_dir: Option<PathBuf>,
+    pub external_hostname: Option<String>,
+    pub external_path: Option<String>,
+    pub external_port: Option<u16>,
+    pub lenient_startup: Option<bool>,
+    pub log_format: Option<String>,
+    pub metrics_token: Option<String>,
+    pub owner: Option<String>,
+    pub replica_max_removed: Option<u8>,
+    pub replica_of: Option<String>,
+    pub replica_sync_interval: Option<u64>,
+    pub replication_token: Option<String>,
+    pub resolver: Option<String>,
+    pub resolver_allowed_hosts: Option<Vec<String>>,
+    pub resolver_override: Option<String>,
+    pub resolver_cache_max_stale: Option<u64>,
+    pub resolver_cache_negative_ttl: Option<u64>,
+    pub resolver_cache_size: Option<usize>,
+    pub resolver_cache_ttl: Option<u64>,
+    pub tenants: Option<Vec<Tenant>>,
+    pub webhook_attempts: Option<u32>,
+    pub webhook_dead_letters: Option<PathBuf>,
+    pub webhooks: Option<Vec<Webhook>>,
+}
+
+impl Settings {
+    /// Extracts the settings from the configuration sources.
+    pub fn extract(figment: &Figment) -> Result<Settings, DIDError> {
+        figment
+            .extract()
+            .map_err(|e| DIDError::ConfigInvalid(format!("Configuration invalid: {}", e)))
+    }
+}
+
+/// Configuration sources of the server and Rocket in the order of precedence: environment variables prefixed with
+/// `DWS_`, the configuration file and Rocket's defaults. The profile is selected via DWS_PROFILE.
+pub fn figment() -> Figment {
+    let figment = Figment::from(rocket::Config::default())
+        .merge(Serialized::defaults(rocket::Config::default()))
+        .merge(Toml::file(Env::var_or("DWS_CONFIG", CONFIG_FILE)))
+        .merge(Env::prefixed("DWS_").ignore(&["tenants"]).global());
+    let figment = match std::env::var("DWS_TENANTS") {
+        Ok(tenants) => figment.merge(json_tenants(&tenants)),
+        Err(_) => figment,
+    };
+    figment.select(Profile::from_env_or("DWS_PROFILE", "default"))
+}
+
+/// Provider for the tenants of the DWS_TENANTS variable, which is a JSON list unlike the other variables. Values that
+/// aren't valid JSON are passed on as string, so that extracting the settings reports them as invalid.
+fn json_tenants(tenants: &str) -> Serialized<serde_json::Value> {
+    Serialized::global(
+        "tenants",
+        serde_json::from_str(tenants).unwrap_or_else(|_| serde_json::Value::from(tenants)),
+    )
+}
+
+impl Config {
+    /// Configuration sources of the server, see [`figment`].
+    pub fn figment() -> Figment {
+        figment()
+    }
+
+    /// Loads the configuration from the configuration file and environment variables.
+    ///
+    /// - `figment` - configuration sources, see [`Config::figment`]
+    /// - returns the validated configuration or the first validation error
+    pub fn load(figment: &Figment) -> Result<Config, DIDError> {
+        Config::load_with(figment, &StoreRegistry::default())
+    }
+
+    /// Loads the configuration like [`Config::load`] and constructs the store from a custom set of backends.
+    ///
+    /// - `figment` - configuration sources, see [`Config::figment`]
+    /// - `backends` - backends that can be selected via DWS_BACKEND
+    /// - returns the validated configuration or the first validation error
+    pub fn load_with(figment: &Figment, backends: &StoreRegistry) -> Result<Config, DIDError> {
+        let settings = Settings::extract(figment)?;
+        let policy = Policy::new(&settings)?;
+        let config = Config::default();
+        let backend = settings.backend.as_deref().unwrap_or("mem");
+        let store = backends.build(backend, figment)?;
+        // the operational data of the file backend survives restarts like its DID documents
+        let data_dir = settings.data_dir.or_else(|| match backend {
+            "file" => Some(default_data_dir()),
+            _ => None,
+        });
+        let change_feed = settings
+            .change_feed
+            .or_else(|| data_dir.map(|dir| dir.join(CHANGE_FEED_FILE)));
+        // sequence numbers of persistent backends must continue after a restart, only the mem backend starts over
+        if change_feed.is_none() && backend != "mem" {
+            return Err(DIDError::ConfigInvalid(format!(
+                "DWS_CHANGE_FEED or DWS_DATA_DIR must be set for backend {}",
+                backend
+            )));
+        }
+        // the cursors of the webhooks are positions in the change feed, so they're kept next to it
+        let webhook_cursors = change_feed
+            .as_ref()
+            .and_then(|file| file.parent())
+            .map(|directory| directory.join(WEBHOOK_CURSORS_DIR));
+        Ok(Config {
+            api_explorer: settings.api_explorer.unwrap_or(config.api_explorer),
+            audit_log: AuditLog::new(settings.audit_log, settings.audit_log_key)?,
+            changes: ChangeFeed::new(
+                change_feed,
+                settings
+                    .change_feed_retention
+                    .unwrap_or(config.changes.retention()),
+            )?
+            .into(),
+            change_feed_token: settings.change_feed_token,
+            external_hostname: settings
+                .external_hostname
+                .unwrap_or(config.external_hostname),
+            external_port: settings
+                .external_port
+                .map_or(config.external_port, |port| port.to_string()),
+            external_path: settings.external_path.unwrap_or(config.external_path),
+            lenient_startup: settings.lenient_startup.unwrap_or(config.lenient_startup),
+            log_format: settings
+                .log_format
+                .map_or(Ok(config.log_format), |format| format.parse())?,
+            metrics_token: settings.metrics_token,
+            policy: policy.into(),
+            resolver_cache: ResolverCache::new(
+                settings
+                    .resolver_cache_ttl
+                    .map_or(config.resolver_cache.ttl, Duration::from_secs),
+                settings
+                    .resolver_cache_negative_ttl
+                    .map_or(config.resolver_cache.negative_ttl, Duration::from_secs),
+                settings
+                    .resolver_cache_max_stale
+                    .map_or(config.resolver_cache.max_stale, Duration::from_secs),
+                settings
+                    .resolver_cache_size
+                    .unwrap_or(config.resolver_cache.capacity),
+            ),
+            replica: settings.replica_of.map(|primary| Replica {
+                primary,
+                interval: settings
+                    .replica_sync_interval
+                    .map_or(Duration::from_secs(60), Duration::from_secs),
+                max_removed: settings.replica_max_removed.unwrap_or(50),
+            }),
+            replication_token: settings.replication_token,
+            store: store.into(),
+            webhooks: Webhooks::new(
+                settings.webhooks.unwrap_or_default(),
+                settings
+                    .webhook_attempts
+                    .unwrap_or(config.webhooks.attempts),
+                settings.webhook_dead_letters,
+                webhook_cursors,
+            )
+            .into(),
+        })
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            api_explorer: false,
+            audit_log: AuditLog::default(),
+            changes: Arc::default(),
+            change_feed_token: None,
+            external_hostname: "localhost".to_string(),
+            external_port: "8000".to_string(),
+            external_path: "/".to_string(),
+            lenient_startup: false,
+            log_format: LogFormat::Text,
+            metrics_token: None,
+            policy: Policy::default().into(),
+            resolver_cache: ResolverCache::default(),
+            replica: None,
+            replication_token: None,
+            store: Arc::new(MemStore::new()),
+            webhooks: Arc::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::config::*;
+
+    #[test]
+    fn test_json_tenants() {
+        let figment = Figment::new()
+            .merge(Toml::string(
+                r#"
+                owner = "did:key:z6MkrFA3mp2Gk6S1ds7FnTgqBfbbUGJKjHHHx5gt7fMnG3Vt"
+
+                [[tenants]]
+                hostname = "a.example.com"
+                owners = ["did:key:a"]
+                "#,
+            ))
+            .merge(json_tenants(
+                r#"[{"hostname": "b.example.com", "owners": ["did:key:b"]}]"#,
+            ));
+        let config = Config::load(&figment).unwrap();
+        assert_eq!(
+            config
+                .policy
+                .load()
+                .tenants
+                .iter()
+                .map(|tenant| tenant.hostname.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["b.example.com"],
+            "When tenants are set via JSON, then they take precedence over the configuration file"
+        );
+
+        let figment = Figment::new()
+            .merge(Serialized::global("owner", "did:key:a"))
+            .merge(json_tenants("[{"));
+        assert!(
+            Config::load(&figment).is_err(),
+            "When the JSON tenants are invalid, then loading the configuration fails"
+        );
+    }
+
+    #[test]
+    fn test_config_load() {
+        let figment = Figment::new().merge(Toml::string(
+            r#"
+            owner = "did:key:z6MkrFA3mp2Gk6S1ds7FnTgqBfbbUGJKjHHHx5gt7fMnG3Vt"
+            external_hostname = "example.com"
+            external_port = 443
+            resolver_cache_ttl = 0
+
+            [[tenants]]
+            hostname = "b.example.com"
+            owners = ["did:key:z6MkrFA3mp2Gk6S1ds7FnTgqBfbbUGJKjHHHx5gt7fMnG3Vt"]
+            "#,
+        ));
+        let config = Config::load(&figment).unwrap();
+        assert_eq!(
+            (
+                config.external_hostname.as_str(),
+                config.external_port.as_str()
+            ),
+            ("example.com", "443"),
+            "When the configuration file sets a value, then it's used"
+        );
+        assert_eq!(
+            config.external_path, "/",
+            "When the configuration file doesn't set a value, then the default is used"
+        );
+        assert!(config.resolver_cache.ttl.is_zero());
+        assert_eq!(
+            config.policy.load().tenants[0].namespace,
+            "b.example.com",
+            "When tenants are configured in the configuration file, then they're validated"
+        );
+
+        let figment = figment.merge(Serialized::default("external_hostname", "example.org"));
+        assert_eq!(
+            Config::load(&figment).unwrap().external_hostname,
+            "example.org",
+            "When a later source sets a value, then it overrides the configuration file"
+        );
+
+        assert!(
+            matches!(
+                Config::load(&Figment::new()),
+                Err(DIDError::OwnerMissing(_))
+            ),
+            "When the owner is missing, then an error is returned"
+        );
+        assert!(
+            matches!(
+                Config::load(
+                    &figment
+                        .clone()
+                        .merge(Serialized::default("resolver_allowed_hosts", ["*"]))
+                ),
+                Err(DIDError::ConfigInvalid(_))
+            ),
+            "When all hosts are allowed for resolution, then an error is returned"
+        );
+        assert!(
+            matches!(
+                Config::load(&figment.clone().merge(Serialized::default("backend", "s4"))),
+                Err(DIDError::UnknownBackend(_))
+            ),
+            "When the backend is unknown, then an error is returned"
+        );
+        assert!(
+            matches!(
+                Config::load(&figment.merge(Serialized::default("external_port", "x"))),
+                Err(DIDError::ConfigInvalid(_))
+            ),
+            "When a value has the wrong type, then an error is returned"
+        );
+    }
+}
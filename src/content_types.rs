@@ -10,6 +10,11 @@ impl DIDContentTypes {
         "did+ld+json",
         &[("", "")],
     ));
+    pub const DID_RESOLUTION: ContentType = ContentType(MediaType::const_new(
+        "application",
+        "ld+json",
+        &[("profile", "https://w3id.org/did-resolution")],
+    ));
     // pub const DID_JSON: ContentType =
     //     ContentType(MediaType::const_new("application", "did+json", &[("", "")]));
 }
@@ -2,16 +2,20 @@
 
 use crate::config::Config;
 use crate::error::DIDError;
+use crate::tenant::Tenant;
+use percent_encoding::percent_decode_str;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sha256::digest;
 use ssi::vc::ProofPurpose;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
 use std::{fmt, path::PathBuf};
 
 static URL_SEGMENT_SEPARATOR: &str = "/";
 
-/// ProofParameters are reqiured to compute the Verifiable Presentation for updating the stored DID
-/// Dcoument.
+/// ProofParameters are required to compute the Verifiable Presentation for updating the stored DID
+/// Document.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ProofParameters {
     pub did: String,
@@ -26,29 +30,33 @@ pub struct ProofParameters {
 
 impl ProofParameters {
     /// Create a new ProofParameters struct
-    pub fn new(config: &rocket::State<Config>, id: &PathBuf) -> Result<ProofParameters, DIDError> {
-        let did = DIDWeb::from_config(config, id)?.to_string();
+    pub(crate) async fn new(
+        config: &rocket::State<Config>,
+        tenant: &Tenant,
+        id: &PathBuf,
+    ) -> Result<ProofParameters, DIDError> {
+        let did = DIDWeb::from_tenant(tenant, id)?.to_string();
         // either compute the the challenge from the DIDDoc or from the DID if DIDDoc isn't present
-        match config.store.get(id) {
+        match config.store.get(&tenant.store_id(id)).await {
             Ok(doc) => serde_json::to_string(&doc)
                 .map_err(|e| DIDError::ContentConversion(e.to_string()))
                 .map(|s| ProofParameters {
                     challenge: Some(digest(s)),
-                    ..ProofParameters::defaults(config, &did)
+                    ..ProofParameters::defaults(tenant, &did)
                 }),
             Err(DIDError::DIDNotFound(_)) => Ok(ProofParameters {
                 challenge: Some(digest(did.to_string())),
-                ..ProofParameters::defaults(config, &did)
+                ..ProofParameters::defaults(tenant, &did)
             }),
             Err(err) => Err(err),
         }
     }
     /// Create ProofParameters with challenge from DID
-    fn defaults(config: &rocket::State<Config>, did: &str) -> ProofParameters {
+    fn defaults(tenant: &Tenant, did: &str) -> ProofParameters {
         ProofParameters {
             did: did.to_string(),
             challenge: None,
-            domain: config.external_hostname.to_string(),
+            domain: tenant.hostname.to_string(),
             proof_purpose: ProofPurpose::Authentication,
         }
     }
@@ -63,19 +71,19 @@ impl fmt::Display for ProofParameters {
 }
 
 /// DIDWeb represents a DID of the DID Web method, see https://w3c-ccg.github.io/did-method-web/
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct DIDWeb {
-    host: DIDSegment, // FIXME: accept all valid host names and not just DIDSegment
+    host: DIDHost,
     port: u16, // FIXME: allow only valid ports to be stored here, .. maybe? create a custom type that excludes 0
     id: Vec<DIDSegment>,
 }
 
 impl fmt::Display for DIDWeb {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let host = if self.host.to_string() != "localhost" && self.port == 443 {
+        let host = if self.port == 443 {
             self.host.to_string()
         } else {
-            // join host with port host as specified in https://w3c-ccg.github.io/did-method-web/#method-specific-identifier
+            // join host with percent-encoded port as specified in https://w3c-ccg.github.io/did-method-web/#method-specific-identifier
             format!("{}%3A{}", self.host, self.port)
         };
         let id = self
@@ -93,13 +101,12 @@ impl fmt::Display for DIDWeb {
 }
 
 impl DIDWeb {
-    pub fn from_config(config: &rocket::State<Config>, id: &PathBuf) -> Result<DIDWeb, DIDError> {
-        DIDWeb::new(
-            &config.external_hostname,
-            &config.external_port,
-            &config.external_path,
-            id,
-        )
+    /// Builds DID from the tenant and id.
+    ///
+    /// * `tenant` - tenant that hosts the DID.
+    /// * `id` - requested id.
+    pub(crate) fn from_tenant(tenant: &Tenant, id: &PathBuf) -> Result<DIDWeb, DIDError> {
+        DIDWeb::new(&tenant.hostname, &tenant.port, &tenant.path, id)
     }
     pub fn new(host: &str, port: &str, path: &str, id: &PathBuf) -> Result<DIDWeb, DIDError> {
         let port = match port.parse::<u16>() {
@@ -167,7 +174,7 @@ impl DIDWeb {
         }
 
         Ok(DIDWeb {
-            host: DIDSegment::from(host)?,
+            host: host.parse()?,
             port,
             id: _id,
         })
@@ -178,24 +185,189 @@ impl DIDWeb {
         "web"
     }
 
-    /// Builds DID from the service configuration and id.
+    /// Returns the host of the DID.
+    pub fn host(&self) -> &DIDHost {
+        &self.host
+    }
+
+    /// Returns the port of the DID.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the path of the DID document relative to the server's root, e.g. `alice/did.json`, or
+    /// `.well-known/did.json` if the DID has no path, see https://w3c-ccg.github.io/did-method-web/#read-resolve
+    pub fn path(&self) -> PathBuf {
+        if self.id.is_empty() {
+            PathBuf::from(".well-known/did.json")
+        } else {
+            self.id
+                .iter()
+                .map(|segment| segment.0.as_str())
+                .collect::<PathBuf>()
+                .join("did.json")
+        }
+    }
+
+    /// Returns the requested id of the DID relative to a base DID, e.g. `alice/did.json` for
+    /// `did:web:example.com:users:alice` and the base `did:web:example.com:users`. The id of the base DID itself is
+    /// `/.well-known/did.json`.
     ///
-    /// * `config` - service configuration.
-    /// * `id` - requested id.
-    pub fn did_from_config(
-        config: &rocket::State<Config>,
-        id: &PathBuf,
-    ) -> Result<DIDWeb, DIDError> {
-        DIDWeb::new(
-            &config.external_hostname,
-            &config.external_port,
-            &config.external_path,
-            id,
-        )
+    /// - returns `None` if the DID doesn't share host, port and path with the base DID
+    pub fn id_relative_to(&self, base: &DIDWeb) -> Option<PathBuf> {
+        if self.host != base.host || self.port != base.port || !self.id.starts_with(&base.id) {
+            return None;
+        }
+        let id = &self.id[base.id.len()..];
+        if id.is_empty() {
+            Some(PathBuf::from("/.well-known/did.json"))
+        } else {
+            Some(
+                id.iter()
+                    .map(|segment| segment.0.as_str())
+                    .collect::<PathBuf>()
+                    .join("did.json"),
+            )
+        }
+    }
+
+    /// Splits the percent-encoded host and optional port, e.g. `example.com%3A8443`. The port defaults to 443.
+    fn parse_host_port(host: &str) -> Result<(DIDHost, u16), DIDError> {
+        // the encoding only uses ASCII characters, so the byte positions of the lowercase copy match
+        let lowercase = host.to_ascii_lowercase();
+        // IPv6 literals contain percent-encoded colons themselves, the port follows the closing bracket
+        let start = if lowercase.starts_with("%5b") {
+            lowercase
+                .find("%5d")
+                .map(|i| i + 3)
+                .unwrap_or(lowercase.len())
+        } else {
+            0
+        };
+        let (host, port) = match lowercase[start..].rfind("%3a") {
+            Some(i) => (&host[..start + i], Some(&host[start + i + 3..])),
+            None => (host, None),
+        };
+        let port = match port {
+            Some(port) => match port.parse::<u16>() {
+                Ok(p) if p != 0 && port.chars().all(|c| c.is_ascii_digit()) => p,
+                _ => {
+                    return Err(DIDError::DIDPortNotAllowed(format!(
+                        "Port '{}' out of range, expected value between 1 and 65535",
+                        port
+                    )))
+                }
+            },
+            None => 443,
+        };
+        Ok((host.parse()?, port))
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl FromStr for DIDWeb {
+    type Err = DIDError;
+
+    /// Parses a did:web DID, e.g. `did:web:example.com%3A8443:alice`. Percent-encoded characters are decoded. DID
+    /// URLs, empty segments and segments that would traverse the path, e.g. `..`, are rejected.
+    fn from_str(did: &str) -> Result<Self, Self::Err> {
+        let method_specific_id = did
+            .strip_prefix("did:")
+            .and_then(|did| did.strip_prefix(DIDWeb::name()))
+            .and_then(|did| did.strip_prefix(':'))
+            .ok_or_else(|| DIDError::DIDMismatch(format!("DID isn't a did:web DID: {}", did)))?;
+        if method_specific_id.contains(&['/', '?', '#'][..]) {
+            return Err(DIDError::IllegalCharacter(
+                "DID URLs with path, query or fragment aren't supported".to_string(),
+            ));
+        }
+        let mut segments = method_specific_id.split(':');
+        let (host, port) = DIDWeb::parse_host_port(segments.next().unwrap_or_default())?;
+        let id = segments
+            .map(|segment| {
+                percent_decode_str(segment)
+                    .decode_utf8()
+                    .map_err(|e| DIDError::IllegalCharacter(e.to_string()))
+                    .and_then(|segment| DIDSegment::from(&segment))
+            })
+            .collect::<Result<Vec<DIDSegment>, DIDError>>()?;
+        Ok(DIDWeb { host, port, id })
+    }
+}
+
+/// Host of a did:web DID. Domain names are stored in their ASCII form, i.e. internationalized domain names are
+/// punycode encoded. IP addresses are accepted even though the did:web specification discourages their use.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DIDHost {
+    Domain(String),
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+}
+
+impl DIDHost {
+    /// Determines whether the host is local or private, i.e. `localhost`, a loopback, private, link-local or
+    /// unspecified IP address. Such hosts must never be fetched on behalf of clients.
+    pub fn is_internal(&self) -> bool {
+        let ipv4 = |ip: &Ipv4Addr| {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // shared address space for carrier-grade NAT, 100.64.0.0/10
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+        };
+        match self {
+            DIDHost::Domain(domain) => domain == "localhost" || domain.ends_with(".localhost"),
+            DIDHost::Ipv4(ip) => ipv4(ip),
+            DIDHost::Ipv6(ip) => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    // unique local addresses, fc00::/7
+                    || ip.segments()[0] & 0xfe00 == 0xfc00
+                    // link-local addresses, fe80::/10
+                    || ip.segments()[0] & 0xffc0 == 0xfe80
+                    || ip.to_ipv4_mapped().as_ref().map_or(false, ipv4)
+            }
+        }
+    }
+}
+
+/// Returns the host in its canonical did:web form.
+impl fmt::Display for DIDHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DIDHost::Domain(domain) => write!(f, "{}", domain),
+            DIDHost::Ipv4(ip) => write!(f, "{}", ip),
+            // brackets and colons aren't allowed in the method-specific identifier and are percent-encoded
+            DIDHost::Ipv6(ip) => write!(f, "%5B{}%5D", ip.to_string().replace(':', "%3A")),
+        }
+    }
+}
+
+impl FromStr for DIDHost {
+    type Err = DIDError;
+
+    /// Parses a host without port in its Unicode, ASCII or percent-encoded form, e.g. `müller.ch`,
+    /// `xn--mller-kva.ch` or `%5B%3A%3A1%5D`.
+    fn from_str(host: &str) -> Result<Self, Self::Err> {
+        let illegal = || DIDError::IllegalCharacter(format!("host is invalid: {}", host));
+        let host = percent_decode_str(host)
+            .decode_utf8()
+            .map_err(|_| illegal())?;
+        if let Some(ip) = host.strip_prefix('[').and_then(|ip| ip.strip_suffix(']')) {
+            return ip.parse().map(DIDHost::Ipv6).map_err(|_| illegal());
+        }
+        if let Ok(ip) = host.parse() {
+            return Ok(DIDHost::Ipv4(ip));
+        }
+        // the strict conversion rejects empty labels, disallowed characters like ':' and names that are too long
+        idna::domain_to_ascii_strict(&host)
+            .map(DIDHost::Domain)
+            .map_err(|_| illegal())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 struct DIDSegment(String);
 
 impl fmt::Display for DIDSegment {
@@ -208,7 +380,8 @@ impl DIDSegment {
     fn from(segment: &str) -> Result<DIDSegment, DIDError> {
         // ensure that each segment conforms to the DID Syntax specification: https://w3c.github.io/did-core/#did-syntax
         let re = Regex::new(r"^[0-9a-zA-Z._-]+$").unwrap();
-        if re.is_match(segment) {
+        // segments are mapped to directories and must not traverse the path
+        if re.is_match(segment) && segment != "." && segment != ".." {
             Ok(DIDSegment(segment.to_owned()))
         } else {
             Err(DIDError::IllegalCharacter(
@@ -228,6 +401,155 @@ impl DIDSegment {
 #[cfg(test)]
 mod test {
     use crate::did::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_did_web_from_str() {
+        let did = DIDWeb::from_str("did:web:example.com%3A8443:users:alice").unwrap();
+        assert_eq!(did.host(), &DIDHost::Domain("example.com".to_string()));
+        assert_eq!(did.port(), 8443);
+        assert_eq!(
+            did.path(),
+            PathBuf::from("users/alice/did.json"),
+            "When the DID has a path, then the path is mapped to directories"
+        );
+
+        let did = DIDWeb::from_str("did:web:xn--mller-kva.ch").unwrap();
+        assert_eq!(did.port(), 443, "When no port is set, then 443 is assumed");
+        assert_eq!(
+            did.path(),
+            PathBuf::from(".well-known/did.json"),
+            "When the DID has no path, then the document is located in .well-known"
+        );
+
+        let did = DIDWeb::from_str("did:web:%5B%3A%3A1%5D%3A8000:alice").unwrap();
+        assert_eq!(
+            (did.host(), did.port()),
+            (&DIDHost::Ipv6(Ipv6Addr::LOCALHOST), 8000),
+            "When the host is an IPv6 literal, then the port follows the closing bracket"
+        );
+        assert_eq!(
+            DIDWeb::from_str("did:web:example.com:%61lice")
+                .unwrap()
+                .to_string(),
+            "did:web:example.com:alice",
+            "When a segment is percent-encoded, then it's decoded"
+        );
+
+        for did in [
+            "did:key:z6Mk",
+            "did:web:",
+            "did:web:example.com::alice",
+            "did:web:example.com:alice:",
+            "did:web:example.com:..:etc",
+            "did:web:example.com:%2E%2E:etc",
+            "did:web:example.com:a%2Fb",
+            "did:web:example.com:alice#key-1",
+            "did:web:example.com:alice/did.json",
+            "did:web:example.com%3A0",
+            "did:web:example.com%3A65536",
+            "did:web:example.com%3A+80",
+        ] {
+            assert!(
+                DIDWeb::from_str(did).is_err(),
+                "When the DID is '{}', then an error is returned",
+                did
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_did_web_round_trip(
+            host in "[a-z0-9]{1,20}(\\.[a-z0-9]{1,20}){0,3}",
+            port in 1u16..,
+            segments in proptest::collection::vec("[0-9a-zA-Z_-][0-9a-zA-Z._-]{0,15}", 0..4),
+        ) {
+            let id = PathBuf::from(".well-known/did.json");
+            let did = DIDWeb::new(&host, &port.to_string(), &segments.join("/"), &id).unwrap();
+            let parsed = DIDWeb::from_str(&did.to_string()).unwrap();
+            prop_assert_eq!(parsed.to_string(), did.to_string());
+            prop_assert_eq!(parsed.path(), did.path());
+            prop_assert_eq!(parsed, did);
+        }
+
+        #[test]
+        fn test_did_web_round_trip_ipv6(ip in any::<Ipv6Addr>(), port in 1u16..) {
+            let id = PathBuf::from(".well-known/did.json");
+            let did = DIDWeb::new(&format!("[{}]", ip), &port.to_string(), "", &id).unwrap();
+            prop_assert_eq!(DIDWeb::from_str(&did.to_string()).unwrap(), did);
+        }
+    }
+
+    #[test]
+    fn test_did_host() {
+        let host = DIDHost::from_str("Müller.ch").unwrap();
+        assert_eq!(
+            host.to_string(),
+            "xn--mller-kva.ch",
+            "When <host> is an internationalized domain name, then it's punycode encoded"
+        );
+        assert_eq!(
+            DIDHost::from_str("xn--mller-kva.ch").unwrap(),
+            host,
+            "When <host> is punycode encoded, then it's equal to the Unicode form"
+        );
+
+        let host = DIDHost::from_str("[2001:DB8::1]").unwrap();
+        assert_eq!(
+            host.to_string(),
+            "%5B2001%3Adb8%3A%3A1%5D",
+            "When <host> is an IPv6 literal, then brackets and colons are percent-encoded"
+        );
+        assert_eq!(
+            DIDHost::from_str(&host.to_string()).unwrap(),
+            host,
+            "When <host> is percent-encoded, then it's decoded"
+        );
+        assert_eq!(
+            DIDHost::from_str("192.168.1.1").unwrap(),
+            DIDHost::Ipv4(Ipv4Addr::new(192, 168, 1, 1))
+        );
+
+        for (host, internal) in [
+            ("example.com", false),
+            ("localhost", true),
+            ("app.localhost", true),
+            ("8.8.8.8", false),
+            ("127.0.0.1", true),
+            ("10.1.2.3", true),
+            ("169.254.169.254", true),
+            ("100.64.0.1", true),
+            ("[2001:db8::1]", false),
+            ("[::1]", true),
+            ("[fd00::1]", true),
+            ("[fe80::1]", true),
+            ("[::ffff:192.168.1.1]", true),
+        ] {
+            assert_eq!(
+                DIDHost::from_str(host).unwrap().is_internal(),
+                internal,
+                "When <host> is '{}', then it's internal: {}",
+                host,
+                internal
+            );
+        }
+
+        for host in [
+            "",
+            "example.com:8443",
+            "exa mple.com",
+            "a..b",
+            "%ZZ",
+            "[::1",
+        ] {
+            assert!(
+                DIDHost::from_str(host).is_err(),
+                "When <host> is '{}', then an error is returned",
+                host
+            );
+        }
+    }
 
     #[test]
     fn test_did_web() {
@@ -273,6 +595,28 @@ mod test {
             "When <port> is empty and host is 'localhost', then the default port 8000 is assumed"
         );
 
+        let host = "Müller.ch";
+        let port = "443";
+        let path = "/";
+        let id = PathBuf::from("abc/did.json");
+        let result = DIDWeb::new(host, port, path, &id).unwrap();
+        assert_eq!(
+            result.to_string(),
+            "did:web:xn--mller-kva.ch:abc",
+            "When <host> is an internationalized domain name and <port> is 443, then the punycode host without port is used"
+        );
+
+        let host = "[::1]";
+        let port = "8443";
+        let path = "/";
+        let id = PathBuf::from("abc/did.json");
+        let result = DIDWeb::new(host, port, path, &id).unwrap();
+        assert_eq!(
+            result.to_string(),
+            "did:web:%5B%3A%3A1%5D%3A8443:abc",
+            "When <host> is an IPv6 literal, then the host and port are percent-encoded"
+        );
+
         let host = "example.com";
         let port = "";
         let path = "";
@@ -1,6 +1,10 @@
 // SPDX-License-Identifier: AGPL-3.0
 
-use rocket::response::Responder;
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket::Request;
+
+use crate::metrics;
 
 /// Custom response status
 #[derive(Responder)]
@@ -10,53 +14,142 @@ pub enum CustomStatus<T> {
 }
 
 // TODO: change String to &'static str to avoid reallocation of the error on the heap
-#[derive(Debug, Responder)]
+#[derive(Debug)]
 pub enum DIDError {
-    #[response(status = 500)] // InternalServerError
     ContentConversion(String),
-    #[response(status = 500)] // TODO: return a default value instead of an error code, maybe
     NoFileRead(String),
-    #[response(status = 500)] // InternalServerError
     NoFileWrite(String),
-    #[response(status = 400)] // BadRequest
     NoFileName(String),
-    #[response(status = 409)] // Conflict
     DIDExists(String),
-    #[response(status = 400)] // BadRequest
     IllegalCharacter(String),
-    #[response(status = 400)] // BadRequest
     DIDDocMissing(String),
-    #[response(status = 400)] // BadRequest
     DIDMismatch(String),
-    #[response(status = 404)] // NotFound
     DIDNotFound(String),
-    #[response(status = 400)] // Bad Request
     DIDPortNotAllowed(String),
-    #[response(status = 401)] // Unauthorized
     PresentationInvalid(String),
-    #[response(status = 500)] // InternalServerError
     UnknownBackend(String),
+    UnknownLogFormat(String),
     OwnerMissing(String),
+    AuditLogTampered(String),
+    UnknownTenant(String),
+    ConfigInvalid(String),
+    StoreUnavailable(String),
+    ReadOnlyReplica(String),
+    ReplicationDenied(String),
+    InvitationInvalid(String),
+    ChangesCompacted(String),
+    ChangesDenied(String),
+    MetricsDenied(String),
+    SnapshotRefused(String),
+}
+
+impl DIDError {
+    /// Name of the error, e.g. `DIDNotFound`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DIDError::ContentConversion(_) => "ContentConversion",
+            DIDError::NoFileRead(_) => "NoFileRead",
+            DIDError::NoFileWrite(_) => "NoFileWrite",
+            DIDError::NoFileName(_) => "NoFileName",
+            DIDError::DIDExists(_) => "DIDExists",
+            DIDError::IllegalCharacter(_) => "IllegalCharacter",
+            DIDError::DIDDocMissing(_) => "DIDDocMissing",
+            DIDError::DIDMismatch(_) => "DIDMismatch",
+            DIDError::DIDNotFound(_) => "DIDNotFound",
+            DIDError::DIDPortNotAllowed(_) => "DIDPortNotAllowed",
+            DIDError::PresentationInvalid(_) => "PresentationInvalid",
+            DIDError::UnknownBackend(_) => "UnknownBackend",
+            DIDError::UnknownLogFormat(_) => "UnknownLogFormat",
+            DIDError::OwnerMissing(_) => "OwnerMissing",
+            DIDError::AuditLogTampered(_) => "AuditLogTampered",
+            DIDError::UnknownTenant(_) => "UnknownTenant",
+            DIDError::ConfigInvalid(_) => "ConfigInvalid",
+            DIDError::StoreUnavailable(_) => "StoreUnavailable",
+            DIDError::ReadOnlyReplica(_) => "ReadOnlyReplica",
+            DIDError::ReplicationDenied(_) => "ReplicationDenied",
+            DIDError::InvitationInvalid(_) => "InvitationInvalid",
+            DIDError::ChangesCompacted(_) => "ChangesCompacted",
+            DIDError::ChangesDenied(_) => "ChangesDenied",
+            DIDError::MetricsDenied(_) => "MetricsDenied",
+            DIDError::SnapshotRefused(_) => "SnapshotRefused",
+        }
+    }
+
+    /// HTTP status that is returned for the error.
+    pub fn status(&self) -> Status {
+        match self {
+            DIDError::ContentConversion(_) => Status::InternalServerError,
+            // TODO: return a default value instead of an error code, maybe
+            DIDError::NoFileRead(_) => Status::InternalServerError,
+            DIDError::NoFileWrite(_) => Status::InternalServerError,
+            DIDError::NoFileName(_) => Status::BadRequest,
+            DIDError::DIDExists(_) => Status::Conflict,
+            DIDError::IllegalCharacter(_) => Status::BadRequest,
+            DIDError::DIDDocMissing(_) => Status::BadRequest,
+            DIDError::DIDMismatch(_) => Status::BadRequest,
+            DIDError::DIDNotFound(_) => Status::NotFound,
+            DIDError::DIDPortNotAllowed(_) => Status::BadRequest,
+            DIDError::PresentationInvalid(_) => Status::Unauthorized,
+            DIDError::UnknownBackend(_) => Status::InternalServerError,
+            DIDError::UnknownLogFormat(_) => Status::InternalServerError,
+            DIDError::OwnerMissing(_) => Status::InternalServerError,
+            DIDError::AuditLogTampered(_) => Status::InternalServerError,
+            DIDError::UnknownTenant(_) => Status::NotFound,
+            DIDError::ConfigInvalid(_) => Status::InternalServerError,
+            DIDError::StoreUnavailable(_) => Status::ServiceUnavailable,
+            DIDError::ReadOnlyReplica(_) => Status::MethodNotAllowed,
+            DIDError::ReplicationDenied(_) => Status::Unauthorized,
+            DIDError::InvitationInvalid(_) => Status::Unauthorized,
+            DIDError::ChangesCompacted(_) => Status::Gone,
+            DIDError::ChangesDenied(_) => Status::Unauthorized,
+            DIDError::MetricsDenied(_) => Status::Unauthorized,
+            DIDError::SnapshotRefused(_) => Status::Conflict,
+        }
+    }
+
+    /// Message of the error.
+    pub fn message(&self) -> &str {
+        match self {
+            DIDError::ContentConversion(e)
+            | DIDError::NoFileRead(e)
+            | DIDError::NoFileWrite(e)
+            | DIDError::NoFileName(e)
+            | DIDError::DIDExists(e)
+            | DIDError::IllegalCharacter(e)
+            | DIDError::DIDDocMissing(e)
+            | DIDError::DIDMismatch(e)
+            | DIDError::DIDNotFound(e)
+            | DIDError::DIDPortNotAllowed(e)
+            | DIDError::PresentationInvalid(e)
+            | DIDError::UnknownBackend(e)
+            | DIDError::UnknownLogFormat(e)
+            | DIDError::OwnerMissing(e)
+            | DIDError::AuditLogTampered(e)
+            | DIDError::UnknownTenant(e)
+            | DIDError::ConfigInvalid(e)
+            | DIDError::StoreUnavailable(e)
+            | DIDError::ReadOnlyReplica(e)
+            | DIDError::ReplicationDenied(e)
+            | DIDError::InvitationInvalid(e)
+            | DIDError::ChangesCompacted(e)
+            | DIDError::ChangesDenied(e)
+            | DIDError::MetricsDenied(e)
+            | DIDError::SnapshotRefused(e) => e,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for DIDError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        metrics::ERRORS.with_label_values(&[self.name()]).inc();
+        (self.status(), self.message().to_string()).respond_to(request)
+    }
 }
 
 impl std::error::Error for DIDError {}
 
 impl std::fmt::Display for DIDError {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        match self {
-            DIDError::ContentConversion(e) => write!(fmt, "Error {}.", e),
-            DIDError::NoFileRead(e) => write!(fmt, "Error {}.", e),
-            DIDError::NoFileWrite(e) => write!(fmt, "Error {}.", e),
-            DIDError::NoFileName(e) => write!(fmt, "Error {}.", e),
-            DIDError::DIDExists(e) => write!(fmt, "Error {}.", e),
-            DIDError::IllegalCharacter(e) => write!(fmt, "Error {}.", e),
-            DIDError::DIDMismatch(e) => write!(fmt, "Error {}.", e),
-            DIDError::DIDDocMissing(e) => write!(fmt, "Error {}.", e),
-            DIDError::DIDNotFound(e) => write!(fmt, "Error {}.", e),
-            DIDError::DIDPortNotAllowed(e) => write!(fmt, "Error {}.", e),
-            DIDError::PresentationInvalid(e) => write!(fmt, "Error {}.", e),
-            DIDError::UnknownBackend(e) => write!(fmt, "Error {}.", e),
-            DIDError::OwnerMissing(e) => write!(fmt, "Error {}.", e),
-        }
+        write!(fmt, "Error {}.", self.message())
     }
 }
@@ -0,0 +1,303 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use either::Either;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{Build, Rocket};
+use serde::Serialize;
+use ssi::did::VerificationRelationship;
+use ssi::did_resolve::{
+    DIDResolver, ResolutionInputMetadata, ERROR_INVALID_DID, ERROR_METHOD_NOT_SUPPORTED,
+    ERROR_NOT_FOUND,
+};
+use std::collections::BTreeSet;
+
+use crate::config::Config;
+use crate::store::BACKENDS;
+use crate::utils::OWNER_RELATIONSHIP;
+
+/// Result of a single readiness check.
+///
+/// * `ok` - Whether the check succeeded
+/// * `degraded` - Set if the check succeeded with a temporary problem, e.g. a resolver that is unavailable while the
+///   cached DID document is still used
+/// * `error` - Reason of the failure or degradation
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub degraded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn from_result<E: ToString>(result: Result<(), E>) -> Check {
+        match result {
+            Ok(()) => Check {
+                ok: true,
+                degraded: false,
+                error: None,
+            },
+            Err(e) => Check {
+                ok: false,
+                degraded: false,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Failure of a resolution.
+enum ResolutionFailure {
+    /// The DID can't be resolved, e.g. because it doesn't exist
+    Permanent(String),
+    /// The resolver failed, e.g. because it's unavailable
+    Transient(String),
+}
+
+/// Readiness of the server and the result of each check.
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub store: Check,
+    pub owner: Check,
+    pub tls: Check,
+}
+
+/// Build information of the server.
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    /// Backends that can be selected via `DWS_BACKEND`.
+    pub backends: &'static [&'static str],
+}
+
+/// Liveness probe. Succeeds as long as the server is able to respond to requests.
+#[get("/healthz")]
+pub fn healthz() -> Status {
+    Status::Ok
+}
+
+/// Readiness probe. Succeeds if the store is accessible, the DIDs of the server's and tenants' owners can be resolved
+/// and the TLS material is loaded. Temporary resolver failures only mark the owner check as degraded.
+///
+/// - `config` Global Rocket configuration
+/// - `rocket_config` Rocket's configuration, used for inspecting the TLS settings
+/// - returns 200 - OK if all checks succeed, otherwise 503 - Service Unavailable
+#[get("/readyz")]
+pub async fn readyz(
+    config: &rocket::State<Config>,
+    rocket_config: &rocket::Config,
+) -> (Status, Json<Readiness>) {
+    let store = Check::from_result(config.store.check().await);
+    let owner = check_owners(config).await;
+    let tls = Check::from_result(check_tls(rocket_config));
+    let ready = store.ok && owner.ok && tls.ok;
+    let status = if ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    (
+        status,
+        Json(Readiness {
+            ready,
+            store,
+            owner,
+            tls,
+        }),
+    )
+}
+
+/// Build information, i.e. the version of the server and the available backends.
+#[get("/version")]
+pub fn version() -> Json<BuildInfo> {
+    Json(BuildInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        backends: BACKENDS,
+    })
+}
+
+/// Ensures that the DIDs of the server's and tenants' owners can be resolved. Resolutions go through the resolver
+/// cache, so that the probe doesn't put load on the resolvers.
+async fn check_owners(config: &Config) -> Check {
+    let mut permanent = vec![];
+    let mut transient = vec![];
+    for owner in owners(config) {
+        match resolve_owner(config, &owner).await {
+            Ok(()) => {}
+            Err(ResolutionFailure::Permanent(e)) => permanent.push(format!("{}: {}", owner, e)),
+            Err(ResolutionFailure::Transient(e)) => transient.push(format!("{}: {}", owner, e)),
+        }
+    }
+    let errors = permanent
+        .iter()
+        .chain(transient.iter())
+        .cloned()
+        .collect::<Vec<String>>();
+    Check {
+        ok: permanent.is_empty(),
+        degraded: permanent.is_empty() && !transient.is_empty(),
+        error: if errors.is_empty() {
+            None
+        } else {
+            Some(format!(
+                "owner DID couldn't be resolved: {}",
+                errors.join(", ")
+            ))
+        },
+    }
+}
+
+/// Resolves an owner's DID.
+async fn resolve_owner(config: &Config, owner: &str) -> Result<(), ResolutionFailure> {
+    let (metadata, document, _) = config
+        .get_resolver()
+        .resolve(owner, &ResolutionInputMetadata::default())
+        .await;
+    match (metadata.error, document) {
+        (Some(error), _)
+            if [
+                ERROR_NOT_FOUND,
+                ERROR_INVALID_DID,
+                ERROR_METHOD_NOT_SUPPORTED,
+            ]
+            .contains(&error.as_str()) =>
+        {
+            Err(ResolutionFailure::Permanent(error))
+        }
+        (Some(error), _) => Err(ResolutionFailure::Transient(error)),
+        (None, None) => Err(ResolutionFailure::Permanent("no DID document".to_string())),
+        (None, Some(_)) => Ok(()),
+    }
+}
+
+/// DIDs of the server's and tenants' owners.
+fn owners(config: &Config) -> BTreeSet<String> {
+    let policy = config.policy.load();
+    std::iter::once(policy.owner.clone())
+        .chain(
+            policy
+                .tenants
+                .iter()
+                .flat_map(|tenant| tenant.owners.iter().cloned()),
+        )
+        .collect()
+}
+
+/// Verification methods of an owner's DID document that authorize requests.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OwnerKeys {
+    pub authentication: Vec<String>,
+    pub capability_invocation: Vec<String>,
+}
+
+/// Resolves an owner's DID and retrieves its verification methods.
+///
+/// - `config` - server configuration
+/// - `owner` - DID of the owner
+/// - returns the verification methods or a diagnostic if the DID can't be resolved or has no methods that presentations
+///   can be signed with
+pub async fn owner_keys(config: &Config, owner: &str) -> Result<OwnerKeys, String> {
+    let (metadata, document, _) = config
+        .get_resolver()
+        .resolve(owner, &ResolutionInputMetadata::default())
+        .await;
+    let document = match (metadata.error, document) {
+        (Some(error), _) => return Err(format!("DID couldn't be resolved: {}", error)),
+        (None, None) => return Err("DID couldn't be resolved".to_string()),
+        (None, Some(document)) => document,
+    };
+    let methods = |relationship| {
+        document
+            .get_verification_method_ids(relationship)
+            .unwrap_or_default()
+    };
+    // presentations of owners are verified against the methods of the owner relationship
+    if methods(OWNER_RELATIONSHIP).is_empty() {
+        return Err(
+            "DID document has no methods that presentations can be signed with".to_string(),
+        );
+    }
+    Ok(OwnerKeys {
+        authentication: methods(VerificationRelationship::Authentication),
+        capability_invocation: methods(VerificationRelationship::CapabilityInvocation),
+    })
+}
+
+/// Resolves the DIDs of the server's and tenants' owners and logs their verification methods.
+///
+/// - `config` - server configuration whose policy lists the owners
+/// - returns the owners that can't be resolved or have no usable keys together with a diagnostic
+pub async fn invalid_owners(config: &Config) -> Vec<(String, String)> {
+    let mut invalid = vec![];
+    for owner in owners(config) {
+        match owner_keys(config, &owner).await {
+            Ok(keys) => tracing::info!(
+                owner = owner.as_str(),
+                authentication = ?keys.authentication,
+                capability_invocation = ?keys.capability_invocation,
+                "owner DID resolved"
+            ),
+            Err(e) => invalid.push((owner, e)),
+        }
+    }
+    invalid
+}
+
+/// Fairing that resolves the DIDs of the server's and tenants' owners at startup and logs their verification methods.
+/// Startup is aborted if an owner can't be resolved or has no usable keys, unless `lenient_startup` is set.
+pub struct OwnerValidator;
+
+#[rocket::async_trait]
+impl Fairing for OwnerValidator {
+    fn info(&self) -> Info {
+        Info {
+            name: "Owner validator",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = match rocket.state::<Config>() {
+            Some(config) => config,
+            None => return Ok(rocket),
+        };
+        let invalid = invalid_owners(config).await;
+        for (owner, e) in invalid.iter() {
+            if config.lenient_startup {
+                tracing::warn!(
+                    owner = owner.as_str(),
+                    error = %e,
+                    "owner DID invalid, continuing in lenient mode"
+                );
+            } else {
+                tracing::error!(owner = owner.as_str(), error = %e, "owner DID invalid");
+            }
+        }
+        if invalid.is_empty() || config.lenient_startup {
+            Ok(rocket)
+        } else {
+            Err(rocket)
+        }
+    }
+}
+
+/// Ensures that the TLS certificate chain and private key are readable if TLS is configured.
+fn check_tls(rocket_config: &rocket::Config) -> Result<(), String> {
+    match &rocket_config.tls {
+        Some(tls) => [tls.certs(), tls.key()]
+            .into_iter()
+            .try_for_each(|material| match material {
+                Either::Left(path) => std::fs::metadata(&path)
+                    .map(|_| ())
+                    .map_err(|e| format!("TLS material {} not accessible: {}", path.display(), e)),
+                Either::Right(_) => Ok(()),
+            }),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,372 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha256::digest;
+use ssi::did::VerificationRelationship;
+use ssi::vc::{Credential, CredentialOrJWT, LinkedDataProofOptions, Presentation, ProofPurpose};
+use ssi_json_ld::ContextLoader;
+use std::cmp::Ordering;
+use std::future::Future;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::error::DIDError;
+use crate::store::DIDWebStore;
+use crate::utils::{compare_date, verify_issuer};
+
+/// Type of invitation credentials.
+pub static INVITATION_TYPE: &str = "RegistrationInvitation";
+
+lazy_static! {
+    /// JSON-LD context that invitation credentials must include, so that the restriction of the path is covered by the
+    /// owner's signature.
+    pub static ref INVITATION_CONTEXT: Value = json!({
+        "RegistrationInvitation": "https://dws.identinet.io/vocab#RegistrationInvitation",
+        "path": "https://dws.identinet.io/vocab#path",
+        "pathPrefix": "https://dws.identinet.io/vocab#pathPrefix"
+    });
+}
+
+/// Invitation of an owner that authorises the invitee to register a DID once.
+///
+/// * `id` - sha256 hash of the issuer and the id of the invitation credential. Both are covered by the issuer's
+///   signature, so every copy of the credential has the same id, no matter how it has been serialised.
+/// * `issuer` - DID of the owner that issued the invitation
+/// * `invitee` - DID of the holder that's allowed to register the DID
+/// * `verification_method` - verification method of the invitee that signed the presentation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invitation {
+    pub id: String,
+    pub issuer: String,
+    pub invitee: String,
+    pub verification_method: String,
+}
+
+/// Redemption of an invitation, recorded as value of the invitation's reservation in the store.
+///
+/// * `timestamp` - Time of the redemption in RFC 3339 format
+/// * `invitation` - id of the invitation, see [`Invitation`]
+/// * `did` - DID that has been registered
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Redemption {
+    pub timestamp: String,
+    pub invitation: String,
+    pub did: String,
+}
+
+/// Redeems an invitation by performing an operation, e.g. the creation of the DID. The invitation is reserved in the
+/// store before the operation is performed, so concurrent requests and other instances that share the store can't
+/// redeem it twice, also after a restart. The reservation is released if the operation fails. If the store doesn't
+/// support reservations, the redemption fails with StoreUnavailable without performing the operation.
+///
+/// - `store` - store that the redemption is recorded in
+/// - `invitation` - invitation that authorises the operation
+/// - `did` - DID that's registered
+/// - `operation` - operation that's performed
+/// - returns the result of the operation
+pub async fn redeem<T, F>(
+    store: &(dyn DIDWebStore + Sync + Send),
+    invitation: &Invitation,
+    did: &str,
+    operation: F,
+) -> Result<T, DIDError>
+where
+    F: Future<Output = Result<T, DIDError>>,
+{
+    let key = format!("invitation-{}", invitation.id);
+    let redemption = serde_json::to_string(&Redemption {
+        timestamp: Utc::now().to_rfc3339(),
+        invitation: invitation.id.clone(),
+        did: did.to_string(),
+    })
+    .map_err(|e| DIDError::ContentConversion(e.to_string()))?;
+    let reserved = store.reserve(&key, &redemption).await.map_err(|e| {
+        tracing::error!(
+            error = e.name(),
+            reason = e.message(),
+            "invitation not reserved"
+        );
+        match e {
+            DIDError::StoreUnavailable(reason) => DIDError::StoreUnavailable(format!(
+                "Invitation can't be redeemed, reservation failed: {}",
+                reason
+            )),
+            e => e,
+        }
+    })?;
+    if !reserved {
+        return Err(DIDError::InvitationInvalid(
+            "Invitation has already been redeemed".to_string(),
+        ));
+    }
+    let result = operation.await;
+    if result.is_err() {
+        if let Err(e) = store.release(&key).await {
+            tracing::error!(
+                error = e.name(),
+                reason = e.message(),
+                "invitation not released"
+            );
+        }
+    }
+    result
+}
+
+/// Finds the first invitation credential of a presentation.
+fn find_invitation(presentation: &Presentation) -> Option<&Credential> {
+    presentation
+        .verifiable_credential
+        .as_ref()?
+        .into_iter()
+        .find_map(|credential| match credential {
+            CredentialOrJWT::Credential(credential)
+                if credential.type_.contains(&INVITATION_TYPE.to_string()) =>
+            {
+                Some(credential)
+            }
+            _ => None,
+        })
+}
+
+/// Ensures that the credential includes [`INVITATION_CONTEXT`].
+fn has_invitation_context(credential: &Credential) -> bool {
+    match serde_json::to_value(&credential.context) {
+        Ok(Value::Array(contexts)) => contexts.contains(&*INVITATION_CONTEXT),
+        _ => false,
+    }
+}
+
+/// Checks whether an invitation's restriction authorises the registration of an id.
+///
+/// - `path` - path that the invitation authorises, e.g. `employees/alice`
+/// - `path_prefix` - path below which any DID may be registered, e.g. `employees`. Ignored if `path` is set.
+/// - `id` - id of the DID that's registered, e.g. `employees/alice/did.json`
+pub fn authorizes(path: Option<&str>, path_prefix: Option<&str>, id: &Path) -> bool {
+    let registered = if id.ends_with("did.json") {
+        id.parent().unwrap_or(id)
+    } else {
+        id
+    };
+    match (path, path_prefix) {
+        (Some(path), _) => registered == Path::new(path.trim_matches('/')),
+        (None, Some(path_prefix)) => {
+            let path_prefix = Path::new(path_prefix.trim_matches('/'));
+            registered.starts_with(path_prefix) && registered != path_prefix
+        }
+        _ => false,
+    }
+}
+
+/// Verifies the invitation credential of a presentation that authorises the presentation's holder to register a DID.
+///
+/// # Arguments
+///
+/// * `config` - Server configuration
+/// * `owners` - DIDs of the owners that may issue invitations
+/// * `id` - path to the identity that's registered
+/// * `presentation` - presentation that contains the invitation and has been signed by the invitee
+/// * returns `None` if the presentation contains no invitation, otherwise the verified invitation or an error
+pub async fn verify_invitation(
+    config: &rocket::State<Config>,
+    owners: &[String],
+    id: &Path,
+    presentation: &Presentation,
+) -> Result<Option<Invitation>, DIDError> {
+    let Some(credential) = find_invitation(presentation) else {
+        return Ok(None);
+    };
+    let invalid = |message: &str| DIDError::InvitationInvalid(message.to_string());
+    let issuer = credential
+        .issuer
+        .as_ref()
+        .map(|issuer| issuer.get_id())
+        .filter(|issuer| owners.contains(issuer))
+        .ok_or_else(|| invalid("Invitation hasn't been issued by an owner"))?;
+    // the id is signed by the issuer, whereas the serialisation of the credential can be changed by the invitee
+    let invitation_id = credential
+        .id
+        .as_ref()
+        .map(|id| digest(format!("{} {}", issuer, id.as_str())))
+        .ok_or_else(|| invalid("Invitation has no id"))?;
+    if !has_invitation_context(credential) {
+        return Err(invalid("Invitation lacks the invitation context"));
+    }
+    compare_date(&credential.issuance_date, Ordering::Less, Utc::now()).ok_or_else(|| {
+        invalid("Invitation has been issued in the future or has no issuance date")
+    })?;
+    compare_date(&credential.expiration_date, Ordering::Greater, Utc::now())
+        .ok_or_else(|| invalid("Invitation has expired or has no expiration date"))?;
+
+    // the proof must have been created with one of the issuer's assertion methods
+    let resolver = config.get_resolver();
+    let result = credential
+        .verify(
+            Some(LinkedDataProofOptions {
+                proof_purpose: Some(ProofPurpose::AssertionMethod),
+                ..LinkedDataProofOptions::default()
+            }),
+            &resolver,
+            &mut ContextLoader::default(),
+        )
+        .await;
+    if !result.errors.is_empty() {
+        return Err(invalid("Invitation invalid, verification failed"));
+    }
+
+    let subject = credential
+        .credential_subject
+        .first()
+        .ok_or_else(|| invalid("Invitation has no subject"))?;
+    let invitee = subject
+        .id
+        .as_ref()
+        .map(|id| id.to_string())
+        .ok_or_else(|| invalid("Invitation has no invitee"))?;
+    let restriction = |name: &str| {
+        subject
+            .property_set
+            .as_ref()
+            .and_then(|properties| properties.get(name))
+            .and_then(Value::as_str)
+    };
+    if !authorizes(restriction("path"), restriction("pathPrefix"), id) {
+        return Err(invalid(
+            "Invitation doesn't authorise the registration at this path",
+        ));
+    }
+    let verification_method = verify_issuer(
+        config,
+        &invitee,
+        VerificationRelationship::Authentication,
+        presentation,
+    )
+    .await
+    .map_err(|_| invalid("Presentation hasn't been signed by the invitee"))?;
+    Ok(Some(Invitation {
+        id: invitation_id,
+        issuer,
+        invitee,
+        verification_method,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::invitation::*;
+    use ssi::did::Document;
+    use std::path::PathBuf;
+
+    fn invitation(id: &str) -> Invitation {
+        Invitation {
+            id: id.to_string(),
+            issuer: "did:key:owner".to_string(),
+            invitee: "did:key:invitee".to_string(),
+            verification_method: "did:key:invitee#key".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_authorizes() {
+        let id = Path::new("employees/alice/did.json");
+        assert!(
+            authorizes(Some("employees/alice"), None, id),
+            "When the invitation's path matches, then the registration is authorised"
+        );
+        assert!(
+            !authorizes(Some("employees/bob"), None, id),
+            "When the invitation's path differs, then the registration isn't authorised"
+        );
+        assert!(
+            authorizes(None, Some("employees/"), id),
+            "When the id is below the invitation's path prefix, then the registration is authorised"
+        );
+        assert!(
+            !authorizes(None, Some("employees"), Path::new("employees/did.json")),
+            "When the id equals the invitation's path prefix, then the registration isn't authorised"
+        );
+        assert!(
+            !authorizes(None, Some("employee"), id),
+            "When the path prefix only matches part of a segment, then the registration isn't authorised"
+        );
+        assert!(
+            !authorizes(None, None, id),
+            "When the invitation has no restriction, then the registration isn't authorised"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_redeem() {
+        let store = crate::store::mem::MemStore::default();
+        let result = redeem(&store, &invitation("a"), "did:web:localhost:alice", async {
+            Err::<(), DIDError>(DIDError::DIDExists("DID already exists".to_string()))
+        })
+        .await;
+        assert!(
+            result.is_err(),
+            "When the operation fails, then its error is returned"
+        );
+        redeem(&store, &invitation("a"), "did:web:localhost:alice", async {
+            Ok(())
+        })
+        .await
+        .expect("When the operation has failed, then the invitation is released");
+        assert!(
+            matches!(
+                redeem(&store, &invitation("a"), "did:web:localhost:bob", async {
+                    Ok(())
+                })
+                .await,
+                Err(DIDError::InvitationInvalid(_))
+            ),
+            "When an invitation is redeemed twice, then it's rejected"
+        );
+    }
+
+    /// Store that keeps the default implementations of `reserve` and `release`.
+    struct UnreservedStore(crate::store::mem::MemStore);
+
+    #[async_trait::async_trait]
+    impl DIDWebStore for UnreservedStore {
+        async fn get(&self, id: &Path) -> Result<Document, DIDError> {
+            self.0.get(id).await
+        }
+        async fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+            self.0.create(id, doc).await
+        }
+        async fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+            self.0.update(id, doc).await
+        }
+        async fn remove(&self, id: &Path) -> Result<Document, DIDError> {
+            self.0.remove(id).await
+        }
+        async fn list(&self) -> Result<Vec<PathBuf>, DIDError> {
+            self.0.list().await
+        }
+        async fn check(&self) -> Result<(), DIDError> {
+            self.0.check().await
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_redeem_unsupported() {
+        let store = UnreservedStore(crate::store::mem::MemStore::default());
+        let mut performed = false;
+        let result = redeem(&store, &invitation("a"), "did:web:localhost:alice", async {
+            performed = true;
+            Ok(())
+        })
+        .await;
+        assert!(
+            matches!(result, Err(DIDError::StoreUnavailable(_))),
+            "When the store doesn't support reservations, then the redemption fails"
+        );
+        assert!(
+            !performed,
+            "When the store doesn't support reservations, then the operation isn't performed"
+        );
+        crate::store::conformance::check_reserve(&store).await;
+    }
+}
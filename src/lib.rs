@@ -0,0 +1,570 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+//! did-web-server hosts DIDs of the did:web method. The library mounts the server's routes into existing Rocket
+//! applications, see [`mount`], and accepts custom storage backends, see [`DIDWebStore`] and [`StoreRegistry`].
+//!
+//! # Stable API
+//!
+//! The items exported by this crate are its stable API and follow semantic versioning. Besides mounting the server,
+//! applications use them to work with did:web DIDs and to verify presentations the same way the server does:
+//!
+//! - [`DIDWeb`] and [`DIDHost`] - parse, inspect and format did:web DIDs
+//! - [`ProofParameters`] - parameters that a presentation must have been created with
+//! - [`verify_presentation`] - verify a presentation and the credential that it contains
+//! - [`did_methods`] - DID methods that the server resolves itself, i.e. did:key, did:jwk and did:web
+//!
+//! ```
+//! use did_web_server::{DIDHost, DIDWeb};
+//!
+//! let did: DIDWeb = "did:web:example.com%3A8443:alice".parse().unwrap();
+//! assert_eq!(did.host(), &DIDHost::Domain("example.com".to_string()));
+//! assert_eq!(did.port(), 8443);
+//! assert_eq!(did.path().to_str(), Some("alice/did.json"));
+//! assert_eq!(did.to_string(), "did:web:example.com%3A8443:alice");
+//! ```
+//!
+//! Routes of an application verify presentations against their own proof parameters:
+//!
+//! ```no_run
+//! use did_web_server::{verify_presentation, Config, DIDError, DIDWeb, ProofParameters};
+//! use rocket::serde::json::Json;
+//! use rocket::State;
+//! use ssi::vc::{Presentation, ProofPurpose};
+//!
+//! /// Accepts presentations by alice that contain her DID document and have been created for the challenge `login`.
+//! #[rocket::post("/login", data = "<presentation>")]
+//! async fn login(
+//!     config: &State<Config>,
+//!     presentation: Json<Presentation>,
+//! ) -> Result<String, DIDError> {
+//!     let did: DIDWeb = "did:web:example.com:alice".parse()?;
+//!     let proof_parameters = ProofParameters {
+//!         did: did.to_string(),
+//!         challenge: Some("login".to_string()),
+//!         domain: did.host().to_string(),
+//!         proof_purpose: ProofPurpose::Authentication,
+//!     };
+//!     verify_presentation(config, proof_parameters, presentation).await?;
+//!     Ok(format!("Welcome {}", did))
+//! }
+//!
+//! # fn main() {
+//! #     let _ = rocket::routes![login];
+//! # }
+//! ```
+
+// Fail build if feature is requsted, see https://www.reddit.com/r/rust/comments/8oz7md/make_cargo_fail_on_warning/
+#![cfg_attr(feature = "fail-on-warnings", deny(warnings))]
+
+mod audit;
+mod cache;
+mod changes;
+mod config;
+mod content_types;
+mod did;
+mod error;
+mod health;
+mod invitation;
+mod logging;
+mod metrics;
+mod openapi;
+mod reload;
+mod replica;
+mod resolution;
+mod resolver;
+mod store;
+mod tenant;
+mod utils;
+mod webhooks;
+
+pub use crate::config::Config;
+pub use crate::did::{DIDHost, DIDWeb, ProofParameters};
+pub use crate::error::DIDError;
+pub use crate::resolver::did_methods;
+pub use crate::store::{conformance, DIDWebStore, StoreRegistry};
+pub use crate::utils::verify_presentation;
+
+use crate::audit::{AuditRecord, Operation, Source};
+use crate::content_types::DIDContentTypes;
+use crate::error::CustomStatus;
+use crate::invitation::{redeem, verify_invitation};
+use crate::logging::RequestId;
+use crate::tenant::Tenant;
+use crate::utils::{verify_issuer, verify_owner};
+use rocket::http::ContentType;
+use rocket::serde::json::Json;
+use rocket::{Build, Rocket};
+use ssi::did::{Document, VerificationRelationship};
+use ssi::vc::Presentation;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{Instrument, Span};
+
+#[cfg(test)]
+mod test;
+#[cfg(test)]
+mod test_resolver;
+
+#[macro_use]
+extern crate rocket;
+
+/// Retrieve DID document proof parameters.
+///
+/// - `config` Global Rocket configuration
+/// - `tenant` - tenant that matches the request's host
+/// - `id` - requested id, e.g. `alice`
+/// - returns ProofParameters
+#[allow(clippy::unused_unit)]
+#[get("/<id..>?proofParameters")]
+async fn get_proof_parameters(
+    config: &rocket::State<Config>,
+    tenant: Tenant,
+    id: PathBuf,
+) -> Result<Json<ProofParameters>, DIDError> {
+    ProofParameters::new(config, &tenant, &id).await.map(Json)
+}
+
+#[allow(clippy::unused_unit)]
+#[get("/.well-known/did.json?proofParameters")]
+async fn get_proof_parameters_wellknown(
+    config: &rocket::State<Config>,
+    tenant: Tenant,
+) -> Result<Json<ProofParameters>, DIDError> {
+    get_proof_parameters(config, tenant, PathBuf::from("/.well-known/did.json")).await
+}
+
+/// Retrieve DID document.
+///
+/// - `config` Global Rocket configuration
+/// - `request_id` - ID of the request
+/// - `tenant` - tenant that matches the request's host
+/// - `id` - requested id, e.g. `alice`
+/// - returns JSON encoded DID document
+#[get("/<id..>")]
+async fn get(
+    config: &rocket::State<Config>,
+    request_id: RequestId,
+    tenant: Tenant,
+    id: PathBuf,
+) -> (ContentType, Result<Json<Document>, DIDError>) {
+    // TODO: verify that the DID in the doc is equal to the DID that has been requested - bail out otherwise
+    // TODO: maybe return json_api for errors?
+    let result = config.store.get(&tenant.store_id(&id)).await.map_err(|e| {
+        tracing::debug!(
+            request_id = %request_id,
+            id = %id.display(),
+            error = e.name(),
+            "DID document not retrieved"
+        );
+        e
+    });
+    let content_type = match &result {
+        Ok(_diddoc) => {
+            // if diddoc.context {
+            DIDContentTypes::DID_LD_JSON
+            // } else {
+            // DIDContentTypes::DID_JSON
+            // }
+        }
+        Err(_) => ContentType::JSON,
+    };
+    (content_type, result.map(Json))
+}
+
+// Required to explicitly allow access to a path starting with "."
+#[get("/.well-known/did.json")]
+async fn get_wellknown(
+    config: &rocket::State<Config>,
+    request_id: RequestId,
+    tenant: Tenant,
+) -> (ContentType, Result<Json<Document>, DIDError>) {
+    get(
+        config,
+        request_id,
+        tenant,
+        PathBuf::from("/.well-known/did.json"),
+    )
+    .await
+}
+
+/// Writes a DID document to the store and records the change in the change feed. The feed is locked during the write,
+/// so that concurrent changes are recorded in the order in which they've been written. If the change can't be
+/// recorded, the write is reverted and the error is returned, so that consumers of the feed never miss a change.
+///
+/// - `config` - the server configuration.
+/// - `operation` - `create`, `update` or `delete`.
+/// - `did` - DID whose document is written.
+/// - `store_id` - id of the DID document in the store.
+/// - `document` - new DID document, `None` if the document is removed.
+/// - returns the previous DID document, if there is one
+async fn write(
+    config: &Config,
+    operation: Operation,
+    did: &str,
+    store_id: &Path,
+    document: Option<Document>,
+) -> Result<Option<Document>, DIDError> {
+    let store = config.store.as_ref();
+    let publisher = config.changes.lock().await;
+    let previous = match (operation, &document) {
+        (Operation::Create, Some(document)) => {
+            store.create(store_id, document.clone()).await?;
+            None
+        }
+        (Operation::Update, Some(document)) => {
+            Some(store.update(store_id, document.clone()).await?)
+        }
+        _ => Some(store.remove(store_id).await?),
+    };
+    if let Err(e) = publisher
+        .publish(store, operation, did, store_id, document)
+        .await
+    {
+        let reverted = match previous.clone() {
+            None => store.remove(store_id).await.map(|_| ()),
+            Some(previous) if operation == Operation::Update => {
+                store.update(store_id, previous).await.map(|_| ())
+            }
+            Some(previous) => store.create(store_id, previous).await.map(|_| ()),
+        };
+        match reverted {
+            Ok(()) => tracing::error!(
+                error = e.name(),
+                reason = e.message(),
+                "change not recorded in change feed, write reverted"
+            ),
+            Err(revert) => tracing::error!(
+                error = e.name(),
+                reason = e.message(),
+                revert_error = revert.name(),
+                revert_reason = revert.message(),
+                "change not recorded in change feed and write not reverted"
+            ),
+        }
+        return Err(e);
+    }
+    Ok(previous)
+}
+
+/// Creates a DID document at the given position. The DID Document's id must match the DID of
+/// computed DID at this position otherwise the DID wouldn't be manageable. Only the tenant's owners are allowed to
+/// create / register new DID documents, or holders of an invitation that an owner has issued, see [`invitation`].
+///
+/// * `config` - the server configuration.
+/// * `request_id` - ID of the request.
+/// * `source` - source of the request.
+/// * `tenant` - tenant that matches the request's host.
+/// * `id` - path to the identity.
+/// * `presentation` - verifable presentation that holds the updated DID Document.
+/// - returns DID as JSON string
+///
+/// # TODO
+///
+/// * Implement authentication via some fitting method, JWT or actual signed requests via a private
+///   key
+/// * Support subfolder so that the DIDs don't only have to live in the top-level folder
+/// * implement .well-known support
+#[post("/<id..>", data = "<presentation>")]
+// #[post("/<id..>", data = "<presentation>")]
+async fn create(
+    config: &rocket::State<Config>,
+    request_id: RequestId,
+    source: Source,
+    tenant: Tenant,
+    id: PathBuf,
+    presentation: Json<Presentation>,
+) -> Result<CustomStatus<Json<ProofParameters>>, DIDError> {
+    let span = logging::operation_span(&request_id, "create", &id);
+    let mut audit = AuditRecord::new(Operation::Create, &request_id, &source, &id, &presentation);
+    let result = async {
+        config.check_writable()?;
+        logging::log_presentation(&presentation);
+        // only the tenant's owners and the holders of their invitations are allowed to create DIDs
+        let (controlling_did, verification_method, invitation) =
+            match verify_owner(config, &tenant.owners, &presentation).await {
+                Ok((owner, verification_method)) => (owner, verification_method, None),
+                Err(e) => {
+                    match verify_invitation(config, &tenant.owners, &id, &presentation).await? {
+                        Some(invitation) => (
+                            invitation.invitee.clone(),
+                            invitation.verification_method.clone(),
+                            Some(invitation),
+                        ),
+                        None => return Err(e),
+                    }
+                }
+            };
+        Span::current().record("verification_method", verification_method.as_str());
+        audit.authorizing_did = Some(controlling_did);
+        audit.verification_method = Some(verification_method);
+        let proof_parameters = ProofParameters::new(config, &tenant, &id).await?;
+        let did = proof_parameters.did.to_string();
+        Span::current().record("did", did.as_str());
+        audit.did = Some(did.clone());
+        audit.challenge = proof_parameters.challenge.clone();
+        let (_result, _vc, did_doc) =
+            verify_presentation(config, proof_parameters, presentation).await?;
+        tracing::debug!("verified presentation");
+
+        // INFO: unsure how to easily convert a CredentialSubject into a Document. Via json encoding? - not beautiful!!
+        let did_doc = serde_json::to_string(&did_doc)
+            .ok()
+            .and_then(|s| serde_json::from_str::<Document>(&s).ok());
+        match did_doc {
+            Some(document) => {
+                audit.new_document = Some(document.clone());
+                let store_id = tenant.store_id(&id);
+                let create = write(config, Operation::Create, &did, &store_id, Some(document));
+                // an invitation authorises a single registration
+                match &invitation {
+                    Some(invitation) => {
+                        redeem(config.store.as_ref(), invitation, &did, create).await?
+                    }
+                    None => create.await?,
+                };
+                ProofParameters::new(config, &tenant, &id)
+                    .await
+                    .map(Json)
+                    .map(CustomStatus::Created)
+            }
+            None => Err(DIDError::DIDDocMissing("DID document invalid".to_string())),
+        }
+    }
+    .instrument(span.clone())
+    .await;
+    span.in_scope(|| {
+        logging::log_outcome(&result);
+        if let (Ok(_), Some(did)) = (&result, &audit.did) {
+            config.resolver_cache.invalidate(did);
+        }
+    });
+    config
+        .audit_log
+        .record_or_log(audit, &result)
+        .instrument(span)
+        .await;
+    result
+}
+
+/// Updates a DID Document if the identity is authorized to perform this operation.
+///
+/// * `config` - the server configuration.
+/// * `request_id` - ID of the request.
+/// * `source` - source of the request.
+/// * `tenant` - tenant that matches the request's host.
+/// * `id` - path to the identity.
+/// * `presentation` - verifable presentation that holds the updated DID Document.
+///
+/// # TODO
+///
+/// * [ ] implement .well-known support
+#[put("/<id..>", data = "<presentation>")]
+async fn update(
+    config: &rocket::State<Config>,
+    request_id: RequestId,
+    source: Source,
+    tenant: Tenant,
+    id: PathBuf,
+    presentation: Json<Presentation>,
+) -> Result<Json<ProofParameters>, DIDError> {
+    let span = logging::operation_span(&request_id, "update", &id);
+    let mut audit = AuditRecord::new(Operation::Update, &request_id, &source, &id, &presentation);
+    let result = async {
+        config.check_writable()?;
+        logging::log_presentation(&presentation);
+        // The user is the only one allowed to update the personal DID document
+        let controlling_did = DIDWeb::from_tenant(&tenant, &id)?.to_string();
+        Span::current().record("did", controlling_did.as_str());
+        audit.did = Some(controlling_did.to_string());
+        audit.authorizing_did = Some(controlling_did.to_string());
+        let verification_method = verify_issuer(
+            config,
+            &controlling_did,
+            VerificationRelationship::Authentication,
+            &presentation,
+        )
+        .await?;
+        Span::current().record("verification_method", verification_method.as_str());
+        audit.verification_method = Some(verification_method);
+
+        // retrieve proof parameters required to verify the correctness of the presentation
+        let proof_parameters = ProofParameters::new(config, &tenant, &id).await?;
+        audit.challenge = proof_parameters.challenge.clone();
+        audit.old_document = config.store.get(&tenant.store_id(&id)).await.ok();
+        let (_result, _vc, did_doc) =
+            verify_presentation(config, proof_parameters, presentation).await?;
+        tracing::debug!("verified presentation");
+
+        // INFO: unsure how to easily convert a CredentialSubject into a Document. Via json encoding? - not beautiful!!
+        let did_doc = serde_json::to_string(&did_doc)
+            .ok()
+            .and_then(|s| serde_json::from_str::<Document>(&s).ok());
+        match did_doc {
+            Some(document) => {
+                audit.new_document = Some(document.clone());
+                write(
+                    config,
+                    Operation::Update,
+                    &controlling_did,
+                    &tenant.store_id(&id),
+                    Some(document),
+                )
+                .await?;
+                ProofParameters::new(config, &tenant, &id).await.map(Json)
+            }
+            None => Err(DIDError::DIDDocMissing("DID document invalid".to_string())),
+        }
+    }
+    .instrument(span.clone())
+    .await;
+    span.in_scope(|| {
+        logging::log_outcome(&result);
+        if let (Ok(_), Some(did)) = (&result, &audit.did) {
+            config.resolver_cache.invalidate(did);
+        }
+    });
+    config
+        .audit_log
+        .record_or_log(audit, &result)
+        .instrument(span)
+        .await;
+    result
+}
+
+/// Deletes a DID Document if the identity is authorized to perform this operation. Currently, only the owner of the
+/// tenant is allowed to delete DID Documents.
+///
+/// # Arguments
+///
+/// * `config` - the server configuration.
+/// * `request_id` - ID of the request.
+/// * `source` - source of the request.
+/// * `tenant` - tenant that matches the request's host.
+/// * `id` - path to the identity.
+/// * `presentation` - verifable presentation that holds the updated DID Document.
+#[delete("/<id..>", data = "<presentation>")]
+async fn delete(
+    config: &rocket::State<Config>,
+    request_id: RequestId,
+    source: Source,
+    tenant: Tenant,
+    id: PathBuf,
+    presentation: Json<Presentation>,
+) -> Result<Json<ProofParameters>, DIDError> {
+    let span = logging::operation_span(&request_id, "delete", &id);
+    let mut audit = AuditRecord::new(Operation::Delete, &request_id, &source, &id, &presentation);
+    let result = async {
+        config.check_writable()?;
+        logging::log_presentation(&presentation);
+        // only the tenant's owners are allowed to create DIDs
+        let (controlling_did, verification_method) =
+            verify_owner(config, &tenant.owners, &presentation).await?;
+        Span::current().record("verification_method", verification_method.as_str());
+        audit.authorizing_did = Some(controlling_did);
+        audit.verification_method = Some(verification_method);
+        let proof_parameters = ProofParameters::new(config, &tenant, &id).await?;
+        let did = proof_parameters.did.to_string();
+        Span::current().record("did", did.as_str());
+        audit.did = Some(did.clone());
+        audit.challenge = proof_parameters.challenge.clone();
+        audit.old_document = config.store.get(&tenant.store_id(&id)).await.ok();
+        verify_presentation(config, proof_parameters, presentation).await?;
+        write(config, Operation::Delete, &did, &tenant.store_id(&id), None).await?;
+        ProofParameters::new(config, &tenant, &id).await.map(Json)
+    }
+    .instrument(span.clone())
+    .await;
+    span.in_scope(|| {
+        logging::log_outcome(&result);
+        if let (Ok(_), Some(did)) = (&result, &audit.did) {
+            config.resolver_cache.invalidate(did);
+        }
+    });
+    config
+        .audit_log
+        .record_or_log(audit, &result)
+        .instrument(span)
+        .await;
+    result
+}
+
+/// Routes of the server. Use [`mount`] to mount them, which also manages the configuration and attaches the
+/// required fairings.
+///
+/// - `config` - server configuration, the API explorer is only included if it's enabled
+pub fn routes(config: &Config) -> Vec<rocket::Route> {
+    let mut routes = routes![
+        audit::export_audit_log,
+        audit::get_audit_log_proof_parameters,
+        changes::get_changes,
+        changes::stream_changes,
+        create,
+        delete,
+        get,
+        get_proof_parameters,
+        get_proof_parameters_wellknown,
+        get_wellknown,
+        health::healthz,
+        health::readyz,
+        health::version,
+        metrics::get_metrics,
+        openapi::get_openapi,
+        replica::get_snapshot,
+        resolution::resolve,
+        update,
+    ];
+    if config.api_explorer {
+        routes.extend(routes![openapi::get_openapi_explorer]);
+    }
+    routes
+}
+
+/// Mounts the server's routes into a Rocket instance, e.g. of an existing application. Both the routes and the OpenAPI
+/// specification, i.e. `<base>/.well-known/openapi.json`, are mounted under `base`.
+///
+/// - `rocket` - Rocket instance that the routes are mounted into
+/// - `base` - prefix of the routes, e.g. `/dids`. DIDs are computed from `external_path`, which must correspond to
+///   the prefix, e.g. `dids`.
+/// - `config` - server configuration that's placed into Rocket's managed state
+pub fn mount(rocket: Rocket<Build>, base: &str, config: Config) -> Rocket<Build> {
+    let routes = routes(&config);
+    // record the latency of all store operations
+    let config = Config {
+        store: Arc::new(metrics::MeteredStore::new(config.store)),
+        ..config
+    };
+    rocket
+        .manage(config)
+        .attach(logging::RequestLogger)
+        .attach(metrics::RequestCounter)
+        .attach(openapi::fairing(base, &routes))
+        .attach(replica::ReplicaSync)
+        .attach(webhooks::WebhookDelivery)
+        .mount(base, routes)
+}
+
+/// Builds the server's Rocket instance. Rocket's own settings, e.g. the port, are read from the configuration file and
+/// environment variables, see [`config::figment`]. Tests use it to start the server with a configuration that isn't
+/// derived from environment variables.
+pub(crate) fn build_rocket(config: Config) -> Rocket<Build> {
+    mount(rocket::custom(config::figment()), "/", config)
+}
+
+/// Builds the standalone server as it's started by the `did-web-server` binary. The configuration is loaded from the
+/// configuration file and environment variables, logging is initialized, and the startup check of the owners' DIDs and
+/// the reloading of the configuration are attached.
+///
+/// - returns the Rocket instance or an error if the configuration is invalid
+pub fn server() -> Result<Rocket<Build>, DIDError> {
+    let figment = config::figment();
+    let config = Config::load(&figment)?;
+    logging::init(
+        config.log_format,
+        &figment
+            .extract_inner::<String>("log_level")
+            .unwrap_or_else(|_| "normal".to_string()),
+    );
+    Ok(build_rocket(config)
+        .attach(health::OwnerValidator)
+        .attach(reload::ConfigReloader))
+}
@@ -0,0 +1,266 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use lazy_static::lazy_static;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, Response};
+use serde_json::Value;
+use ssi::vc::Presentation;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::Empty;
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+use crate::error::DIDError;
+
+/// Header that carries the request ID.
+pub static REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Keys of proof properties whose values are never logged.
+static REDACTED_KEYS: &[&str] = &["proofValue", "jws", "signatureValue"];
+
+lazy_static! {
+    /// Prefix of generated request IDs, unique per process start.
+    static ref REQUEST_ID_PREFIX: String = format!(
+        "{:x}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    );
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Output format of log messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable log messages.
+    Text,
+    /// One JSON object per log message.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = DIDError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(DIDError::UnknownLogFormat(format!(
+                "Log format is unknown: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Initializes structured logging. Log messages emitted by Rocket are forwarded to the same output.
+///
+/// - `format` - output format of the log messages.
+/// - `log_level` - Rocket's log level, i.e. `off`, `critical`, `normal` or `debug`. It's only used if no filter is
+///   set via `DWS_LOG_FILTER`, e.g. `did_web_server=debug,rocket=warn`.
+pub fn init(format: LogFormat, log_level: &str) {
+    let filter = EnvFilter::try_from_env("DWS_LOG_FILTER").unwrap_or_else(|_| {
+        EnvFilter::new(match log_level {
+            "off" => "off",
+            "critical" => "warn",
+            "debug" => "debug",
+            _ => "info",
+        })
+    });
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    // initialization fails if logging has been initialized before, e.g. in tests
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+}
+
+/// Creates the span that all log messages of a mutating operation are recorded in. The fields `did` and
+/// `verification_method` are recorded once they're known.
+///
+/// - `request_id` - ID of the request.
+/// - `operation` - name of the operation, e.g. `create`.
+/// - `id` - requested id, e.g. `alice/did.json`.
+pub fn operation_span(request_id: &RequestId, operation: &'static str, id: &Path) -> Span {
+    tracing::info_span!(
+        "operation",
+        request_id = %request_id,
+        operation,
+        id = %id.display(),
+        did = Empty,
+        verification_method = Empty,
+    )
+}
+
+/// Logs the outcome of an operation in the current span.
+pub fn log_outcome<T>(result: &Result<T, DIDError>) {
+    match result {
+        Ok(_) => tracing::info!(outcome = "success", "operation succeeded"),
+        Err(e) => tracing::warn!(
+            outcome = "failure",
+            error = e.name(),
+            reason = e.message(),
+            "operation failed"
+        ),
+    }
+}
+
+/// Logs a received presentation with redacted proof values.
+pub fn log_presentation(presentation: &Presentation) {
+    if tracing::enabled!(tracing::Level::DEBUG) {
+        let presentation = serde_json::to_value(presentation)
+            .map(redact)
+            .unwrap_or_default();
+        tracing::debug!(%presentation, "received presentation");
+    }
+}
+
+/// Replaces all proof values in a JSON value, e.g. `proofValue` or `jws`, with `[REDACTED]`.
+pub fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    if REDACTED_KEYS.contains(&key.as_str()) {
+                        (key, Value::String("[REDACTED]".to_string()))
+                    } else {
+                        (key, redact(value))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(redact).collect()),
+        value => value,
+    }
+}
+
+/// ID of a request. The ID is taken from the `X-Request-Id` header if it's present and valid. Otherwise, a new ID is
+/// generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Uses `header` as request ID if it's a valid ID, otherwise generates a new ID.
+    pub fn from_header(header: Option<&str>) -> RequestId {
+        match header {
+            Some(id)
+                if !id.is_empty()
+                    && id.len() <= 128
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)) =>
+            {
+                RequestId(id.to_string())
+            }
+            _ => RequestId(format!(
+                "{}-{}",
+                *REQUEST_ID_PREFIX,
+                NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+            )),
+        }
+    }
+
+    fn from_request(request: &Request<'_>) -> RequestId {
+        request
+            .local_cache(|| RequestId::from_header(request.headers().get_one(REQUEST_ID_HEADER)))
+            .clone()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId::from_request(request))
+    }
+}
+
+/// Fairing that logs every handled request and returns the request ID in the `X-Request-Id` header.
+pub struct RequestLogger;
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request logger",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let request_id = RequestId::from_request(request);
+        tracing::info!(
+            request_id = %request_id,
+            method = %request.method(),
+            path = %request.uri().path(),
+            status = response.status().code,
+            "request handled"
+        );
+        response.set_header(Header::new(REQUEST_ID_HEADER, request_id.0));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::logging::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact() {
+        let presentation = json!({
+            "type": "VerifiablePresentation",
+            "verifiableCredential": [{
+                "credentialSubject": { "id": "did:web:example.com" },
+                "proof": { "proofValue": "z3FXQ", "verificationMethod": "did:key:z6Mk#z6Mk" }
+            }],
+            "proof": { "jws": "eyJhbGciOiJFZERTQSJ9..sig" }
+        });
+        let result = redact(presentation);
+        assert_eq!(
+            result["verifiableCredential"][0]["proof"]["proofValue"], "[REDACTED]",
+            "When a nested proof contains a proof value, then the value is redacted"
+        );
+        assert_eq!(
+            result["proof"]["jws"], "[REDACTED]",
+            "When a proof contains a JWS, then the JWS is redacted"
+        );
+        assert_eq!(
+            result["verifiableCredential"][0]["proof"]["verificationMethod"], "did:key:z6Mk#z6Mk",
+            "When a proof contains other properties, then they're kept"
+        );
+    }
+
+    #[test]
+    fn test_request_id() {
+        assert_eq!(
+            RequestId::from_header(Some("abc-123")),
+            RequestId("abc-123".to_string()),
+            "When a valid request ID is provided, then it's used"
+        );
+        assert_ne!(
+            RequestId::from_header(Some("abc\n123")),
+            RequestId("abc\n123".to_string()),
+            "When an invalid request ID is provided, then a new ID is generated"
+        );
+        assert_ne!(
+            RequestId::from_header(None),
+            RequestId::from_header(None),
+            "When no request ID is provided, then unique IDs are generated"
+        );
+    }
+}
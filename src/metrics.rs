@@ -0,0 +1,237 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    Encoder, Histogram, HistogramVec, IntCounterVec, IntGauge, TextEncoder,
+};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, Response};
+use ssi::did::Document;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::error::DIDError;
+use crate::replica::bearer_token_matches;
+use crate::store::DIDWebStore;
+
+/// Duration that the number of stored DIDs is cached for, so that scrapes don't list the whole store.
+static STORED_DIDS_TTL: Duration = Duration::from_secs(300);
+
+lazy_static! {
+    /// Number of handled requests per route, method and response status.
+    pub static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "dws_http_requests_total",
+        "Number of handled HTTP requests",
+        &["route", "method", "status"]
+    )
+    .unwrap();
+    /// Number of errors returned to clients per error.
+    pub static ref ERRORS: IntCounterVec = register_int_counter_vec!(
+        "dws_errors_total",
+        "Number of errors returned to clients",
+        &["error"]
+    )
+    .unwrap();
+    /// Latency of store operations per operation and result.
+    pub static ref STORE_LATENCY: HistogramVec = register_histogram_vec!(
+        "dws_store_operation_duration_seconds",
+        "Latency of store operations",
+        &["operation", "result"]
+    )
+    .unwrap();
+    /// Duration of presentation verifications, including the resolution of remote DIDs.
+    pub static ref PRESENTATION_VERIFICATION: Histogram = register_histogram!(
+        "dws_presentation_verification_duration_seconds",
+        "Duration of presentation verifications including DID resolution"
+    )
+    .unwrap();
+    /// Number of lookups in the resolver cache per result, `hit` or `miss`.
+    pub static ref RESOLVER_CACHE: IntCounterVec = register_int_counter_vec!(
+        "dws_resolver_cache_lookups_total",
+        "Number of lookups in the resolver cache",
+        &["result"]
+    )
+    .unwrap();
+    /// Time of the last successful synchronisation of a replica in seconds since the epoch.
+    pub static ref REPLICA_LAST_SYNC: IntGauge = register_int_gauge!(
+        "dws_replica_last_sync_timestamp_seconds",
+        "Time of the last successful synchronisation of the replica"
+    )
+    .unwrap();
+    /// Number of webhook delivery attempts per result, `delivered`, `retry` or `failed`.
+    pub static ref WEBHOOK_DELIVERIES: IntCounterVec = register_int_counter_vec!(
+        "dws_webhook_deliveries_total",
+        "Number of webhook delivery attempts",
+        &["result"]
+    )
+    .unwrap();
+    /// Number of DIDs in the store.
+    pub static ref STORED_DIDS: IntGauge =
+        register_int_gauge!("dws_stored_dids", "Number of DIDs in the store").unwrap();
+    /// Time that the stored DIDs have last been counted.
+    static ref STORED_DIDS_COUNTED: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// Request guard that admits Prometheus if it presents the metrics token. Metrics are public if no token is set.
+pub struct MetricsReader;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetricsReader {
+    type Error = DIDError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request
+            .rocket()
+            .state::<Config>()
+            .and_then(|config| config.metrics_token.as_deref());
+        match bearer_token_matches(request, token) {
+            None | Some(true) => Outcome::Success(MetricsReader),
+            Some(false) => {
+                let e = DIDError::MetricsDenied("Metrics token invalid".to_string());
+                Outcome::Error((e.status(), e))
+            }
+        }
+    }
+}
+
+/// Counts the stored DIDs if the last count is older than [`STORED_DIDS_TTL`]. Listing the store is expensive for
+/// remote backends, so the count is only refreshed occasionally and kept up to date by [`MeteredStore`] in between.
+///
+/// - `store` - store whose DIDs are counted
+async fn count_stored_dids(store: &(dyn DIDWebStore + Sync + Send)) -> Result<(), DIDError> {
+    let expired = STORED_DIDS_COUNTED
+        .lock()
+        .unwrap()
+        .map_or(true, |counted| counted.elapsed() >= STORED_DIDS_TTL);
+    if expired {
+        STORED_DIDS.set(store.list().await?.len() as i64);
+        *STORED_DIDS_COUNTED.lock().unwrap() = Some(Instant::now());
+    }
+    Ok(())
+}
+
+/// Retrieve metrics in the Prometheus text format.
+///
+/// - `config` Global Rocket configuration
+/// - returns all registered metrics. Requires the metrics token if it's set.
+#[get("/metrics")]
+pub async fn get_metrics(
+    config: &rocket::State<Config>,
+    _reader: MetricsReader,
+) -> Result<(ContentType, String), DIDError> {
+    // the number of stored DIDs is recounted periodically to correct changes made by other instances
+    count_stored_dids(config.store.as_ref()).await?;
+    let encoder = TextEncoder::new();
+    let content_type =
+        ContentType::parse_flexible(encoder.format_type()).unwrap_or(ContentType::Text);
+    encoder
+        .encode_to_string(&prometheus::gather())
+        .map(|metrics| (content_type, metrics))
+        .map_err(|e| DIDError::ContentConversion(e.to_string()))
+}
+
+/// Fairing that counts the handled requests per route and response status.
+pub struct RequestCounter;
+
+#[rocket::async_trait]
+impl Fairing for RequestCounter {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request counter",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let route = request
+            .route()
+            .and_then(|route| route.name.as_deref())
+            .unwrap_or("unmatched");
+        REQUESTS
+            .with_label_values(&[
+                route,
+                request.method().as_str(),
+                &response.status().code.to_string(),
+            ])
+            .inc();
+    }
+}
+
+/// Store that records the latency of all operations of the wrapped store.
+pub struct MeteredStore {
+    store: Arc<dyn DIDWebStore + Sync + Send>,
+}
+
+impl MeteredStore {
+    pub fn new(store: Arc<dyn DIDWebStore + Sync + Send>) -> Self {
+        MeteredStore { store }
+    }
+
+    /// Measures the duration of `op` and records it for `operation`.
+    async fn observe<T, F: Future<Output = Result<T, DIDError>>>(
+        operation: &str,
+        op: F,
+    ) -> Result<T, DIDError> {
+        let timer = std::time::Instant::now();
+        let result = op.await;
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        STORE_LATENCY
+            .with_label_values(&[operation, outcome])
+            .observe(timer.elapsed().as_secs_f64());
+        result
+    }
+}
+
+#[async_trait]
+impl DIDWebStore for MeteredStore {
+    async fn get(&self, id: &Path) -> Result<Document, DIDError> {
+        MeteredStore::observe("get", self.store.get(id)).await
+    }
+
+    async fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        let result = MeteredStore::observe("create", self.store.create(id, doc)).await;
+        if result.is_ok() {
+            STORED_DIDS.inc();
+        }
+        result
+    }
+
+    async fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        MeteredStore::observe("update", self.store.update(id, doc)).await
+    }
+
+    async fn remove(&self, id: &Path) -> Result<Document, DIDError> {
+        let result = MeteredStore::observe("remove", self.store.remove(id)).await;
+        if result.is_ok() {
+            STORED_DIDS.dec();
+        }
+        result
+    }
+
+    async fn list(&self) -> Result<Vec<PathBuf>, DIDError> {
+        MeteredStore::observe("list", self.store.list()).await
+    }
+
+    async fn check(&self) -> Result<(), DIDError> {
+        MeteredStore::observe("check", self.store.check()).await
+    }
+
+    async fn reserve(&self, key: &str, value: &str) -> Result<bool, DIDError> {
+        MeteredStore::observe("reserve", self.store.reserve(key, value)).await
+    }
+
+    async fn release(&self, key: &str) -> Result<(), DIDError> {
+        MeteredStore::observe("release", self.store.release(key)).await
+    }
+
+    async fn next_sequence(&self) -> Result<Option<u64>, DIDError> {
+        MeteredStore::observe("next_sequence", self.store.next_sequence()).await
+    }
+}
@@ -0,0 +1,741 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use rocket::fairing::AdHoc;
+use rocket::http::Method;
+use rocket::response::content::RawHtml;
+use rocket::serde::json::Json;
+use rocket::Route;
+use serde_json::{json, Map, Value};
+
+/// OpenAPI specification of the server, computed from the mounted routes at ignition.
+pub struct OpenApiSpec(pub Value);
+
+/// Fairing that computes the OpenAPI specification from the server's routes and places it into managed state.
+///
+/// Only the given routes are documented, i.e. routes of the application that embeds the server aren't part of the
+/// specification even if their names match an operation of the server.
+///
+/// - `base` - prefix that the routes are mounted under, see [`crate::mount`]
+/// - `routes` - the server's routes as returned by [`crate::routes`]
+pub fn fairing(base: &str, routes: &[Route]) -> AdHoc {
+    // mount the routes the same way Rocket::mount does, so that the paths include the prefix
+    let routes = routes
+        .iter()
+        .cloned()
+        .filter_map(|route| route.map_base(|old| format!("{}{}", base, old)).ok())
+        .collect::<Vec<Route>>();
+    AdHoc::on_ignite("OpenAPI specification", |rocket| async move {
+        let spec = spec(routes.iter());
+        rocket.manage(OpenApiSpec(spec))
+    })
+}
+
+/// Retrieve the OpenAPI specification of this server.
+#[get("/.well-known/openapi.json")]
+pub fn get_openapi(spec: &rocket::State<OpenApiSpec>) -> Json<Value> {
+    Json(spec.0.clone())
+}
+
+/// Interactive API explorer that renders the OpenAPI specification of this server. The explorer is bundled with the
+/// server, so that it works without access to third-party hosts.
+#[get("/.well-known/openapi.html")]
+pub fn get_openapi_explorer() -> RawHtml<&'static str> {
+    RawHtml(include_str!("openapi_explorer.html"))
+}
+
+/// Converts the path of a route into an OpenAPI path template, e.g. `/<id..>` into `/{id}/did.json`.
+///
+/// Trailing segment parameters are only used for DID document paths and therefore always end in `did.json`.
+pub fn path_template(route: &Route) -> String {
+    route
+        .uri
+        .path()
+        .split('/')
+        .map(
+            |segment| match segment.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+                Some(param) => match param.strip_suffix("..") {
+                    Some(param) => format!("{{{}}}/did.json", param),
+                    None => format!("{{{}}}", param),
+                },
+                None => segment.to_string(),
+            },
+        )
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Returns the OpenAPI operation that documents the route with the given name.
+pub fn operation(name: &str) -> Option<Value> {
+    let id = json!({
+        "name": "id",
+        "in": "path",
+        "required": true,
+        "schema": { "$ref": "#/components/schemas/ID" }
+    });
+    let proof_parameters = json!({
+        "name": "proofParameters",
+        "in": "query",
+        "description": "Return the proof parameters that are required for registering, updating, and deleting the DID instead of the DID document.",
+        "required": false,
+        "allowEmptyValue": true,
+        "schema": { "type": "string" }
+    });
+    let since = |description: &str| {
+        json!({
+            "name": "since",
+            "in": "query",
+            "description": description,
+            "required": false,
+            "schema": { "type": "integer", "minimum": 0 }
+        })
+    };
+    let presentation = json!({
+        "content": {
+            "application/json": {
+                "schema": { "$ref": "#/components/schemas/VerifiablePresentation" }
+            }
+        }
+    });
+    let proof_parameters_response = |description: &str| {
+        json!({
+            "description": description,
+            "content": {
+                "application/json": {
+                    "schema": { "$ref": "#/components/schemas/ProofParameters" }
+                }
+            }
+        })
+    };
+    let operation = match name {
+        "get" | "get_wellknown" => json!({
+            "tags": ["did"],
+            "operationId": if name == "get" { "resolveDID" } else { "resolveWellKnownDID" },
+            "summary": "Resolve DID",
+            "description": "Corresponds to the Read (Resolve) method specified at https://w3c-ccg.github.io/did-method-web/#read-resolve.",
+            "parameters": if name == "get" { json!([id]) } else { json!([]) },
+            "responses": {
+                "200": {
+                    "description": "Returns DID Document.",
+                    "content": {
+                        "application/did+ld+json": {
+                            "schema": { "$ref": "#/components/schemas/DIDDoc" }
+                        }
+                    }
+                },
+                "400": { "description": "Bad Request. Requested path not supported." },
+                "404": { "description": "Not Found. DID document not found on server." },
+                "500": { "description": "Internal Server Error." }
+            }
+        }),
+        "get_proof_parameters" | "get_proof_parameters_wellknown" => json!({
+            "tags": ["did"],
+            "operationId": if name == "get_proof_parameters" { "getProofParameters" } else { "getWellKnownProofParameters" },
+            "summary": "Get Proof Parameters",
+            "description": "Returns the proof parameters that are required for registering, updating, and deleting the DID.",
+            "parameters": if name == "get_proof_parameters" { json!([id, proof_parameters]) } else { json!([proof_parameters]) },
+            "responses": {
+                "200": proof_parameters_response("Returns the proof parameters."),
+                "400": { "description": "Bad Request. Requested path not supported." },
+                "500": { "description": "Internal Server Error." }
+            }
+        }),
+        "create" => json!({
+            "tags": ["did"],
+            "operationId": "registerDID",
+            "summary": "Register DID",
+            "description": "This endpoint can only be called by the owner of the service or by the holder of an invitation credential that the owner issued for this path. Corresponds to the Create (Register) method specified at https://w3c-ccg.github.io/did-method-web/#create-register.",
+            "parameters": [id],
+            "requestBody": presentation,
+            "responses": {
+                "201": proof_parameters_response("Identifier created / DID document stored."),
+                "400": { "description": "Bad Request. DID document missing or not valid." },
+                "401": { "description": "Unauthorized. Authorization failed due to missing or broken proof signature." },
+                "404": { "description": "Not Found. DID of issuer could not be resolved." },
+                "405": { "description": "Method Not Allowed. The server is a read-only replica." },
+                "409": { "description": "Conflict. DID document already exists on server." },
+                "500": { "description": "Internal Server Error." }
+            }
+        }),
+        "update" => json!({
+            "tags": ["did"],
+            "operationId": "updateDID",
+            "summary": "Update DID",
+            "description": "This endpoint can only be called by the owner of the DID. Corresponds to the Update method specified at https://w3c-ccg.github.io/did-method-web/#update.",
+            "parameters": [id],
+            "requestBody": presentation,
+            "responses": {
+                "200": proof_parameters_response("Identifier updated."),
+                "400": { "description": "Bad Request. DID document missing or not valid." },
+                "401": { "description": "Unauthorized. Authorization failed due to missing or broken proof signature." },
+                "404": { "description": "Not Found. DID of issuer could not be resolved or DID that is being updated does not exist." },
+                "405": { "description": "Method Not Allowed. The server is a read-only replica." },
+                "500": { "description": "Internal Server Error." }
+            }
+        }),
+        "delete" => json!({
+            "tags": ["did"],
+            "operationId": "deactivateDID",
+            "summary": "Deactivate DID",
+            "description": "This endpoint can only be called by the owner of the service. Corresponds to the Deactivate (Revoke) method specified at https://w3c-ccg.github.io/did-method-web/#deactivate-revoke.",
+            "parameters": [id],
+            "requestBody": presentation,
+            "responses": {
+                "200": proof_parameters_response("Identifier deactivated."),
+                "400": { "description": "Bad Request. Requested path not supported." },
+                "401": { "description": "Unauthorized. Authorization failed due to missing or broken proof signature." },
+                "404": { "description": "Not Found. DID of issuer could not be resolved or DID that is being updated does not exist." },
+                "405": { "description": "Method Not Allowed. The server is a read-only replica." },
+                "500": { "description": "Internal Server Error." }
+            }
+        }),
+        "get_audit_log_proof_parameters" => json!({
+            "tags": ["audit"],
+            "operationId": "getAuditLogProofParameters",
+            "summary": "Get audit log proof parameters",
+            "description": "Returns the proof parameters that are required for exporting the audit log. The challenge is the hash of the most recent successful operation and changes with every export.",
+            "parameters": [proof_parameters],
+            "responses": {
+                "200": proof_parameters_response("Returns the proof parameters.")
+            }
+        }),
+        "export_audit_log" => json!({
+            "tags": ["audit"],
+            "operationId": "exportAuditLog",
+            "summary": "Export audit log",
+            "description": "This endpoint can only be called by the owner of the service. Returns all entries of the audit log, including the entry that records the export. Entries are hash-chained via `previous_hash` and signed if a key is configured.",
+            "requestBody": presentation,
+            "responses": {
+                "200": {
+                    "description": "Entries of the audit log.",
+                    "content": {
+                        "application/json": {
+                            "schema": { "type": "array", "items": { "$ref": "#/components/schemas/AuditEntry" } }
+                        }
+                    }
+                },
+                "401": { "description": "Unauthorized. Authorization failed due to missing or broken proof signature." },
+                "404": { "description": "Not Found. DID of the owner could not be resolved." },
+                "500": { "description": "Internal Server Error." }
+            }
+        }),
+        "get_snapshot" => json!({
+            "tags": ["replication"],
+            "operationId": "getReplicationSnapshot",
+            "summary": "Replication snapshot",
+            "description": "Returns all DID documents for synchronising replicas. Requires the replication token as bearer token.",
+            "security": [{ "replicationToken": [] }],
+            "responses": {
+                "200": {
+                    "description": "Ids and DID documents of all stored DIDs.",
+                    "content": {
+                        "application/json": {
+                            "schema": { "type": "array", "items": { "$ref": "#/components/schemas/ReplicationEntry" } }
+                        }
+                    }
+                },
+                "401": { "description": "Unauthorized. Replication token missing or invalid, or replication disabled." }
+            }
+        }),
+        "get_changes" => json!({
+            "tags": ["replication"],
+            "operationId": "getChanges",
+            "summary": "Change feed",
+            "description": "Returns the changes of DID documents after the given sequence number, ordered by sequence number. At most `limit` changes are returned, clients page through the feed by requesting the changes after the last returned sequence number. Requires the change feed token as bearer token.",
+            "security": [{ "changeFeedToken": [] }],
+            "parameters": [
+                since("Sequence number of the last change that the client has seen. All retained changes are returned if not set or 0."),
+                {
+                    "name": "limit",
+                    "in": "query",
+                    "description": "Maximum number of returned changes.",
+                    "required": false,
+                    "schema": { "type": "integer", "minimum": 1, "maximum": 1000, "default": 100 }
+                }
+            ],
+            "responses": {
+                "200": {
+                    "description": "Changes of DID documents.",
+                    "content": {
+                        "application/json": {
+                            "schema": { "type": "array", "items": { "$ref": "#/components/schemas/Change" } }
+                        }
+                    }
+                },
+                "401": { "description": "Unauthorized. Change feed token missing or invalid, or change feed access disabled." },
+                "410": { "description": "Gone. Changes after the given sequence number have been compacted, the client has to resynchronize." }
+            }
+        }),
+        "stream_changes" => json!({
+            "tags": ["replication"],
+            "operationId": "streamChanges",
+            "summary": "Change stream",
+            "description": "Streams the changes of DID documents as Server-Sent Events of type `change`. The id of each event is the sequence number of the change. Requires the change feed token as bearer token.",
+            "security": [{ "changeFeedToken": [] }],
+            "parameters": [since("Sequence number of the last change that the client has seen. Only new changes are streamed if not set.")],
+            "responses": {
+                "200": {
+                    "description": "Stream of changes, each encoded as JSON.",
+                    "content": {
+                        "text/event-stream": {
+                            "schema": { "$ref": "#/components/schemas/Change" }
+                        }
+                    }
+                },
+                "401": { "description": "Unauthorized. Change feed token missing or invalid, or change feed access disabled." },
+                "410": { "description": "Gone. Changes after the given sequence number have been compacted, the client has to resynchronize." }
+            }
+        }),
+        "resolve" => json!({
+            "tags": ["resolver"],
+            "operationId": "resolveIdentifier",
+            "summary": "Resolve DID or dereference DID URL",
+            "description": "Resolves a DID following the DID Resolution HTTP(S) binding, see https://w3c-ccg.github.io/did-resolution/#bindings-https. DIDs that are hosted by this server are resolved from the store, all other DIDs are resolved via the built-in and configured resolvers. did:web DIDs of other hosts are only resolved if their host is listed in `DWS_RESOLVER_ALLOWED_HOSTS`. DID URLs with a fragment (encoded as `%23`) are dereferenced to the verification method or service with the corresponding id. DID URLs with a `service` parameter are redirected to the selected service endpoint.",
+            "parameters": [{
+                "name": "did",
+                "in": "path",
+                "required": true,
+                "description": "DID or DID URL, e.g. `did:web:example.com:alice%23key-1`",
+                "schema": { "type": "string" }
+            }, {
+                "name": "service",
+                "in": "query",
+                "required": false,
+                "description": "Fragment of the service whose endpoint is selected, e.g. `files`",
+                "schema": { "type": "string" }
+            }, {
+                "name": "relativeRef",
+                "in": "query",
+                "required": false,
+                "description": "Relative reference that is resolved against the selected service endpoint, e.g. `/docs/a.pdf`",
+                "schema": { "type": "string" }
+            }],
+            "responses": {
+                "200": {
+                    "description": "DID resolution result or the dereferenced resource. Only the DID document is returned if `application/did+ld+json` is requested.",
+                    "content": {
+                        "application/json": {
+                            "schema": { "type": "object" }
+                        },
+                        "application/ld+json;profile=\"https://w3id.org/did-resolution\"": {
+                            "schema": { "$ref": "#/components/schemas/ResolutionResult" }
+                        },
+                        "application/did+ld+json": {
+                            "schema": { "$ref": "#/components/schemas/DIDDoc" }
+                        }
+                    }
+                },
+                "303": { "description": "See Other. Redirect to the selected service endpoint." },
+                "400": { "description": "Bad Request. The DID or DID URL is invalid." },
+                "403": { "description": "Forbidden. The DID isn't hosted by this server and its host isn't allowed." },
+                "404": { "description": "Not Found. The DID document or the resource identified by the DID URL could not be found." },
+                "410": { "description": "Gone. The DID has been deactivated." },
+                "501": { "description": "Not Implemented. The DID method is not supported." },
+                "500": { "description": "Internal Server Error." }
+            }
+        }),
+        "healthz" => json!({
+            "tags": ["meta"],
+            "operationId": "getLiveness",
+            "summary": "Liveness probe",
+            "description": "Succeeds as long as the server is able to respond to requests.",
+            "responses": {
+                "200": { "description": "Server is alive." }
+            }
+        }),
+        "readyz" => json!({
+            "tags": ["meta"],
+            "operationId": "getReadiness",
+            "summary": "Readiness probe",
+            "description": "Succeeds if the store is readable and writable, the DIDs of the server's and tenants' owners can be resolved and the TLS material is loaded. Temporary resolver failures mark the owner check as degraded without failing it.",
+            "responses": {
+                "200": {
+                    "description": "Server is ready.",
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Readiness" } } }
+                },
+                "503": {
+                    "description": "Service Unavailable. At least one check failed.",
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Readiness" } } }
+                }
+            }
+        }),
+        "version" => json!({
+            "tags": ["meta"],
+            "operationId": "getBuildInfo",
+            "summary": "Build information",
+            "description": "Returns the version of the server and the available storage backends.",
+            "responses": {
+                "200": {
+                    "description": "Build information.",
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/BuildInfo" } } }
+                }
+            }
+        }),
+        "get_metrics" => json!({
+            "tags": ["meta"],
+            "operationId": "getMetrics",
+            "summary": "Prometheus metrics",
+            "description": "Returns request, error, store latency and presentation verification metrics in the Prometheus text format. Requires the metrics token as bearer token if it's configured.",
+            "security": [{}, { "metricsToken": [] }],
+            "responses": {
+                "200": {
+                    "description": "Metrics.",
+                    "content": { "text/plain": { "schema": { "type": "string" } } }
+                },
+                "401": { "description": "Unauthorized. Metrics token invalid." },
+                "500": { "description": "Internal Server Error." }
+            }
+        }),
+        "get_openapi" => json!({
+            "tags": ["meta"],
+            "operationId": "getOpenAPISpecification",
+            "summary": "Get OpenAPI specification",
+            "description": "Returns the OpenAPI specification of this server.",
+            "responses": {
+                "200": {
+                    "description": "OpenAPI specification.",
+                    "content": { "application/json": { "schema": { "type": "object" } } }
+                }
+            }
+        }),
+        "get_openapi_explorer" => json!({
+            "tags": ["meta"],
+            "operationId": "getAPIExplorer",
+            "summary": "Get API explorer",
+            "description": "Returns an interactive API explorer for this server.",
+            "responses": {
+                "200": {
+                    "description": "API explorer.",
+                    "content": { "text/html": { "schema": { "type": "string" } } }
+                }
+            }
+        }),
+        _ => return None,
+    };
+    Some(operation)
+}
+
+/// Computes the OpenAPI specification for the given routes. Routes that aren't documented by [`operation`] are
+/// omitted.
+pub fn spec<'a>(routes: impl Iterator<Item = &'a Route>) -> Value {
+    let mut paths = Map::new();
+    for route in routes {
+        let operation = match route.name.as_deref().and_then(operation) {
+            Some(operation) => operation,
+            None => continue,
+        };
+        let method = match route.method {
+            Method::Get => "get",
+            Method::Post => "post",
+            Method::Put => "put",
+            Method::Delete => "delete",
+            _ => continue,
+        };
+        let path = paths
+            .entry(path_template(route))
+            .or_insert_with(|| Value::Object(Map::new()));
+        match path.get_mut(method) {
+            // Routes that differ only in their query are merged into a single operation, e.g. `?proofParameters`
+            Some(Value::Object(existing)) => merge_operation(existing, operation),
+            _ => {
+                path[method] = operation;
+            }
+        }
+    }
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "title": "did-web-server API",
+            "description": "Open API specification of this [did-web-server](https://dws.identinet.io) instance.",
+            "license": {
+                "name": "AGPL-3.0",
+                "identifier": "AGPL-3.0"
+            },
+            "contact": {
+                "name": "identinet GmbH",
+                "email": "support@identinet.io",
+                "url": "https://identinet.io"
+            }
+        },
+        "servers": [{ "url": "/" }],
+        "paths": paths,
+        "tags": [
+            { "name": "did", "description": "Everything related to DIDs" },
+            { "name": "resolver", "description": "Resolution of DIDs" },
+            { "name": "audit", "description": "Audit log of all mutating operations" },
+            { "name": "replication", "description": "Synchronisation of replicas" },
+            { "name": "meta", "description": "Information about the server" }
+        ],
+        "components": {
+            "schemas": {
+                "ID": {
+                    "type": "string",
+                    "description": "**ID**: An identifier, i.e. the path to the DID document without `/did.json`.",
+                    "example": "person"
+                },
+                "DID": {
+                    "type": "string",
+                    "description": "**DID**: W3C Decentralized Identifier (DID), see https://w3c.github.io/did-core/.",
+                    "example": "did:web:example.com:person"
+                },
+                "ProofParameters": {
+                    "type": "object",
+                    "title": "ProofParameters",
+                    "description": "**ProofParameters**: Proof parameters define properties that need to be present in the next submitted Verifiable Presentation for PUT, POST and DELETE requests.",
+                    "required": ["did", "challenge", "domain", "proof_purpose"],
+                    "properties": {
+                        "did": { "$ref": "#/components/schemas/DID" },
+                        "challenge": { "type": "string" },
+                        "domain": { "type": "string" },
+                        "proof_purpose": { "type": "string" }
+                    }
+                },
+                "DIDDoc": {
+                    "type": "object",
+                    "title": "DID Document",
+                    "description": "**DID Document**: W3C DID Document, see https://w3c.github.io/did-core/.",
+                    "required": ["id"],
+                    "properties": {
+                        "id": { "$ref": "#/components/schemas/DID" }
+                    }
+                },
+                "VerifiableCredential": {
+                    "type": "object",
+                    "title": "VerifiableCredential",
+                    "description": "**VerifiableCredential**: W3C Verifiable Credential, see https://w3c.github.io/vc-data-model/.",
+                    "required": ["type", "credentialSubject", "proof"],
+                    "properties": {
+                        "type": { "type": "array", "items": { "type": "string" } },
+                        "credentialSubject": { "$ref": "#/components/schemas/DIDDoc" },
+                        "proof": { "type": "object" }
+                    }
+                },
+                "Readiness": {
+                    "type": "object",
+                    "title": "Readiness",
+                    "description": "**Readiness**: Result of the readiness checks.",
+                    "properties": {
+                        "ready": { "type": "boolean" },
+                        "store": { "$ref": "#/components/schemas/Check" },
+                        "owner": { "$ref": "#/components/schemas/Check" },
+                        "tls": { "$ref": "#/components/schemas/Check" }
+                    }
+                },
+                "Check": {
+                    "type": "object",
+                    "title": "Check",
+                    "properties": {
+                        "ok": { "type": "boolean" },
+                        "degraded": { "type": "boolean" },
+                        "error": { "type": "string" }
+                    }
+                },
+                "ReplicationEntry": {
+                    "type": "object",
+                    "title": "ReplicationEntry",
+                    "required": ["id", "document"],
+                    "properties": {
+                        "id": { "type": "string", "example": "example.com/person/did.json" },
+                        "document": { "$ref": "#/components/schemas/DIDDoc" }
+                    }
+                },
+                "Change": {
+                    "type": "object",
+                    "title": "Change",
+                    "description": "**Change**: Creation, update or deletion of a DID document.",
+                    "required": ["sequence", "timestamp", "operation", "did", "id"],
+                    "properties": {
+                        "sequence": { "type": "integer" },
+                        "timestamp": { "type": "string", "format": "date-time" },
+                        "operation": { "type": "string", "enum": ["create", "update", "delete"] },
+                        "did": { "$ref": "#/components/schemas/DID" },
+                        "id": { "type": "string", "example": "example.com/person/did.json" },
+                        "document": {
+                            "description": "DID document after the change, missing if the DID has been deactivated.",
+                            "allOf": [{ "$ref": "#/components/schemas/DIDDoc" }]
+                        }
+                    }
+                },
+                "BuildInfo": {
+                    "type": "object",
+                    "title": "BuildInfo",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "version": { "type": "string" },
+                        "backends": { "type": "array", "items": { "type": "string" } }
+                    }
+                },
+                "ResolutionResult": {
+                    "type": "object",
+                    "title": "ResolutionResult",
+                    "description": "**ResolutionResult**: DID resolution result, see https://w3c-ccg.github.io/did-resolution/#did-resolution-result.",
+                    "properties": {
+                        "@context": { "type": "string" },
+                        "didDocument": { "$ref": "#/components/schemas/DIDDoc" },
+                        "didResolutionMetadata": { "type": "object" },
+                        "didDocumentMetadata": { "type": "object" }
+                    }
+                },
+                "AuditEntry": {
+                    "type": "object",
+                    "title": "AuditEntry",
+                    "description": "**AuditEntry**: Record of a create, update, delete or export operation.",
+                    "properties": {
+                        "sequence": { "type": "integer" },
+                        "timestamp": { "type": "string", "format": "date-time" },
+                        "request_id": { "type": "string" },
+                        "operation": { "type": "string", "enum": ["create", "update", "delete", "export"] },
+                        "outcome": { "type": "string", "enum": ["success", "rejected", "suppressed"] },
+                        "error": { "type": "string" },
+                        "source": { "type": "string", "description": "Address of the peer, IPv6 addresses are reduced to their /64 prefix." },
+                        "suppressed": { "type": "integer", "description": "Number of rejected operations that a suppressed entry aggregates." },
+                        "id": { "type": "string" },
+                        "did": { "$ref": "#/components/schemas/DID" },
+                        "authorizing_did": { "$ref": "#/components/schemas/DID" },
+                        "verification_method": { "type": "string" },
+                        "challenge": { "type": "string" },
+                        "old_document_hash": { "type": "string" },
+                        "new_document_hash": { "type": "string" },
+                        "presentation": { "type": "object", "description": "Submitted presentation, the proof values of rejected operations are redacted." },
+                        "previous_hash": { "type": "string" },
+                        "hash": { "type": "string" },
+                        "signature": { "type": "string" }
+                    }
+                },
+                "VerifiablePresentation": {
+                    "type": "object",
+                    "title": "VerifiablePresentation",
+                    "description": "**VerifiablePresentation**: W3C Verifiable Presentation, see https://w3c.github.io/vc-data-model/.",
+                    "required": ["type", "verifiableCredential", "proof"],
+                    "properties": {
+                        "type": { "type": "array", "items": { "type": "string" } },
+                        "verifiableCredential": { "$ref": "#/components/schemas/VerifiableCredential" },
+                        "proof": { "type": "object" }
+                    }
+                }
+            },
+            "securitySchemes": {
+                "replicationToken": { "type": "http", "scheme": "bearer" },
+                "changeFeedToken": { "type": "http", "scheme": "bearer" },
+                "metricsToken": { "type": "http", "scheme": "bearer" }
+            }
+        }
+    })
+}
+
+/// Merges the parameters of `operation` into `existing` and takes over its responses if they aren't documented yet.
+fn merge_operation(existing: &mut Map<String, Value>, operation: Value) {
+    if let Value::Object(operation) = operation {
+        for (key, value) in operation {
+            match (key.as_str(), existing.get_mut(&key), value) {
+                ("parameters", Some(Value::Array(parameters)), Value::Array(additional)) => {
+                    for parameter in additional {
+                        if !parameters.contains(&parameter) {
+                            parameters.push(parameter);
+                        }
+                    }
+                }
+                (_, None, value) => {
+                    existing.insert(key, value);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::openapi::*;
+    use rocket::route::dummy_handler;
+    use std::collections::BTreeSet;
+
+    /// Extracts the operations, i.e. path and method, from the `paths` section of an OpenAPI specification in YAML.
+    fn yaml_operations(yaml: &str) -> BTreeSet<(String, String)> {
+        let mut operations = BTreeSet::new();
+        let mut path = None;
+        for line in yaml
+            .lines()
+            .skip_while(|line| *line != "paths:")
+            .skip(1)
+            .take_while(|line| line.is_empty() || line.starts_with(' '))
+        {
+            if let Some(p) = line.strip_prefix("  /").and_then(|p| p.strip_suffix(':')) {
+                path = Some(format!("/{}", p));
+            } else if let (Some(path), Some(method)) = (
+                &path,
+                line.strip_prefix("    ").and_then(|m| m.strip_suffix(':')),
+            ) {
+                if ["get", "post", "put", "delete"].contains(&method) {
+                    operations.insert((path.clone(), method.to_string()));
+                }
+            }
+        }
+        operations
+    }
+
+    #[test]
+    fn test_spec_documented() {
+        let config = Config {
+            api_explorer: true,
+            ..Config::default()
+        };
+        let spec = spec(crate::routes(&config).iter());
+        let paths = spec["paths"].as_object().unwrap();
+        let operations = paths
+            .iter()
+            .flat_map(|(path, methods)| {
+                methods
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (path.clone(), method.clone()))
+            })
+            .collect::<BTreeSet<(String, String)>>();
+        assert_eq!(
+            yaml_operations(include_str!("../docs/public/openapi.yaml")),
+            operations,
+            "When the server's routes are documented, then docs/public/openapi.yaml documents the same operations"
+        );
+
+        let ids = paths
+            .values()
+            .flat_map(|methods| methods.as_object().unwrap().values())
+            .filter_map(|operation| operation["operationId"].as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            ids.iter().collect::<BTreeSet<_>>().len(),
+            ids.len(),
+            "When the specification is computed, then every operationId is unique"
+        );
+    }
+
+    #[test]
+    fn test_path_template() {
+        let route = Route::new(Method::Get, "/<id..>", dummy_handler);
+        assert_eq!(
+            path_template(&route),
+            "/{id}/did.json",
+            "When a route ends in a segments parameter, then the parameter is followed by did.json"
+        );
+
+        let route = Route::new(
+            Method::Get,
+            "/.well-known/did.json?proofParameters",
+            dummy_handler,
+        );
+        assert_eq!(
+            path_template(&route),
+            "/.well-known/did.json",
+            "When a route has a static path and a query, then only the path is returned"
+        );
+
+        let route = Route::new(Method::Get, "/a/<b>/c", dummy_handler);
+        assert_eq!(
+            path_template(&route),
+            "/a/{b}/c",
+            "When a route contains a single segment parameter, then it's converted to a path template parameter"
+        );
+    }
+}
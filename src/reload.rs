@@ -0,0 +1,251 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::figment::providers::Env;
+use rocket::figment::Figment;
+use rocket::tokio::time;
+use rocket::{Orbit, Rocket, Shutdown};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::cache::ResolverCache;
+use crate::config::{self, Config, Policy, Settings, CONFIG_FILE};
+use crate::error::DIDError;
+use crate::health::invalid_owners;
+
+/// Interval in which the configuration file is checked for changes.
+static POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Value that is replaced atomically while it's in use. Readers retrieve a snapshot that stays unchanged until it's
+/// dropped. Clones share the same value.
+#[derive(Debug)]
+pub struct Reloadable<T>(Arc<RwLock<Arc<T>>>);
+
+impl<T> Reloadable<T> {
+    /// Retrieves the current value.
+    pub fn load(&self) -> Arc<T> {
+        match self.0.read() {
+            Ok(value) => value.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Replaces the value. Snapshots that have been retrieved before keep the previous value.
+    pub fn store(&self, value: T) {
+        let mut current = match self.0.write() {
+            Ok(current) => current,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *current = Arc::new(value);
+    }
+}
+
+impl<T> Clone for Reloadable<T> {
+    fn clone(&self) -> Self {
+        Reloadable(self.0.clone())
+    }
+}
+
+impl<T> From<T> for Reloadable<T> {
+    fn from(value: T) -> Self {
+        Reloadable(Arc::new(RwLock::new(Arc::new(value))))
+    }
+}
+
+/// Configuration that reloaded policies are validated with before they're swapped in. It shares the store and the
+/// external hostname with the server's configuration, so that owners whose DIDs are hosted by this server are resolved
+/// from the store, but has its own resolver cache, so that the owners are resolved with the reloaded resolvers.
+fn validation_config(config: &Config) -> Config {
+    Config {
+        external_hostname: config.external_hostname.clone(),
+        external_path: config.external_path.clone(),
+        external_port: config.external_port.clone(),
+        lenient_startup: config.lenient_startup,
+        store: config.store.clone(),
+        ..Config::default()
+    }
+}
+
+/// Reloads the policy from the configuration sources. The DIDs of the owners are validated like at startup, see
+/// [`OwnerValidator`](crate::health::OwnerValidator), before the policy is replaced.
+///
+/// - `figment` - configuration sources, see [`config::figment`]
+/// - `policy` - policy that is replaced
+/// - `cache` - resolver cache that is cleared since the resolvers might have changed
+/// - `validation` - configuration that the reloaded policy is validated with, see [`validation_config`]
+/// - returns an error if the configuration is invalid. The previous policy stays in place in this case.
+pub async fn reload(
+    figment: &Figment,
+    policy: &Reloadable<Policy>,
+    cache: &ResolverCache,
+    validation: &Config,
+) -> Result<(), DIDError> {
+    let settings = Settings::extract(figment)?;
+    let reloaded = Policy::new(&settings)?;
+    validation.policy.store(reloaded.clone());
+    validation.resolver_cache.clear();
+    let invalid = invalid_owners(validation).await;
+    for (owner, e) in invalid.iter() {
+        tracing::warn!(owner = owner.as_str(), error = %e, "owner DID invalid");
+    }
+    if !invalid.is_empty() && !validation.lenient_startup {
+        return Err(DIDError::ConfigInvalid(format!(
+            "Owner DID invalid: {}",
+            invalid
+                .iter()
+                .map(|(owner, e)| format!("{}: {}", owner, e))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )));
+    }
+    policy.store(reloaded);
+    cache.clear();
+    Ok(())
+}
+
+/// Modification time of the configuration file, `None` if it doesn't exist.
+fn modified(file: &Path) -> Option<SystemTime> {
+    std::fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(unix)]
+async fn hangup(signal: &mut Option<rocket::tokio::signal::unix::Signal>) {
+    match signal {
+        Some(signal) => {
+            signal.recv().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Reloads the policy whenever the configuration file changes or the server receives SIGHUP, until the server shuts
+/// down.
+async fn watch(
+    policy: Reloadable<Policy>,
+    cache: ResolverCache,
+    validation: Config,
+    shutdown: Shutdown,
+) {
+    let file = PathBuf::from(Env::var_or("DWS_CONFIG", CONFIG_FILE));
+    let mut last_modified = modified(&file);
+    #[cfg(unix)]
+    let mut signal =
+        rocket::tokio::signal::unix::signal(rocket::tokio::signal::unix::SignalKind::hangup()).ok();
+    let mut interval = time::interval(POLL_INTERVAL);
+    loop {
+        #[cfg(unix)]
+        let hangup = hangup(&mut signal);
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<()>();
+        let trigger = rocket::tokio::select! {
+            _ = shutdown.clone() => return,
+            _ = hangup => "SIGHUP",
+            _ = interval.tick() => {
+                let modified = modified(&file);
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+                "configuration file changed"
+            }
+        };
+        match reload(&config::figment(), &policy, &cache, &validation).await {
+            Ok(()) => tracing::info!(trigger, "configuration reloaded"),
+            Err(e) => tracing::error!(
+                trigger,
+                error = %e,
+                "configuration invalid, keeping the previous configuration"
+            ),
+        }
+    }
+}
+
+/// Fairing that reloads the owner, resolvers and tenants without a restart. The store, audit log and listening
+/// sockets stay as they are.
+pub struct ConfigReloader;
+
+#[rocket::async_trait]
+impl Fairing for ConfigReloader {
+    fn info(&self) -> Info {
+        Info {
+            name: "Configuration reloader",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        if let Some(config) = rocket.state::<Config>() {
+            rocket::tokio::spawn(watch(
+                config.policy.clone(),
+                config.resolver_cache.clone(),
+                validation_config(config),
+                rocket.shutdown(),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::reload::*;
+    use rocket::figment::providers::Serialized;
+
+    #[test]
+    fn test_reloadable() {
+        let policy = Reloadable::from(Policy::default());
+        let snapshot = policy.load();
+        policy.clone().store(Policy {
+            owner: "did:key:new".to_string(),
+            ..Policy::default()
+        });
+        assert_eq!(
+            policy.load().owner,
+            "did:key:new",
+            "When the value is replaced via a clone, then all clones see the new value"
+        );
+        assert_eq!(
+            snapshot.owner, "<invalidDID>",
+            "When the value is replaced, then existing snapshots keep the previous value"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_reload() {
+        let config = Config::default();
+        let validation = validation_config(&config);
+        let owner = "did:key:z6MksRCeBVzFcsnR4Ao7YurYSJEVxNzUPnBNkXAcQdvwmwLR";
+        let figment = |owner: &str| Figment::new().merge(Serialized::default("owner", owner));
+
+        reload(
+            &figment(owner),
+            &config.policy,
+            &config.resolver_cache,
+            &validation,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            config.policy.load().owner,
+            owner,
+            "When the reloaded owner's DID can be resolved, then the policy is replaced"
+        );
+
+        assert!(
+            reload(
+                &figment("did:invalid:owner"),
+                &config.policy,
+                &config.resolver_cache,
+                &validation,
+            )
+            .await
+            .is_err(),
+            "When the reloaded owner's DID can't be resolved, then an error is returned"
+        );
+        assert_eq!(
+            config.policy.load().owner,
+            owner,
+            "When the reloaded owner's DID can't be resolved, then the previous policy stays in place"
+        );
+    }
+}
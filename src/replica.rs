@@ -0,0 +1,397 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::json::Json;
+use rocket::tokio::time;
+use rocket::{Orbit, Request, Rocket};
+use serde::{Deserialize, Serialize};
+use ssi::did::Document;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::DIDError;
+use crate::metrics;
+use crate::store::DIDWebStore;
+
+/// Path of the snapshot of all DID documents that replicas synchronise from.
+pub static REPLICATION_PATH: &str = "/.well-known/replication";
+
+/// Configuration of a replica, i.e. a read-only server that mirrors the DID documents of a primary.
+///
+/// * `primary` - URL of the primary server, e.g. `https://primary.example.com`. Set via DWS_REPLICA_OF variable
+/// * `interval` - Interval of the synchronisation. Set via DWS_REPLICA_SYNC_INTERVAL variable in seconds, e.g. `60`
+/// * `max_removed` - Percentage of the local DID documents that a synchronisation may remove, so that an empty
+///   snapshot of a misconfigured primary doesn't wipe the replica. Set via DWS_REPLICA_MAX_REMOVED variable, e.g. `50`
+#[derive(Debug, Clone)]
+pub struct Replica {
+    pub primary: String,
+    pub interval: Duration,
+    pub max_removed: u8,
+}
+
+/// DID document in the store of the primary.
+///
+/// * `id` - id of the DID document in the store, e.g. `example.com/alice/did.json`
+/// * `document` - DID document
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Entry {
+    pub id: PathBuf,
+    pub document: Document,
+}
+
+/// Number of DID documents that have been changed by a synchronisation.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SyncResult {
+    pub created: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl Config {
+    /// Ensures that the server accepts changes of DID documents, i.e. that it isn't a replica.
+    pub(crate) fn check_writable(&self) -> Result<(), DIDError> {
+        match &self.replica {
+            Some(replica) => Err(DIDError::ReadOnlyReplica(format!(
+                "Server is a read-only replica, send changes to {}",
+                replica.primary
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Request guard that ensures that the request carries the replication token, i.e. `Authorization: Bearer <token>`.
+pub struct Replicator;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Replicator {
+    type Error = DIDError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request
+            .rocket()
+            .state::<Config>()
+            .and_then(|config| config.replication_token.as_deref());
+        match bearer_token_matches(request, token) {
+            Some(true) => Outcome::Success(Replicator),
+            None => {
+                let e = DIDError::ReplicationDenied("Replication is disabled".to_string());
+                Outcome::Error((e.status(), e))
+            }
+            Some(false) => {
+                let e = DIDError::ReplicationDenied("Replication token invalid".to_string());
+                Outcome::Error((e.status(), e))
+            }
+        }
+    }
+}
+
+/// Determines whether a request carries a token as bearer token, i.e. `Authorization: Bearer <token>`.
+///
+/// - `request` - received request
+/// - `token` - expected token
+/// - returns `None` if no token is configured, otherwise whether the request carries the token
+pub(crate) fn bearer_token_matches(request: &Request<'_>, token: Option<&str>) -> Option<bool> {
+    let presented = request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "));
+    token.map(|token| presented.map_or(false, |presented| constant_time_eq(token, presented)))
+}
+
+/// Compares two secrets in constant time so that the token can't be guessed from the response times.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// Retrieve all DID documents for synchronising a replica. Requires the replication token.
+///
+/// - `config` Global Rocket configuration
+/// - returns the ids and DID documents of all stored DIDs
+#[get("/.well-known/replication")]
+pub async fn get_snapshot(
+    config: &rocket::State<Config>,
+    _replicator: Replicator,
+) -> Result<Json<Vec<Entry>>, DIDError> {
+    snapshot(config.store.as_ref()).await.map(Json)
+}
+
+/// Reads all DID documents of a store.
+pub async fn snapshot(store: &(dyn DIDWebStore + Sync + Send)) -> Result<Vec<Entry>, DIDError> {
+    let mut entries = vec![];
+    for id in store.list().await? {
+        match store.get(&id).await {
+            Ok(document) => entries.push(Entry { id, document }),
+            // the DID has been removed in the meantime
+            Err(DIDError::DIDNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(entries)
+}
+
+/// Applies a snapshot of the primary to the local store. DID documents that aren't part of the snapshot are removed.
+/// The snapshot is refused before anything is changed if it removes more than `max_removed` percent of the local DID
+/// documents.
+///
+/// - `store` - local store
+/// - `entries` - snapshot of the primary
+/// - `max_removed` - percentage of the local DID documents that may be removed, `100` to apply every snapshot
+/// - returns the number of changed DID documents
+pub async fn apply(
+    store: &(dyn DIDWebStore + Sync + Send),
+    entries: Vec<Entry>,
+    max_removed: u8,
+) -> Result<SyncResult, DIDError> {
+    let mut result = SyncResult::default();
+    let local = store.list().await?;
+    let total = local.len();
+    let mut removed = local.into_iter().collect::<HashSet<PathBuf>>();
+    for entry in &entries {
+        removed.remove(&entry.id);
+    }
+    if removed.len() * 100 > total * usize::from(max_removed) {
+        return Err(DIDError::SnapshotRefused(format!(
+            "Snapshot refused, it removes {} of {} DID documents. Set DWS_REPLICA_MAX_REMOVED to 100 to apply it",
+            removed.len(),
+            total
+        )));
+    }
+    for entry in entries {
+        match store.get(&entry.id).await {
+            Ok(document) if same(&document, &entry.document) => {}
+            Ok(_) => {
+                store.update(&entry.id, entry.document).await?;
+                result.updated += 1;
+            }
+            Err(DIDError::DIDNotFound(_)) => {
+                store.create(&entry.id, entry.document).await?;
+                result.created += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    for id in removed {
+        store.remove(&id).await?;
+        result.removed += 1;
+    }
+    Ok(result)
+}
+
+/// Compares two DID documents by their JSON representation.
+fn same(a: &Document, b: &Document) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// Retrieves the snapshot from the primary and applies it to the local store.
+///
+/// - `store` - local store
+/// - `token` - replication token that the primary requires
+/// - `replica` - primary that the DID documents are retrieved from
+/// - returns the number of changed DID documents
+pub async fn sync(
+    store: &(dyn DIDWebStore + Sync + Send),
+    token: Option<&str>,
+    replica: &Replica,
+) -> Result<SyncResult, DIDError> {
+    let url = format!(
+        "{}{}",
+        replica.primary.trim_end_matches('/'),
+        REPLICATION_PATH
+    );
+    let unavailable =
+        |e: reqwest::Error| DIDError::StoreUnavailable(format!("Primary unavailable: {}", e));
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let entries = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(unavailable)?
+        .json::<Vec<Entry>>()
+        .await
+        .map_err(unavailable)?;
+    apply(store, entries, replica.max_removed).await
+}
+
+/// Synchronises the replica periodically until the server shuts down.
+async fn run(
+    store: Arc<dyn DIDWebStore + Sync + Send>,
+    token: Option<String>,
+    replica: Replica,
+    shutdown: rocket::Shutdown,
+) {
+    let mut interval = time::interval(replica.interval);
+    loop {
+        rocket::tokio::select! {
+            _ = shutdown.clone() => return,
+            _ = interval.tick() => {}
+        }
+        match sync(store.as_ref(), token.as_deref(), &replica).await {
+            Ok(result) => {
+                metrics::REPLICA_LAST_SYNC.set(chrono::Utc::now().timestamp());
+                tracing::info!(
+                    primary = %replica.primary,
+                    created = result.created,
+                    updated = result.updated,
+                    removed = result.removed,
+                    "replica synchronised"
+                );
+            }
+            Err(e) => {
+                tracing::error!(primary = %replica.primary, error = %e, "replica not synchronised")
+            }
+        }
+    }
+}
+
+/// Fairing that synchronises the DID documents of a replica from its primary. It does nothing unless DWS_REPLICA_OF
+/// is set.
+pub struct ReplicaSync;
+
+#[rocket::async_trait]
+impl Fairing for ReplicaSync {
+    fn info(&self) -> Info {
+        Info {
+            name: "Replica synchronisation",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        if let Some(config) = rocket.state::<Config>() {
+            if let Some(replica) = config.replica.clone() {
+                rocket::tokio::spawn(run(
+                    config.store.clone(),
+                    config.replication_token.clone(),
+                    replica,
+                    rocket.shutdown(),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::replica::*;
+    use crate::store::mem::MemStore;
+
+    fn entry(id: &str, version: u32) -> Entry {
+        Entry {
+            id: PathBuf::from(id),
+            document: serde_json::from_value(serde_json::json!({
+                "@context": "https://www.w3.org/ns/did/v1",
+                "id": format!("did:web:localhost:{}", version)
+            }))
+            .unwrap(),
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_apply() {
+        let store = MemStore::default();
+        let result = apply(
+            &store,
+            vec![entry("alice/did.json", 1), entry("bob/did.json", 1)],
+            50,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (result.created, store.list().await.unwrap().len()),
+            (2, 2),
+            "When the snapshot contains new DIDs, then they're created"
+        );
+
+        let result = apply(&store, vec![entry("alice/did.json", 2)], 50)
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            SyncResult {
+                created: 0,
+                updated: 1,
+                removed: 1
+            },
+            "When DIDs changed or have been removed on the primary, then they're updated or removed"
+        );
+        assert_eq!(
+            store
+                .get(&PathBuf::from("alice/did.json"))
+                .await
+                .unwrap()
+                .id,
+            "did:web:localhost:2",
+            "When a DID changed on the primary, then the local copy is replaced"
+        );
+
+        let result = apply(&store, vec![entry("alice/did.json", 2)], 50)
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            SyncResult::default(),
+            "When nothing changed on the primary, then the local store stays unchanged"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_apply_empty() {
+        let store = MemStore::default();
+        apply(
+            &store,
+            vec![entry("alice/did.json", 1), entry("bob/did.json", 1)],
+            50,
+        )
+        .await
+        .unwrap();
+        assert!(
+            matches!(
+                apply(&store, vec![], 50).await,
+                Err(DIDError::SnapshotRefused(_))
+            ),
+            "When the snapshot is empty, then it's refused"
+        );
+        assert_eq!(
+            store.list().await.unwrap().len(),
+            2,
+            "When a snapshot is refused, then the local store stays unchanged"
+        );
+        assert_eq!(
+            apply(&store, vec![], 100).await.unwrap().removed,
+            2,
+            "When all removals are allowed, then the empty snapshot is applied"
+        );
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let config = Config {
+            replica: Some(Replica {
+                primary: "https://primary.example.com".to_string(),
+                interval: Duration::from_secs(60),
+                max_removed: 50,
+            }),
+            ..Config::default()
+        };
+        assert!(
+            matches!(config.check_writable(), Err(DIDError::ReadOnlyReplica(_))),
+            "When the server is a replica, then changes are rejected"
+        );
+        assert!(
+            Config::default().check_writable().is_ok(),
+            "When the server is a primary, then changes are accepted"
+        );
+    }
+}
@@ -0,0 +1,649 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use async_trait::async_trait;
+use rocket::http::uri::Origin;
+use rocket::http::{Accept, ContentType, Status};
+use rocket::response::Redirect;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use ssi::did::Document;
+use ssi::did_resolve::{
+    DIDResolver, DocumentMetadata, ResolutionInputMetadata, ResolutionMetadata, ERROR_INVALID_DID,
+    ERROR_METHOD_NOT_SUPPORTED, ERROR_NOT_FOUND, ERROR_REPRESENTATION_NOT_SUPPORTED,
+    TYPE_DID_LD_JSON,
+};
+use std::str::FromStr;
+use url::Url;
+
+use crate::config::Config;
+use crate::content_types::DIDContentTypes;
+use crate::did::{DIDHost, DIDWeb};
+use crate::error::DIDError;
+use crate::logging::RequestId;
+
+/// JSON-LD context of DID resolution results.
+pub static RESOLUTION_CONTEXT: &str = "https://w3id.org/did-resolution/v1";
+
+/// Error that is returned if the resolution failed for any other reason.
+pub static ERROR_INTERNAL: &str = "internalError";
+
+/// Error that is returned if the server isn't allowed to resolve a DID on behalf of a client.
+pub static ERROR_NOT_ALLOWED: &str = "notAllowed";
+
+/// DID resolution result, see https://w3c-ccg.github.io/did-resolution/#did-resolution-result
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolutionResult {
+    #[serde(rename = "@context")]
+    pub context: String,
+    pub did_document: Option<Document>,
+    pub did_resolution_metadata: ResolutionMetadata,
+    pub did_document_metadata: Option<DocumentMetadata>,
+}
+
+impl ResolutionResult {
+    fn new(
+        did_resolution_metadata: ResolutionMetadata,
+        did_document: Option<Document>,
+        did_document_metadata: Option<DocumentMetadata>,
+    ) -> ResolutionResult {
+        ResolutionResult {
+            context: RESOLUTION_CONTEXT.to_string(),
+            did_document,
+            did_resolution_metadata,
+            did_document_metadata,
+        }
+    }
+
+    fn error(error: &str) -> ResolutionResult {
+        ResolutionResult::new(ResolutionMetadata::from_error(error), None, None)
+    }
+
+    /// HTTP status that corresponds to the resolution result, see
+    /// https://w3c-ccg.github.io/did-resolution/#bindings-https
+    pub fn status(&self) -> Status {
+        match self.did_resolution_metadata.error.as_deref() {
+            None => match &self.did_document_metadata {
+                Some(DocumentMetadata {
+                    deactivated: Some(true),
+                    ..
+                }) => Status::Gone,
+                _ => Status::Ok,
+            },
+            Some(ERROR_INVALID_DID) => Status::BadRequest,
+            Some(ERROR_NOT_FOUND) => Status::NotFound,
+            Some(ERROR_NOT_ALLOWED) => Status::Forbidden,
+            Some(ERROR_REPRESENTATION_NOT_SUPPORTED) => Status::NotAcceptable,
+            Some(ERROR_METHOD_NOT_SUPPORTED) => Status::NotImplemented,
+            Some(_) => Status::InternalServerError,
+        }
+    }
+}
+
+/// Resolves a DID that is hosted by this server from the store.
+///
+/// - `config` - server configuration
+/// - `did` - DID that is resolved
+/// - returns `None` if the DID isn't hosted by this server
+pub async fn resolve_local(config: &Config, did: &str) -> Option<ResolutionResult> {
+    let did = DIDWeb::from_str(did).ok()?;
+    let id = config.local_store_id(&did)?;
+    Some(match config.store.get(&id).await {
+        Ok(document) => ResolutionResult::new(
+            ResolutionMetadata {
+                error: None,
+                content_type: Some(TYPE_DID_LD_JSON.to_string()),
+                property_set: None,
+            },
+            Some(document),
+            Some(DocumentMetadata::default()),
+        ),
+        Err(DIDError::DIDNotFound(_)) => ResolutionResult::error(ERROR_NOT_FOUND),
+        Err(_) => ResolutionResult::error(ERROR_INTERNAL),
+    })
+}
+
+/// Resolver for DIDs that are hosted by this server. The DID documents are read from the store so that the server
+/// never fetches its own DIDs via the network. All other DIDs are rejected with `methodNotSupported` so that the next
+/// resolver of a `SeriesResolver` is used.
+pub struct StoreResolver<'a> {
+    pub config: &'a Config,
+}
+
+#[async_trait]
+impl DIDResolver for StoreResolver<'_> {
+    async fn resolve(
+        &self,
+        did: &str,
+        _input_metadata: &ResolutionInputMetadata,
+    ) -> (
+        ResolutionMetadata,
+        Option<Document>,
+        Option<DocumentMetadata>,
+    ) {
+        let result = resolve_local(self.config, did)
+            .await
+            .unwrap_or_else(|| ResolutionResult::error(ERROR_METHOD_NOT_SUPPORTED));
+        (
+            result.did_resolution_metadata,
+            result.did_document,
+            result.did_document_metadata,
+        )
+    }
+}
+
+/// Determines whether the server resolves a did:web DID on behalf of a client. DIDs that are hosted by this server
+/// are always resolved. Other did:web DIDs require a network request to their host, so that their host must be listed
+/// in `resolver_allowed_hosts` and must not be internal, see [`DIDHost::is_internal`]. Hosts are matched exactly or,
+/// if the entry starts with `*.`, including all subdomains. The host's DNS records aren't checked, so the allowed hosts
+/// must be trusted not to resolve to internal addresses.
+///
+/// - `config` - server configuration
+/// - `did` - did:web DID that is resolved
+pub fn resolution_allowed(config: &Config, did: &DIDWeb) -> bool {
+    if config.local_store_id(did).is_some() {
+        return true;
+    }
+    let host = did.host();
+    if host.is_internal() {
+        return false;
+    }
+    let host = host.to_string();
+    config
+        .policy
+        .load()
+        .resolver_allowed_hosts
+        .iter()
+        .any(|allowed| {
+            // entries are compared in their ASCII form like the host
+            let ascii =
+                |allowed: &str| DIDHost::from_str(allowed).map(|allowed| allowed.to_string());
+            match allowed.strip_prefix("*.") {
+                Some(domain) => ascii(domain).map_or(false, |domain| {
+                    host.strip_suffix(&domain)
+                        .map_or(false, |subdomain| subdomain.ends_with('.'))
+                }),
+                None => ascii(allowed).map_or(false, |allowed| allowed == host),
+            }
+        })
+}
+
+/// Resolves a DID. DIDs that are hosted by this server are resolved from the store, all other DIDs are resolved via
+/// the configured resolvers and cached. did:web DIDs of other hosts are only resolved if allowed, see
+/// [`resolution_allowed`].
+///
+/// - `config` - server configuration
+/// - `did` - DID that is resolved
+pub async fn resolve_did(config: &Config, did: &str) -> ResolutionResult {
+    if !did.starts_with("did:") {
+        return ResolutionResult::error(ERROR_INVALID_DID);
+    }
+    if did.starts_with("did:web:") {
+        match DIDWeb::from_str(did) {
+            Ok(did) if resolution_allowed(config, &did) => {}
+            Ok(_) => return ResolutionResult::error(ERROR_NOT_ALLOWED),
+            Err(_) => return ResolutionResult::error(ERROR_INVALID_DID),
+        }
+    }
+    let resolver = config.get_resolver();
+    let (metadata, document, document_metadata) = resolver
+        .resolve(did, &ResolutionInputMetadata::default())
+        .await;
+    ResolutionResult::new(metadata, document, document_metadata)
+}
+
+/// JSON properties of a DID document that contain resources which can be dereferenced via a fragment.
+static RESOURCE_PROPERTIES: &[&str] = &[
+    "verificationMethod",
+    "service",
+    "authentication",
+    "assertionMethod",
+    "keyAgreement",
+    "capabilityInvocation",
+    "capabilityDelegation",
+];
+
+/// Error that is returned if a DID URL is invalid.
+pub static ERROR_INVALID_DID_URL: &str = "invalidDidUrl";
+
+/// DID URL that is dereferenced, see https://www.w3.org/TR/did-core/#did-url-syntax
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DIDURL {
+    pub did: String,
+    pub fragment: Option<String>,
+    /// Value of the `service` parameter.
+    pub service: Option<String>,
+    /// Value of the `relativeRef` parameter.
+    pub relative_ref: Option<String>,
+}
+
+impl DIDURL {
+    /// Builds a DID URL from the DID and fragment and the query parameters.
+    ///
+    /// - `did` - DID with optional fragment, e.g. `did:web:example.com:alice#key-1`
+    /// - `query` - decoded query parameters, e.g. `service` and `relativeRef`
+    pub fn new<'a>(did: &str, query: impl Iterator<Item = (&'a str, &'a str)>) -> DIDURL {
+        let (did, fragment) = match did.split_once('#') {
+            Some((did, fragment)) => (did, Some(fragment.to_string())),
+            None => (did, None),
+        };
+        query.fold(
+            DIDURL {
+                did: did.to_string(),
+                fragment,
+                ..DIDURL::default()
+            },
+            |did_url, (key, value)| match key {
+                "service" => DIDURL {
+                    service: Some(value.to_string()),
+                    ..did_url
+                },
+                "relativeRef" => DIDURL {
+                    relative_ref: Some(value.to_string()),
+                    ..did_url
+                },
+                _ => did_url,
+            },
+        )
+    }
+
+    /// Returns true if the DID URL refers to the DID document itself.
+    pub fn is_did(&self) -> bool {
+        self.fragment.is_none() && self.service.is_none() && self.relative_ref.is_none()
+    }
+}
+
+/// Outcome of dereferencing a DID URL, see https://w3c-ccg.github.io/did-resolution/#dereferencing
+#[derive(Debug, PartialEq)]
+pub enum Dereferenced {
+    /// Resource from the DID document, e.g. a verification method.
+    Resource(Value),
+    /// URL of the selected service endpoint.
+    ServiceEndpoint(String),
+}
+
+/// Dereferences a DID URL within a resolved DID document.
+///
+/// - `did_url` - DID URL that is dereferenced
+/// - `document` - resolved DID document of `did_url.did`
+/// - returns the selected resource or service endpoint URL, otherwise the dereferencing error
+pub fn dereference(did_url: &DIDURL, document: &Document) -> Result<Dereferenced, &'static str> {
+    let document = serde_json::to_value(document).map_err(|_| ERROR_INTERNAL)?;
+    let find_resource = |fragment: &str| {
+        let absolute = format!("{}#{}", did_url.did, fragment);
+        let relative = format!("#{}", fragment);
+        RESOURCE_PROPERTIES
+            .iter()
+            .filter_map(|property| document.get(property).and_then(Value::as_array))
+            .flatten()
+            .find(|resource| {
+                matches!(resource.get("id").and_then(Value::as_str),
+                    Some(id) if id == absolute || id == relative)
+            })
+            .cloned()
+    };
+    match (&did_url.service, &did_url.relative_ref) {
+        (Some(service), relative_ref) => {
+            let service = find_resource(service).ok_or(ERROR_NOT_FOUND)?;
+            // the service endpoint is either a URL or a set of URLs of which the first is selected
+            let endpoint = match service.get("serviceEndpoint") {
+                Some(Value::String(endpoint)) => Some(endpoint.as_str()),
+                Some(Value::Array(endpoints)) => endpoints.iter().find_map(Value::as_str),
+                _ => None,
+            }
+            .ok_or(ERROR_NOT_FOUND)?;
+            let mut url = Url::parse(endpoint).map_err(|_| ERROR_INVALID_DID_URL)?;
+            // the relative reference is resolved against the service endpoint as specified in RFC 3986 section 5
+            if let Some(relative_ref) = relative_ref {
+                url = url.join(relative_ref).map_err(|_| ERROR_INVALID_DID_URL)?;
+            }
+            if did_url.fragment.is_some() {
+                url.set_fragment(did_url.fragment.as_deref());
+            }
+            Ok(Dereferenced::ServiceEndpoint(url.to_string()))
+        }
+        (None, Some(_)) => Err(ERROR_INVALID_DID_URL),
+        (None, None) => {
+            let fragment = did_url.fragment.as_deref().ok_or(ERROR_INVALID_DID_URL)?;
+            let mut resource = find_resource(fragment).ok_or(ERROR_NOT_FOUND)?;
+            // the resource is interpreted in the context of the DID document
+            if let (Some(resource), Some(context)) =
+                (resource.as_object_mut(), document.get("@context"))
+            {
+                resource
+                    .entry("@context")
+                    .or_insert_with(|| context.clone());
+            }
+            Ok(Dereferenced::Resource(resource))
+        }
+    }
+}
+
+/// HTTP status that corresponds to a dereferencing error.
+fn dereferencing_status(error: &str) -> Status {
+    match error {
+        ERROR_NOT_FOUND => Status::NotFound,
+        ERROR_INVALID_DID_URL => Status::BadRequest,
+        _ => Status::InternalServerError,
+    }
+}
+
+/// Response of the resolution endpoint.
+#[derive(Responder)]
+pub enum ResolutionResponse {
+    Content((Status, (ContentType, String))),
+    Redirect(Redirect),
+}
+
+impl ResolutionResponse {
+    fn json<T: Serialize>(status: Status, content_type: ContentType, body: &T) -> Self {
+        match serde_json::to_string(body) {
+            Ok(body) => ResolutionResponse::Content((status, (content_type, body))),
+            Err(e) => ResolutionResponse::Content((
+                Status::InternalServerError,
+                (ContentType::Text, e.to_string()),
+            )),
+        }
+    }
+
+    /// Dereferencing result that describes an error.
+    fn dereferencing_error(status: Status, error: &str) -> Self {
+        ResolutionResponse::json(
+            status,
+            ContentType::JSON,
+            &json!({
+                "@context": RESOLUTION_CONTEXT,
+                "dereferencingMetadata": { "error": error },
+                "contentStream": null,
+                "contentMetadata": {}
+            }),
+        )
+    }
+}
+
+/// Resolve a DID or dereference a DID URL following the DID Resolution HTTP(S) binding, see
+/// https://w3c-ccg.github.io/did-resolution/#bindings-https
+///
+/// - `config` Global Rocket configuration
+/// - `request_id` - ID of the request
+/// - `uri` - requested URI, used to retrieve the DID without percent-decoding, e.g. `did:web:example.com%3A8443`
+/// - `did` - requested DID or DID URL, percent-decoded. The fragment must be percent-encoded as `%23`
+/// - `accept` - requested media type. If `application/did+ld+json` is requested, only the DID document is returned
+/// - returns the DID resolution result, the dereferenced resource or a redirect to the selected service endpoint
+#[get("/1.0/identifiers/<did>")]
+pub async fn resolve(
+    config: &rocket::State<Config>,
+    request_id: RequestId,
+    uri: &Origin<'_>,
+    did: &str,
+    accept: Option<&Accept>,
+) -> ResolutionResponse {
+    // percent-encoded characters are part of did:web DIDs and must be preserved, the DID is only decoded if the
+    // client encoded the whole DID. The fragment is always encoded as it'd be dropped by clients otherwise.
+    let did = match uri.path().raw_segments().last() {
+        Some(raw) if raw.as_str().starts_with("did:") => raw.as_str().replace("%23", "#"),
+        _ => did.to_string(),
+    };
+    let did_url = DIDURL::new(
+        &did,
+        uri.query()
+            .map(|query| query.segments())
+            .into_iter()
+            .flatten(),
+    );
+    let result = resolve_did(config, &did_url.did).await;
+    let status = result.status();
+    tracing::debug!(
+        request_id = %request_id,
+        did = %did_url.did,
+        status = status.code,
+        error = result.did_resolution_metadata.error.as_deref(),
+        "DID resolved"
+    );
+
+    if !did_url.is_did() {
+        return match (&result.did_resolution_metadata.error, &result.did_document) {
+            (None, Some(document)) => match dereference(&did_url, document) {
+                Ok(Dereferenced::Resource(resource)) => {
+                    ResolutionResponse::json(Status::Ok, ContentType::JSON, &resource)
+                }
+                Ok(Dereferenced::ServiceEndpoint(url)) => {
+                    ResolutionResponse::Redirect(Redirect::to(url))
+                }
+                Err(error) => {
+                    ResolutionResponse::dereferencing_error(dereferencing_status(error), error)
+                }
+            },
+            (Some(error), _) => ResolutionResponse::dereferencing_error(status, error),
+            (None, None) => {
+                ResolutionResponse::dereferencing_error(Status::NotFound, ERROR_NOT_FOUND)
+            }
+        };
+    }
+
+    let document_only = accept
+        .map(|accept| accept.preferred().media_type())
+        .map(|media_type| media_type.sub() == "did+ld+json" || media_type.sub() == "did+json")
+        .unwrap_or(false);
+    if document_only {
+        match &result.did_document {
+            Some(document) => {
+                ResolutionResponse::json(status, DIDContentTypes::DID_LD_JSON, document)
+            }
+            None => ResolutionResponse::Content((status, (ContentType::JSON, String::new()))),
+        }
+    } else {
+        ResolutionResponse::json(status, DIDContentTypes::DID_RESOLUTION, &result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Policy;
+    use crate::resolution::*;
+
+    fn document() -> Document {
+        serde_json::from_value(json!({
+            "@context": "https://www.w3.org/ns/did/v1",
+            "id": "did:web:example.com:alice",
+            "verificationMethod": [{
+                "id": "did:web:example.com:alice#key-1",
+                "type": "JsonWebKey2020",
+                "controller": "did:web:example.com:alice",
+                "publicKeyJwk": { "kty": "OKP", "crv": "Ed25519", "x": "Cd9QKXnQyYxIbjdDLvRb6aCqJQ_Ec2wp0W9ag1VvW3c" }
+            }],
+            "service": [{
+                "id": "#files",
+                "type": "LinkedDomains",
+                "serviceEndpoint": "https://files.example.com/alice/"
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn test_store_resolver() {
+        let config = Config {
+            external_hostname: "example.com".to_string(),
+            external_port: "443".to_string(),
+            ..Config::default()
+        };
+        config
+            .store
+            .create(&std::path::PathBuf::from("alice/did.json"), document())
+            .await
+            .unwrap();
+        let resolver = StoreResolver { config: &config };
+        let (metadata, document, _) = resolver
+            .resolve(
+                "did:web:example.com:alice",
+                &ResolutionInputMetadata::default(),
+            )
+            .await;
+        assert!(
+            metadata.error.is_none() && document.is_some(),
+            "When a locally hosted DID is resolved, then the DID document is read from the store"
+        );
+        let (metadata, _, _) = resolver
+            .resolve(
+                "did:web:example.com:bob",
+                &ResolutionInputMetadata::default(),
+            )
+            .await;
+        assert_eq!(
+            metadata.error.as_deref(),
+            Some(ERROR_NOT_FOUND),
+            "When a locally hosted DID doesn't exist, then notFound is returned"
+        );
+        let (metadata, _, _) = resolver
+            .resolve(
+                "did:web:example.org:alice",
+                &ResolutionInputMetadata::default(),
+            )
+            .await;
+        assert_eq!(
+            metadata.error.as_deref(),
+            Some(ERROR_METHOD_NOT_SUPPORTED),
+            "When a DID isn't hosted by this server, then the next resolver is used"
+        );
+    }
+
+    #[test]
+    fn test_resolution_allowed() {
+        let config = Config {
+            external_hostname: "example.com".to_string(),
+            external_port: "443".to_string(),
+            policy: Policy {
+                resolver_allowed_hosts: vec![
+                    "example.org".to_string(),
+                    "*.example.net".to_string(),
+                ],
+                ..Policy::default()
+            }
+            .into(),
+            ..Config::default()
+        };
+        for (did, allowed) in [
+            ("did:web:example.com:alice", true),
+            ("did:web:example.org:alice", true),
+            ("did:web:id.example.net:alice", true),
+            ("did:web:example.net:alice", false),
+            ("did:web:badexample.net:alice", false),
+            ("did:web:example.info:alice", false),
+        ] {
+            assert_eq!(
+                resolution_allowed(&config, &DIDWeb::from_str(did).unwrap()),
+                allowed,
+                "When <did> is '{}', then its resolution is allowed: {}",
+                did,
+                allowed
+            );
+        }
+
+        let config = Config {
+            policy: Policy {
+                resolver_allowed_hosts: vec!["169.254.169.254".to_string()],
+                ..Policy::default()
+            }
+            .into(),
+            ..Config::default()
+        };
+        assert!(
+            !resolution_allowed(
+                &config,
+                &DIDWeb::from_str("did:web:169.254.169.254:latest").unwrap()
+            ),
+            "When an internal host is allowed, then it's still rejected"
+        );
+    }
+
+    #[test]
+    fn test_did_url() {
+        let did_url = DIDURL::new(
+            "did:web:example.com:alice#key-1",
+            [
+                ("service", "files"),
+                ("relativeRef", "/x"),
+                ("versionId", "1"),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(
+            did_url,
+            DIDURL {
+                did: "did:web:example.com:alice".to_string(),
+                fragment: Some("key-1".to_string()),
+                service: Some("files".to_string()),
+                relative_ref: Some("/x".to_string()),
+            },
+            "When a DID URL is parsed, then the fragment and parameters are separated from the DID"
+        );
+        assert!(DIDURL::new("did:web:example.com:alice", std::iter::empty()).is_did());
+    }
+
+    #[test]
+    fn test_dereference() {
+        let document = document();
+        let result = dereference(
+            &DIDURL::new("did:web:example.com:alice#key-1", std::iter::empty()),
+            &document,
+        );
+        match result {
+            Ok(Dereferenced::Resource(resource)) => {
+                assert_eq!(resource["type"], "JsonWebKey2020");
+                assert_eq!(
+                    resource["@context"], "https://www.w3.org/ns/did/v1",
+                    "When a resource is dereferenced, then the document's context is added"
+                );
+            }
+            result => panic!(
+                "When a fragment is dereferenced, then the resource is returned: {:?}",
+                result
+            ),
+        }
+
+        assert_eq!(
+            dereference(
+                &DIDURL::new(
+                    "did:web:example.com:alice",
+                    [("service", "files"), ("relativeRef", "/x")].into_iter()
+                ),
+                &document
+            ),
+            Ok(Dereferenced::ServiceEndpoint("https://files.example.com/x".to_string())),
+            "When a service with relative reference is dereferenced, then the reference is resolved against the endpoint"
+        );
+        assert_eq!(
+            dereference(
+                &DIDURL::new(
+                    "did:web:example.com:alice#top",
+                    [("service", "files"), ("relativeRef", "docs/a")].into_iter()
+                ),
+                &document
+            ),
+            Ok(Dereferenced::ServiceEndpoint(
+                "https://files.example.com/alice/docs/a#top".to_string()
+            )),
+            "When a fragment is present, then it's appended to the service endpoint"
+        );
+        assert_eq!(
+            dereference(
+                &DIDURL::new("did:web:example.com:alice#key-2", std::iter::empty()),
+                &document
+            ),
+            Err(ERROR_NOT_FOUND),
+            "When the fragment doesn't exist, then notFound is returned"
+        );
+        assert_eq!(
+            dereference(
+                &DIDURL::new(
+                    "did:web:example.com:alice",
+                    [("relativeRef", "/x")].into_iter()
+                ),
+                &document
+            ),
+            Err(ERROR_INVALID_DID_URL),
+            "When relativeRef is used without service, then invalidDidUrl is returned"
+        );
+    }
+}
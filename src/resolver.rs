@@ -27,6 +27,11 @@ lazy_static! {
     };
 }
 
+/// DID methods that the server resolves without relying on an external resolver, i.e. did:key, did:jwk and did:web.
+pub fn did_methods() -> &'static DIDMethods<'static> {
+    &DID_METHODS
+}
+
 impl ResolverOptions {
     pub fn get_resolver(&self) -> SeriesResolver {
         let mut resolvers = vec![DID_METHODS.to_resolver()];
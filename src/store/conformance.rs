@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+//! Conformance test suite for implementations of [`DIDWebStore`]. Backends that are maintained outside of this crate
+//! run it in their own tests:
+//!
+//! ```ignore
+//! #[rocket::async_test]
+//! async fn test_conformance() {
+//!     did_web_server::conformance::check_store(&MyStore::new()).await;
+//! }
+//! ```
+//!
+//! The suite panics with a description of the violated semantics. It only touches ids below `conformance/`, so it can
+//! be run against a store that contains other DID documents.
+
+use serde_json::{json, Value};
+use ssi::did::Document;
+use std::path::PathBuf;
+
+use crate::error::DIDError;
+use crate::store::DIDWebStore;
+
+/// Builds a DID document that can be told apart by its `alsoKnownAs` entry.
+fn document(did: &str, version: u32) -> Document {
+    serde_json::from_value(json!({
+        "@context": "https://www.w3.org/ns/did/v1",
+        "id": did,
+        "alsoKnownAs": [format!("https://example.com/version/{}", version)]
+    }))
+    .unwrap()
+}
+
+/// Converts the result of an operation into JSON so that documents can be compared.
+fn json(result: Result<Document, DIDError>) -> Option<Value> {
+    result.ok().and_then(|doc| serde_json::to_value(doc).ok())
+}
+
+/// Runs all checks of the conformance suite against a store.
+///
+/// - `store` - store under test
+pub async fn check_store(store: &(dyn DIDWebStore + Sync + Send)) {
+    check_check(store).await;
+    check_create(store).await;
+    check_update(store).await;
+    check_remove(store).await;
+    check_list(store).await;
+    check_reserve(store).await;
+    check_next_sequence(store).await;
+}
+
+/// The store is accessible.
+pub async fn check_check(store: &(dyn DIDWebStore + Sync + Send)) {
+    assert!(
+        store.check().await.is_ok(),
+        "When the store is accessible, then the check succeeds"
+    );
+}
+
+/// Documents are created once and can be retrieved afterwards.
+pub async fn check_create(store: &(dyn DIDWebStore + Sync + Send)) {
+    let id = PathBuf::from("conformance/create/did.json");
+    let did = "did:web:localhost:conformance:create";
+    assert!(
+        matches!(store.get(&id).await, Err(DIDError::DIDNotFound(_))),
+        "When <id> is not in store, then DIDNotFound is returned"
+    );
+    assert_eq!(
+        json(store.create(&id, document(did, 1)).await),
+        json(Ok(document(did, 1))),
+        "When <id> is created, then the new document is returned"
+    );
+    assert_eq!(
+        json(store.get(&id).await),
+        json(Ok(document(did, 1))),
+        "When <id> has been created, then the document is retrieved"
+    );
+    assert!(
+        matches!(
+            store.create(&id, document(did, 2)).await,
+            Err(DIDError::DIDExists(_))
+        ),
+        "When <id> is created again, then DIDExists is returned"
+    );
+    assert_eq!(
+        json(store.get(&id).await),
+        json(Ok(document(did, 1))),
+        "When <id> is created again, then the stored document stays unchanged"
+    );
+    store.remove(&id).await.unwrap();
+}
+
+/// Only existing documents are updated and the previous version is returned.
+pub async fn check_update(store: &(dyn DIDWebStore + Sync + Send)) {
+    let id = PathBuf::from("conformance/update/did.json");
+    let did = "did:web:localhost:conformance:update";
+    assert!(
+        matches!(
+            store.update(&id, document(did, 1)).await,
+            Err(DIDError::DIDNotFound(_))
+        ),
+        "When <id> is not in store and updated, then DIDNotFound is returned"
+    );
+    assert!(
+        matches!(store.get(&id).await, Err(DIDError::DIDNotFound(_))),
+        "When <id> is not in store and updated, then it isn't created"
+    );
+    store.create(&id, document(did, 1)).await.unwrap();
+    assert_eq!(
+        json(store.update(&id, document(did, 2)).await),
+        json(Ok(document(did, 1))),
+        "When <id> is updated, then the old document is returned"
+    );
+    assert_eq!(
+        json(store.get(&id).await),
+        json(Ok(document(did, 2))),
+        "When <id> has been updated, then the new document is retrieved"
+    );
+    store.remove(&id).await.unwrap();
+}
+
+/// Only existing documents are removed and the removed version is returned.
+pub async fn check_remove(store: &(dyn DIDWebStore + Sync + Send)) {
+    let id = PathBuf::from("conformance/remove/did.json");
+    let did = "did:web:localhost:conformance:remove";
+    assert!(
+        matches!(store.remove(&id).await, Err(DIDError::DIDNotFound(_))),
+        "When <id> is not in store and removed, then DIDNotFound is returned"
+    );
+    store.create(&id, document(did, 1)).await.unwrap();
+    assert_eq!(
+        json(store.remove(&id).await),
+        json(Ok(document(did, 1))),
+        "When <id> is removed, then the old document is returned"
+    );
+    assert!(
+        matches!(store.get(&id).await, Err(DIDError::DIDNotFound(_))),
+        "When <id> has been removed, then DIDNotFound is returned"
+    );
+    assert!(
+        store.create(&id, document(did, 2)).await.is_ok(),
+        "When <id> has been removed, then it can be created again"
+    );
+    store.remove(&id).await.unwrap();
+}
+
+/// The ids of all stored documents are listed.
+pub async fn check_list(store: &(dyn DIDWebStore + Sync + Send)) {
+    let ids = [
+        PathBuf::from("conformance/list/did.json"),
+        PathBuf::from("conformance/list/nested/did.json"),
+    ];
+    for (i, id) in ids.iter().enumerate() {
+        let did = format!("did:web:localhost:conformance:list:{}", i);
+        store.create(id, document(&did, 1)).await.unwrap();
+    }
+    let listed = store.list().await.unwrap();
+    assert!(
+        ids.iter().all(|id| listed.contains(id)),
+        "When documents are stored at <id>, then all ids are listed"
+    );
+    for id in ids.iter() {
+        store.remove(id).await.unwrap();
+    }
+    let listed = store.list().await.unwrap();
+    assert!(
+        ids.iter().all(|id| !listed.contains(id)),
+        "When documents have been removed, then their ids aren't listed anymore"
+    );
+}
+
+/// Keys are reserved once until they're released. The check is skipped for backends that don't support reservations
+/// and keep the default implementation, which returns StoreUnavailable.
+pub async fn check_reserve(store: &(dyn DIDWebStore + Sync + Send)) {
+    let key = "conformance-reserve";
+    let _ = store.release(key).await;
+    match store.reserve(key, "{}").await {
+        Err(DIDError::StoreUnavailable(_)) => return,
+        result => assert!(
+            result.unwrap(),
+            "When <key> isn't reserved, then it's reserved"
+        ),
+    }
+    assert!(
+        !store.reserve(key, "{}").await.unwrap(),
+        "When <key> is reserved again, then the reservation fails"
+    );
+    assert!(
+        !store
+            .list()
+            .await
+            .unwrap()
+            .iter()
+            .any(|id| id.ends_with(key)),
+        "When <key> is reserved, then it isn't listed as document"
+    );
+    store.release(key).await.unwrap();
+    assert!(
+        store.reserve(key, "{}").await.unwrap(),
+        "When <key> has been released, then it can be reserved again"
+    );
+    store.release(key).await.unwrap();
+}
+
+/// Sequence numbers, if the store allocates them, increase with every allocation.
+pub async fn check_next_sequence(store: &(dyn DIDWebStore + Sync + Send)) {
+    let first = store.next_sequence().await.unwrap();
+    let second = store.next_sequence().await.unwrap();
+    if let (Some(first), Some(second)) = (first, second) {
+        assert!(
+            second > first,
+            "When sequence numbers are allocated, then they increase"
+        );
+    } else {
+        assert_eq!(
+            (first, second),
+            (None, None),
+            "When the store doesn't allocate sequence numbers, then it never does"
+        );
+    }
+}
@@ -1,25 +1,66 @@
 // SPDX-License-Identifier: AGPL-3.0
 
-use std::io::prelude::*;
-
+use async_trait::async_trait;
+use rocket::figment::Figment;
+use rocket::tokio::fs::{self, OpenOptions};
+use rocket::tokio::io::AsyncWriteExt;
+use rocket::tokio::sync::Mutex as AsyncMutex;
+use rocket::tokio::task;
 use ssi::did::Document;
-use std::fs;
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use crate::config::{default_data_dir, Settings};
 use crate::{error::DIDError, store::DIDWebStore};
 
+/// Name of the directory in the data directory that reservations are written to.
+static RESERVATIONS_DIR: &str = "reservations";
+
+/// Counter that makes the names of temporary files unique within the process.
+static NEXT_TEMPORARY_FILE: AtomicU64 = AtomicU64::new(1);
+
+/// Stores DID documents as files. Documents are written to a temporary file in the same directory first and then moved
+/// into place, so that readers never see partially written documents.
+///
+/// * `directory` - directory that the DID documents are stored in
+/// * `reservations` - directory that reservations are written to, one file per key. It's kept apart from the DID
+///   documents, which are often served as static web root.
+/// * `writer` - serializes the changes of documents, so that a document isn't recreated by an update while it's removed
 #[derive(Debug)]
 pub struct FileStore {
     directory: PathBuf,
+    reservations: PathBuf,
+    writer: AsyncMutex<()>,
 }
 
 impl FileStore {
     pub fn new(directory: String) -> Self {
         FileStore {
             directory: PathBuf::from(directory),
-            // ..FileStore::default()
+            ..FileStore::default()
         }
     }
+
+    /// Creates the store in the directory that's set via DWS_BACKEND_FILE_STORE, by default `$PWD/did_store/`.
+    /// Reservations are kept in the directory that's set via DWS_DATA_DIR, by default `$PWD/dws_data/`.
+    pub fn from_figment(figment: &Figment) -> Result<Self, DIDError> {
+        let settings = Settings::extract(figment)?;
+        let store = settings
+            .backend_file_store
+            .map(|directory| FileStore {
+                directory,
+                ..FileStore::default()
+            })
+            .unwrap_or_default();
+        Ok(match settings.data_dir {
+            Some(data_dir) => FileStore {
+                reservations: data_dir.join(RESERVATIONS_DIR),
+                ..store
+            },
+            None => store,
+        })
+    }
 }
 
 impl Default for FileStore {
@@ -31,138 +72,233 @@ impl Default for FileStore {
                     .map(|val| val.join("did_store").to_str().unwrap_or(".").to_string())
                     .unwrap_or_else(|_| ".".to_string()),
             ),
+            reservations: default_data_dir().join(RESERVATIONS_DIR),
+            writer: AsyncMutex::new(()),
         }
     }
 }
 
+#[async_trait]
 impl DIDWebStore for FileStore {
-    // fn exists(&self, id: &Path) -> bool {
-    //     match id2filename(&self.directory, id) {
-    //         Ok(filename) => filename.exists(),
-    //         Err(_) => false,
-    //     }
-    // }
-
-    fn get(&self, id: &Path) -> Result<Document, DIDError> {
-        id2filename(&self.directory, id)
-            // .map(|f| {
-            //     f.to_str().map(log("path"));
-            //     f
-            // })
-            .map_err(|e| DIDError::DIDNotFound(e.to_string()))
-            // TODO: use let doc = match Document::from_json(include_str!("../tests/did-example-foo.json")) {
-            .and_then(|filename| {
-                if filename.exists() {
-                    Ok(filename)
-                } else {
-                    Err(DIDError::DIDNotFound("DID not found".to_string()))
-                }
-            })
-            // .map(|f| {
-            //     f.to_str().map(log("path"));
-            //     f
-            // })
-            .and_then(|filename| {
-                fs::read(filename).map_err(|e| DIDError::NoFileRead(e.to_string()))
-            })
-            .and_then(|b| {
-                String::from_utf8(b).map_err(|e| DIDError::ContentConversion(e.to_string()))
-            })
+    async fn get(&self, id: &Path) -> Result<Document, DIDError> {
+        let filename =
+            id2filename(&self.directory, id).map_err(|e| DIDError::DIDNotFound(e.to_string()))?;
+        let content = fs::read(filename).await.map_err(|e| match e.kind() {
+            ErrorKind::NotFound => DIDError::DIDNotFound("DID not found".to_string()),
+            _ => DIDError::NoFileRead(e.to_string()),
+        })?;
+        String::from_utf8(content)
+            .map_err(|e| DIDError::ContentConversion(e.to_string()))
             .and_then(|ref s| {
                 serde_json::from_str::<Document>(s)
                     .map_err(|e| DIDError::ContentConversion(e.to_string()))
             })
     }
 
-    fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
-        let did = doc.id.to_string();
-        self.store_diddoc(id, doc, |filename| {
-            if filename.exists() {
-                Err(DIDError::DIDExists(format!("DID already exists: {}", did)))
-            } else {
-                Ok(filename)
+    async fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        let _writer = self.writer.lock().await;
+        let filename =
+            id2filename(&self.directory, id).map_err(|e| DIDError::NoFileName(e.to_string()))?;
+        let temporary = FileStore::write_temporary(&filename, &doc).await?;
+        // linking fails atomically if the file exists, so concurrent creations can't overwrite each other
+        let result = fs::hard_link(&temporary, &filename)
+            .await
+            .map_err(|e| match e.kind() {
+                ErrorKind::AlreadyExists => {
+                    DIDError::DIDExists(format!("DID already exists: {}", doc.id))
+                }
+                _ => DIDError::NoFileWrite(e.to_string()),
+            });
+        let _ = fs::remove_file(&temporary).await;
+        result.map(|_| doc)
+    }
+
+    async fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        let _writer = self.writer.lock().await;
+        let document = self.get(id).await?; // WARNING: potential early return!
+        let filename =
+            id2filename(&self.directory, id).map_err(|e| DIDError::NoFileName(e.to_string()))?;
+        let temporary = FileStore::write_temporary(&filename, &doc).await?;
+        // renaming replaces the document atomically, readers either see the old or the new version
+        match fs::rename(&temporary, &filename).await {
+            Ok(()) => Ok(document),
+            Err(e) => {
+                let _ = fs::remove_file(&temporary).await;
+                Err(match e.kind() {
+                    ErrorKind::NotFound => DIDError::DIDNotFound("DID doesn't exist".to_string()),
+                    _ => DIDError::NoFileWrite(e.to_string()),
+                })
             }
-        })
+        }
     }
 
-    fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
-        self.store_diddoc(id, doc, |filename| Ok(filename))
+    async fn remove(&self, id: &Path) -> Result<Document, DIDError> {
+        let _writer = self.writer.lock().await;
+        let document = self.get(id).await?; // WARNING: potential early return!
+        let filename =
+            id2filename(&self.directory, id).map_err(|e| DIDError::NoFileName(e.to_string()))?;
+        // Delete file that stores DID doc
+        fs::remove_file(filename)
+            .await
+            .map_err(|e| match e.kind() {
+                ErrorKind::NotFound => DIDError::DIDNotFound("DID doesn't exist".to_string()),
+                _ => DIDError::NoFileWrite(e.to_string()),
+            })?;
+        Ok(document)
     }
 
-    fn remove(&self, id: &Path) -> Result<Document, DIDError> {
-        let document = self.get(id)?; // WARNING: potential early return!
-        id2filename(&self.directory, id)
-            .map_err(|e| DIDError::NoFileName(e.to_string()))
-            .and_then(|filename| {
-                if filename.exists() {
-                    Ok(filename)
-                } else {
-                    Err(DIDError::DIDNotFound("DID doesn't exist".to_string()))
-                }
-            })
-            // Delete file that stores DID doc
-            .and_then(|filename| {
-                std::fs::remove_file(filename).map_err(|e| DIDError::NoFileWrite(e.to_string()))
+    async fn list(&self) -> Result<Vec<PathBuf>, DIDError> {
+        let directory = self.directory.clone();
+        // walking the directory tree is done on the blocking thread pool
+        task::spawn_blocking(move || {
+            if !directory.exists() {
+                return Ok(vec![]);
+            }
+            list_diddocs(&directory).map(|filenames| {
+                filenames
+                    .iter()
+                    .filter_map(|filename| filename.strip_prefix(&directory).ok())
+                    .map(|id| id.to_path_buf())
+                    .collect()
             })
-            .map(|_| document.to_owned())
+        })
+        .await
+        .map_err(|e| DIDError::NoFileRead(e.to_string()))?
+    }
+
+    async fn check(&self) -> Result<(), DIDError> {
+        // the directory of the DID documents is often served as static web root, so the probe is written to the
+        // reservations directory and the documents' directory is only inspected
+        fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|e| DIDError::NoFileWrite(e.to_string()))?;
+        fs::read_dir(&self.directory)
+            .await
+            .map_err(|e| DIDError::NoFileRead(e.to_string()))?;
+        let metadata = fs::metadata(&self.directory)
+            .await
+            .map_err(|e| DIDError::NoFileRead(e.to_string()))?;
+        if metadata.permissions().readonly() {
+            return Err(DIDError::NoFileWrite(format!(
+                "Directory is read-only: {}",
+                self.directory.display()
+            )));
+        }
+        let probe = self.reservations.join(".dws-check");
+        fs::create_dir_all(&self.reservations)
+            .await
+            .map_err(|e| DIDError::NoFileWrite(e.to_string()))?;
+        fs::write(&probe, b"")
+            .await
+            .map_err(|e| DIDError::NoFileWrite(e.to_string()))?;
+        fs::read(&probe)
+            .await
+            .map_err(|e| DIDError::NoFileRead(e.to_string()))?;
+        fs::remove_file(&probe)
+            .await
+            .map_err(|e| DIDError::NoFileWrite(e.to_string()))
+    }
+
+    async fn reserve(&self, key: &str, value: &str) -> Result<bool, DIDError> {
+        fs::create_dir_all(&self.reservations)
+            .await
+            .map_err(|e| DIDError::NoFileWrite(e.to_string()))?;
+        let filename = self.reservations.join(key);
+        // creating the file fails atomically if it exists, so concurrent reservations can't both succeed
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&filename)
+            .await;
+        let mut file = match file {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => return Ok(false),
+            Err(e) => return Err(DIDError::NoFileWrite(e.to_string())),
+        };
+        let write = async {
+            file.write_all(value.as_bytes()).await?;
+            file.sync_all().await
+        };
+        match write.await {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                let _ = fs::remove_file(&filename).await;
+                Err(DIDError::NoFileWrite(e.to_string()))
+            }
+        }
+    }
+
+    async fn release(&self, key: &str) -> Result<(), DIDError> {
+        match fs::remove_file(self.reservations.join(key)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(DIDError::NoFileWrite(e.to_string())),
+            _ => Ok(()),
+        }
     }
 }
 
 impl FileStore {
-    /// Persisently stores DID Document
+    /// Writes a DID Document to a new temporary file next to the file that stores it. The parent directory is created
+    /// if it doesn't exist. The temporary file doesn't end in `did.json`, so it's never listed as DID document.
     ///
-    /// - `config` - Global configuration.
-    /// - `id` - id part of the did:web method as specified in https://w3c-ccg.github.io/did-method-web/
+    /// - `filename` - file that stores the DID Document, see [`id2filename`]
     /// - `doc` - DID Document.
-    /// - `contraints_op` - Call contraints_op with the computed file name. contraints_op returns Ok if the update / creation can continue.
-    /// - returns size of bytes written or an error
-    fn store_diddoc<F: FnOnce(&PathBuf) -> Result<&PathBuf, DIDError>>(
-        &self,
-        id: &Path,
-        doc: Document,
-        contraints_op: F,
-    ) -> Result<Document, DIDError> {
-        id2filename(&self.directory, id)
-            .map_err(|e| DIDError::NoFileName(e.to_string()))
-            .and_then(|filename| match contraints_op(&filename) {
-                Ok(_) => Ok(filename),
-                Err(e) => Err(e),
-            })
-            .and_then(|filename| {
-                // Create parent directory if it doesn't exist
-                println!("fn: {:?}", filename.to_str());
-                match filename.parent() {
-                    Some(parent) => {
-                        if !parent.exists() {
-                            return std::fs::create_dir_all(parent)
-                                .map_err(|e| DIDError::NoFileWrite(e.to_string()))
-                                .map(|()| filename);
-                        }
-                        Ok(filename)
-                    }
-                    None => Err(DIDError::NoFileWrite(
-                        "Unable to obtain directory for storing DID document in".to_string(),
-                    )),
-                }
-            })
-            // Store DID document in file
-            .and_then(|filename| {
-                // TODO: externalize into a separate function store_did_doc
-                std::fs::File::create(filename)
-                    .map_err(|e| DIDError::NoFileWrite(e.to_string()))
-                    .and_then(|mut f| {
-                        serde_json::to_string(&doc)
-                            .map_err(|e| DIDError::ContentConversion(e.to_string()))
-                            .and_then(|s| {
-                                f.write(s.as_bytes())
-                                    .map_err(|e| DIDError::NoFileWrite(e.to_string()))
-                            })
-                            .map(|_| doc)
-                    })
-            })
+    /// - returns the path of the temporary file or an error if the document couldn't be written
+    async fn write_temporary(filename: &Path, doc: &Document) -> Result<PathBuf, DIDError> {
+        let s =
+            serde_json::to_string(doc).map_err(|e| DIDError::ContentConversion(e.to_string()))?;
+        // Create parent directory if it doesn't exist
+        let parent = filename.parent().ok_or_else(|| {
+            DIDError::NoFileWrite(
+                "Unable to obtain directory for storing DID document in".to_string(),
+            )
+        })?;
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| DIDError::NoFileWrite(e.to_string()))?;
+        let temporary = parent.join(format!(
+            ".did.json.{}.{}.tmp",
+            std::process::id(),
+            NEXT_TEMPORARY_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let write = async {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temporary)
+                .await?;
+            file.write_all(s.as_bytes()).await?;
+            // the content must be on disk before the file is moved into place, otherwise a crash leaves an empty file
+            file.sync_all().await
+        };
+        match write.await {
+            Ok(()) => Ok(temporary),
+            Err(e) => {
+                let _ = fs::remove_file(&temporary).await;
+                Err(DIDError::NoFileWrite(e.to_string()))
+            }
+        }
     }
 }
 
+/// Recursively collects all DID document files below a directory.
+///
+/// @param directory - Directory to search for DID documents
+fn list_diddocs(directory: &Path) -> Result<Vec<PathBuf>, DIDError> {
+    std::fs::read_dir(directory)
+        .map_err(|e| DIDError::NoFileRead(e.to_string()))?
+        .try_fold(vec![], |mut filenames, entry| {
+            let path = entry
+                .map_err(|e| DIDError::NoFileRead(e.to_string()))?
+                .path();
+            if path.is_dir() {
+                filenames.append(&mut list_diddocs(&path)?);
+            } else if path.file_name().and_then(|f| f.to_str()) == Some("did.json") {
+                filenames.push(path);
+            }
+            Ok(filenames)
+        })
+}
+
 /// Computes the absolute path to a file with json extension in a base
 /// direcotory and an ID.
 ///
@@ -299,4 +435,59 @@ mod test {
             }
         }
     }
+
+    #[rocket::async_test]
+    async fn test_atomic_writes() {
+        let directory = std::env::temp_dir().join(format!("dws-atomic-{}", std::process::id()));
+        let store = FileStore::new(directory.to_str().unwrap().to_string());
+        let id = PathBuf::from("alice/did.json");
+        let doc = serde_json::from_str::<Document>(
+            &std::fs::read_to_string("./src/__fixtures__/valid-did.json").unwrap(),
+        )
+        .unwrap();
+        store.create(&id, doc.clone()).await.unwrap();
+        store.update(&id, doc.clone()).await.unwrap();
+        assert_eq!(
+            std::fs::read_dir(directory.join("alice"))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_str().unwrap().to_string())
+                .collect::<Vec<String>>(),
+            vec!["did.json".to_string()],
+            "When a document is created and updated, then no temporary files are left behind"
+        );
+        store.remove(&id).await.unwrap();
+        assert!(
+            matches!(store.update(&id, doc).await, Err(DIDError::DIDNotFound(_))),
+            "When a removed document is updated, then DIDNotFound is returned"
+        );
+        let _ = std::fs::remove_dir_all(directory);
+    }
+
+    #[rocket::async_test]
+    async fn test_check() {
+        let directory = std::env::temp_dir().join(format!("dws-check-{}", std::process::id()));
+        let store = FileStore {
+            reservations: directory.join("reservations"),
+            ..FileStore::new(directory.join("dids").to_str().unwrap().to_string())
+        };
+        store.check().await.unwrap();
+        assert_eq!(
+            std::fs::read_dir(directory.join("dids")).unwrap().count(),
+            0,
+            "When the store is checked, then nothing is written to the directory of the DID documents"
+        );
+        let _ = std::fs::remove_dir_all(directory);
+    }
+
+    #[rocket::async_test]
+    async fn test_conformance() {
+        let directory =
+            std::env::temp_dir().join(format!("dws-conformance-{}", std::process::id()));
+        crate::store::conformance::check_store(&FileStore {
+            reservations: directory.join("reservations"),
+            ..FileStore::new(directory.join("dids").to_str().unwrap().to_string())
+        })
+        .await;
+        let _ = std::fs::remove_dir_all(directory);
+    }
 }
@@ -1,8 +1,10 @@
 // SPDX-License-Identifier: AGPL-3.0
 
+use async_trait::async_trait;
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use ssi::did::Document;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::error::DIDError;
 
@@ -13,6 +15,7 @@ use crate::utils::path_to_string;
 pub struct MemStore {
     // store: HashMap<String, Document>,
     store: DashMap<String, Document>,
+    reservations: DashMap<String, String>,
 }
 
 impl MemStore {
@@ -27,59 +30,86 @@ impl Default for MemStore {
     fn default() -> Self {
         Self {
             store: DashMap::new(),
+            reservations: DashMap::new(),
         }
     }
 }
 
 impl MemStore {
     fn id_to_string(id: &Path) -> String {
-        path_to_string(id, ":")
+        // absolute and relative ids refer to the same document, like in the other stores
+        path_to_string(id.strip_prefix("/").unwrap_or(id), ":")
     }
 }
 
+#[async_trait]
 impl DIDWebStore for MemStore {
-    // fn exists(&self, id: &Path) -> bool {
-    //     self.store.get(&MemStore::id_to_string(id)).is_some()
-    // }
-
-    fn get(&self, id: &Path) -> Result<Document, DIDError> {
+    async fn get(&self, id: &Path) -> Result<Document, DIDError> {
         self.store
             .get(&MemStore::id_to_string(id))
             .map(|doc| doc.to_owned())
             .ok_or_else(|| DIDError::DIDNotFound("DID not found".to_string()))
     }
 
-    fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
-        if self.store.get(&MemStore::id_to_string(id)).is_some() {
-            Err(DIDError::DIDExists(format!(
+    async fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        // the entry locks its shard, so concurrent creations of the same id can't both succeed
+        match self.store.entry(MemStore::id_to_string(id)) {
+            Entry::Occupied(_) => Err(DIDError::DIDExists(format!(
                 "DID already exists: {}",
                 doc.id
-            )))
-        } else {
-            self.store.insert(MemStore::id_to_string(id), doc);
-            self.get(id)
+            ))),
+            Entry::Vacant(entry) => Ok(entry.insert(doc).value().to_owned()),
         }
     }
 
-    fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
-        if self.store.get(&MemStore::id_to_string(id)).is_none() {
-            Err(DIDError::DIDNotFound("DID not found".to_string()))
-        } else {
-            self.store
-                .insert(MemStore::id_to_string(id), doc)
-                .ok_or_else(
-                    // TODO: this should never be reached
-                    || DIDError::DIDNotFound("DID not found".to_string()),
-                )
+    async fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        match self.store.entry(MemStore::id_to_string(id)) {
+            Entry::Occupied(mut entry) => Ok(entry.insert(doc)),
+            Entry::Vacant(_) => Err(DIDError::DIDNotFound("DID not found".to_string())),
         }
     }
 
-    fn remove(&self, id: &Path) -> Result<Document, DIDError> {
+    async fn remove(&self, id: &Path) -> Result<Document, DIDError> {
         self.store
             .remove(&MemStore::id_to_string(id))
             .map(|(_, v)| v)
             .ok_or_else(|| DIDError::DIDNotFound("DID not found".to_string()))
     }
+
+    async fn list(&self) -> Result<Vec<PathBuf>, DIDError> {
+        Ok(self
+            .store
+            .iter()
+            .map(|entry| {
+                entry
+                    .key()
+                    .split(':')
+                    .filter(|segment| *segment != "/")
+                    .collect::<PathBuf>()
+            })
+            .collect())
+    }
+
+    async fn check(&self) -> Result<(), DIDError> {
+        // memory is always accessible
+        Ok(())
+    }
+
+    async fn reserve(&self, key: &str, value: &str) -> Result<bool, DIDError> {
+        // the entry locks its shard, so concurrent reservations can't both insert the key
+        match self.reservations.entry(key.to_string()) {
+            Entry::Occupied(_) => Ok(false),
+            Entry::Vacant(entry) => {
+                entry.insert(value.to_string());
+                Ok(true)
+            }
+        }
+    }
+
+    async fn release(&self, key: &str) -> Result<(), DIDError> {
+        self.reservations.remove(key);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -90,210 +120,270 @@ mod test {
 
     use crate::store::mem::*;
 
-    #[test]
-    fn test_id_not_in_store() {
+    #[rocket::async_test]
+    async fn test_id_not_in_store() {
         let store = MemStore::default();
         let id = PathBuf::from("an/id");
-        let result = store.get(&id);
+        let result = store.get(&id).await;
         assert!(
             result.is_err(),
             "When <id> is not in store, then an error is returned"
         );
     }
 
-    #[test]
-    fn test_add_id_to_store() {
+    #[rocket::async_test]
+    async fn test_add_id_to_store() {
         let store = MemStore::default();
         let id = PathBuf::from("an/id");
-        let result = store.create(
-            &id,
-            Document {
-                context: ssi::did::Contexts::One(Context::URI(
-                    iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
-                )),
-                id: "did:my:did".to_string(),
-                also_known_as: None,
-                controller: None,
-                verification_method: None,
-                authentication: None,
-                assertion_method: None,
-                key_agreement: None,
-                capability_invocation: None,
-                capability_delegation: None,
-                public_key: None,
-                service: None,
-                proof: None,
-                property_set: None,
-            },
-        );
+        let result = store
+            .create(
+                &id,
+                Document {
+                    context: ssi::did::Contexts::One(Context::URI(
+                        iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
+                    )),
+                    id: "did:my:did".to_string(),
+                    also_known_as: None,
+                    controller: None,
+                    verification_method: None,
+                    authentication: None,
+                    assertion_method: None,
+                    key_agreement: None,
+                    capability_invocation: None,
+                    capability_delegation: None,
+                    public_key: None,
+                    service: None,
+                    proof: None,
+                    property_set: None,
+                },
+            )
+            .await;
         assert!(
             result.is_ok(),
             "When an <id> document is put in the store and <id> isn't present in store, then the document is stored at id"
         );
 
-        let result = store.get(&id);
+        let result = store.get(&id).await;
         assert!(
             result.is_ok(),
             "When document is present at <id> and <id> is accessed, then the document is retrieved"
         );
 
-        let result = store.create(
-            &id,
-            Document {
-                context: ssi::did::Contexts::One(Context::URI(
-                    iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
-                )),
-                id: "did:my:did".to_string(),
-                also_known_as: None,
-                controller: None,
-                verification_method: None,
-                authentication: None,
-                assertion_method: None,
-                key_agreement: None,
-                capability_invocation: None,
-                capability_delegation: None,
-                public_key: None,
-                service: None,
-                proof: None,
-                property_set: None,
-            },
-        );
+        let result = store
+            .create(
+                &id,
+                Document {
+                    context: ssi::did::Contexts::One(Context::URI(
+                        iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
+                    )),
+                    id: "did:my:did".to_string(),
+                    also_known_as: None,
+                    controller: None,
+                    verification_method: None,
+                    authentication: None,
+                    assertion_method: None,
+                    key_agreement: None,
+                    capability_invocation: None,
+                    capability_delegation: None,
+                    public_key: None,
+                    service: None,
+                    proof: None,
+                    property_set: None,
+                },
+            )
+            .await;
         assert!(
             result.is_err(),
             "When document is present at <id> and <id> is tried to be created again, then an error is returned"
         );
     }
 
-    #[test]
-    fn test_update_id_in_store() {
+    #[rocket::async_test]
+    async fn test_update_id_in_store() {
         let store = MemStore::default();
         let id = PathBuf::from("an/id");
-        let result = store.update(
-            &id,
-            Document {
-                context: ssi::did::Contexts::One(Context::URI(
-                    iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
-                )),
-                id: "did:my:did".to_string(),
-                also_known_as: None,
-                controller: None,
-                verification_method: None,
-                authentication: None,
-                assertion_method: None,
-                key_agreement: None,
-                capability_invocation: None,
-                capability_delegation: None,
-                public_key: None,
-                service: None,
-                proof: None,
-                property_set: None,
-            },
-        );
+        let result = store
+            .update(
+                &id,
+                Document {
+                    context: ssi::did::Contexts::One(Context::URI(
+                        iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
+                    )),
+                    id: "did:my:did".to_string(),
+                    also_known_as: None,
+                    controller: None,
+                    verification_method: None,
+                    authentication: None,
+                    assertion_method: None,
+                    key_agreement: None,
+                    capability_invocation: None,
+                    capability_delegation: None,
+                    public_key: None,
+                    service: None,
+                    proof: None,
+                    property_set: None,
+                },
+            )
+            .await;
         assert!(
             result.is_err(),
             "When <id> isn't present in store and an update is attempted, then an error is returned"
         );
 
-        let result = store.create(
-            &id,
-            Document {
-                context: ssi::did::Contexts::One(Context::URI(
-                    iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
-                )),
-                id: "did:my:did".to_string(),
-                also_known_as: None,
-                controller: None,
-                verification_method: None,
-                authentication: None,
-                assertion_method: None,
-                key_agreement: None,
-                capability_invocation: None,
-                capability_delegation: None,
-                public_key: None,
-                service: None,
-                proof: None,
-                property_set: None,
-            },
-        );
+        let result = store
+            .create(
+                &id,
+                Document {
+                    context: ssi::did::Contexts::One(Context::URI(
+                        iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
+                    )),
+                    id: "did:my:did".to_string(),
+                    also_known_as: None,
+                    controller: None,
+                    verification_method: None,
+                    authentication: None,
+                    assertion_method: None,
+                    key_agreement: None,
+                    capability_invocation: None,
+                    capability_delegation: None,
+                    public_key: None,
+                    service: None,
+                    proof: None,
+                    property_set: None,
+                },
+            )
+            .await;
         assert!(
             result.is_ok(),
             "When an <id> document is put in the store and <id> isn't present in store, then the document is stored at id"
         );
 
-        let result = store.update(
-            &id,
-            Document {
-                context: ssi::did::Contexts::One(Context::URI(
-                    iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
-                )),
-                id: "did:my:did".to_string(),
-                also_known_as: None,
-                controller: None,
-                verification_method: None,
-                authentication: None,
-                assertion_method: None,
-                key_agreement: None,
-                capability_invocation: None,
-                capability_delegation: None,
-                public_key: None,
-                service: None,
-                proof: None,
-                property_set: None,
-            },
-        );
+        let result = store
+            .update(
+                &id,
+                Document {
+                    context: ssi::did::Contexts::One(Context::URI(
+                        iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
+                    )),
+                    id: "did:my:did".to_string(),
+                    also_known_as: None,
+                    controller: None,
+                    verification_method: None,
+                    authentication: None,
+                    assertion_method: None,
+                    key_agreement: None,
+                    capability_invocation: None,
+                    capability_delegation: None,
+                    public_key: None,
+                    service: None,
+                    proof: None,
+                    property_set: None,
+                },
+            )
+            .await;
         assert!(
             result.is_ok(),
             "When <id> is present in store and an update is attempted, then the update succeeds"
         );
     }
 
-    #[test]
-    fn test_remove_id_from_store() {
+    #[rocket::async_test]
+    async fn test_remove_id_from_store() {
         let store = MemStore::default();
         let id = PathBuf::from("an/id");
-        let result = store.remove(&id);
+        let result = store.remove(&id).await;
         assert!(
             result.is_err(),
             "When <id> isn't present in store and a remove is attempted, then an error is returned"
         );
 
         // TOOD: continue here to fix the result type
-        let result = store.create(
-            &id,
-            Document {
-                context: ssi::did::Contexts::One(Context::URI(
-                    iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
-                )),
-                id: "did:my:did".to_string(),
-                also_known_as: None,
-                controller: None,
-                verification_method: None,
-                authentication: None,
-                assertion_method: None,
-                key_agreement: None,
-                capability_invocation: None,
-                capability_delegation: None,
-                public_key: None,
-                service: None,
-                proof: None,
-                property_set: None,
-            },
-        );
+        let result = store
+            .create(
+                &id,
+                Document {
+                    context: ssi::did::Contexts::One(Context::URI(
+                        iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
+                    )),
+                    id: "did:my:did".to_string(),
+                    also_known_as: None,
+                    controller: None,
+                    verification_method: None,
+                    authentication: None,
+                    assertion_method: None,
+                    key_agreement: None,
+                    capability_invocation: None,
+                    capability_delegation: None,
+                    public_key: None,
+                    service: None,
+                    proof: None,
+                    property_set: None,
+                },
+            )
+            .await;
         assert!(
             result.is_ok(),
             "When an <id> document is put in the store and <id> isn't present in store, then the document is stored at id"
         );
 
-        let result = store.remove(&id);
+        let result = store.remove(&id).await;
         assert!(
             result.is_ok(),
             "When <id> is present in store and a remove is attempted, then the operation succeeds"
         );
 
-        let result = store.get(&id);
+        let result = store.get(&id).await;
         assert!(
             result.is_err(),
             "When <id> is not in store, then an error is returned"
         );
     }
+
+    #[rocket::async_test]
+    async fn test_list_store() {
+        let store = MemStore::default();
+        let result = store.list().await;
+        assert_eq!(
+            result.unwrap().len(),
+            0,
+            "When the store is empty, then no ids are listed"
+        );
+
+        let id = PathBuf::from("an/id/did.json");
+        store
+            .create(
+                &id,
+                Document {
+                    context: ssi::did::Contexts::One(Context::URI(
+                        iref::IriRefBuf::new("https://example.com/my/context").unwrap(),
+                    )),
+                    id: "did:my:did".to_string(),
+                    also_known_as: None,
+                    controller: None,
+                    verification_method: None,
+                    authentication: None,
+                    assertion_method: None,
+                    key_agreement: None,
+                    capability_invocation: None,
+                    capability_delegation: None,
+                    public_key: None,
+                    service: None,
+                    proof: None,
+                    property_set: None,
+                },
+            )
+            .await
+            .unwrap();
+        let result = store.list().await;
+        assert_eq!(
+            result.unwrap(),
+            vec![id],
+            "When a document is stored at <id>, then <id> is listed"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_conformance() {
+        crate::store::conformance::check_store(&MemStore::default()).await;
+    }
 }
@@ -1,23 +1,40 @@
 // SPDX-License-Identifier: AGPL-3.0
 
+pub mod conformance;
 pub mod file;
 pub mod mem;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "s3")]
+pub mod s3;
 
+use async_trait::async_trait;
+use rocket::figment::Figment;
 use ssi::did::Document;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use crate::error::DIDError;
 
-pub trait DIDWebStore {
-    // /// Tests existence of DID in store.
-    // ///
-    // /// - `id` - id part of the did:web method as specified in https://w3c-ccg.github.io/did-method-web/
-    // fn exists(&self, id: &Path) -> bool;
+/// Names of the built-in backends that are available for storing DID documents, see `DWS_BACKEND`.
+pub const BACKENDS: &[&str] = &[
+    "file",
+    "mem",
+    #[cfg(feature = "redis")]
+    "redis",
+    #[cfg(feature = "s3")]
+    "s3",
+];
 
+/// Store for DID documents. Implementations must be safe to share between requests and must not block the executor,
+/// i.e. blocking I/O is moved to `spawn_blocking`. The semantics of the operations are verified by
+/// [`conformance::check_store`].
+#[async_trait]
+pub trait DIDWebStore: Send + Sync {
     /// Get DID from store. The operation fails if the DID doesn't exist.
     ///
     /// - `id` - id part of the did:web method as specified in https://w3c-ccg.github.io/did-method-web/
-    fn get(&self, id: &Path) -> Result<Document, DIDError>;
+    async fn get(&self, id: &Path) -> Result<Document, DIDError>;
 
     /// Create DID in store. The operation fails if the DID already exists.
     ///
@@ -25,7 +42,7 @@ pub trait DIDWebStore {
     /// - `doc` - DID Document.
     ///
     /// @returns The new version of the DID Document
-    fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError>;
+    async fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError>;
 
     /// Update DID in store. The operation fails if the DID doesn't exist.
     ///
@@ -33,12 +50,160 @@ pub trait DIDWebStore {
     /// - `doc` - DID Document.
     ///
     /// @returns The old version of the DID Document
-    fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError>;
+    async fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError>;
 
     /// Remove DID from store. The operation fails if the DID doesn't exist.
     ///
     /// - `id` - id part of the did:web method as specified in https://w3c-ccg.github.io/did-method-web/
     ///
     /// @returns The old version of the DID Document
-    fn remove(&self, id: &Path) -> Result<Document, DIDError>;
+    async fn remove(&self, id: &Path) -> Result<Document, DIDError>;
+
+    /// List the ids of all DIDs in the store.
+    ///
+    /// @returns The ids of all stored DID Documents
+    async fn list(&self) -> Result<Vec<PathBuf>, DIDError>;
+
+    /// Ensure that the store is readable and writable. The operation fails if the store can't be accessed.
+    async fn check(&self) -> Result<(), DIDError>;
+
+    /// Reserve a key once, e.g. to record that an invitation has been redeemed. Reservations are kept apart from the
+    /// DID documents and aren't listed. Concurrent reservations of the same key must not both succeed, also across
+    /// instances that share the store. Backends that don't support reservations return StoreUnavailable.
+    ///
+    /// - `key` - key of the reservation that consists of lower case letters, digits and `-`
+    /// - `value` - JSON encoded details of the reservation
+    ///
+    /// @returns `true` if the key has been reserved, `false` if it had been reserved before
+    async fn reserve(&self, _key: &str, _value: &str) -> Result<bool, DIDError> {
+        Err(DIDError::StoreUnavailable(
+            "Backend doesn't support reservations".to_string(),
+        ))
+    }
+
+    /// Release a reserved key, e.g. because the operation that the reservation guarded failed. Releasing a key that
+    /// isn't reserved succeeds.
+    ///
+    /// - `key` - key of the reservation
+    async fn release(&self, _key: &str) -> Result<(), DIDError> {
+        Err(DIDError::StoreUnavailable(
+            "Backend doesn't support reservations".to_string(),
+        ))
+    }
+
+    /// Allocate the next sequence number of the change feed. Backends that are shared between instances allocate it
+    /// from an atomic counter, so that the sequence numbers of changes are unique and increasing across all instances.
+    /// Backends that serve a single instance return `None`, then the change feed numbers the changes itself.
+    ///
+    /// @returns The next sequence number, or `None` if the backend doesn't allocate sequence numbers
+    async fn next_sequence(&self) -> Result<Option<u64>, DIDError> {
+        Ok(None)
+    }
+}
+
+/// Constructs a store from the configuration sources. Backends read their settings from the figment, e.g.
+/// `figment.extract_inner::<String>("backend_kv_url")` for the `DWS_BACKEND_KV_URL` variable.
+pub type BackendFactory =
+    Box<dyn Fn(&Figment) -> Result<Box<dyn DIDWebStore + Sync + Send>, DIDError> + Sync + Send>;
+
+/// Registry of the backends that can be selected via `DWS_BACKEND`. The default registry contains the built-in
+/// backends, see [`BACKENDS`]. Embedders register additional backends before loading the configuration, see
+/// [`crate::config::Config::load_with`].
+pub struct StoreRegistry {
+    factories: BTreeMap<String, BackendFactory>,
+}
+
+impl StoreRegistry {
+    /// Creates a registry without any backends.
+    pub fn empty() -> Self {
+        StoreRegistry {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a backend. A backend that has been registered before with the same name is replaced.
+    ///
+    /// - `name` - name of the backend, i.e. the value of `DWS_BACKEND`
+    /// - `factory` - constructs the store from the configuration sources
+    pub fn register<F>(&mut self, name: &str, factory: F) -> &mut Self
+    where
+        F: Fn(&Figment) -> Result<Box<dyn DIDWebStore + Sync + Send>, DIDError>
+            + Sync
+            + Send
+            + 'static,
+    {
+        self.factories.insert(name.to_string(), Box::new(factory));
+        self
+    }
+
+    /// Names of all registered backends in alphabetical order.
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    /// Constructs the store of a registered backend.
+    ///
+    /// - `name` - name of the backend
+    /// - `figment` - configuration sources that the backend reads its settings from
+    /// - returns the store or an error if the backend is unknown or its settings are invalid
+    pub fn build(
+        &self,
+        name: &str,
+        figment: &Figment,
+    ) -> Result<Box<dyn DIDWebStore + Sync + Send>, DIDError> {
+        match self.factories.get(name) {
+            Some(factory) => factory(figment),
+            None => Err(DIDError::UnknownBackend(format!(
+                "Backend is unknown: {}",
+                name
+            ))),
+        }
+    }
+}
+
+impl Default for StoreRegistry {
+    fn default() -> Self {
+        let mut registry = StoreRegistry::empty();
+        registry
+            .register("file", |figment| {
+                Ok(Box::new(file::FileStore::from_figment(figment)?))
+            })
+            .register("mem", |_| Ok(Box::new(mem::MemStore::new())));
+        #[cfg(feature = "redis")]
+        registry.register("redis", |figment| {
+            Ok(Box::new(redis::RedisStore::from_figment(figment)?))
+        });
+        #[cfg(feature = "s3")]
+        registry.register("s3", |figment| {
+            Ok(Box::new(s3::S3Store::from_figment(figment)?))
+        });
+        registry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::store::*;
+
+    #[test]
+    fn test_store_registry() {
+        let mut registry = StoreRegistry::default();
+        assert_eq!(
+            registry.names(),
+            BACKENDS,
+            "When no backend has been registered, then the built-in backends are available"
+        );
+        assert!(
+            matches!(
+                registry.build("kv", &Figment::new()),
+                Err(DIDError::UnknownBackend(_))
+            ),
+            "When a backend isn't registered, then an error is returned"
+        );
+        registry.register("kv", |_| Ok(Box::new(mem::MemStore::new())));
+        assert!(
+            registry.build("kv", &Figment::new()).is_ok(),
+            "When a backend has been registered, then it's constructed by its name"
+        );
+    }
 }
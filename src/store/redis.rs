@@ -0,0 +1,438 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use ::redis::aio::ConnectionManager;
+use ::redis::{AsyncCommands, Client, RedisError, Script};
+use async_trait::async_trait;
+use rocket::figment::Figment;
+use rocket::tokio::sync::OnceCell;
+use ssi::did::Document;
+use std::path::{Path, PathBuf};
+
+use crate::config::Settings;
+use crate::error::DIDError;
+use crate::store::DIDWebStore;
+use crate::utils::path_to_string;
+
+/// Replaces the document only if it exists and returns the previous version.
+static UPDATE_SCRIPT: &str = r#"
+local old = redis.call('GET', KEYS[1])
+if old then
+    redis.call('SET', KEYS[1], ARGV[1])
+end
+return old
+"#;
+
+/// Removes the document and returns the removed version.
+static REMOVE_SCRIPT: &str = r#"
+local old = redis.call('GET', KEYS[1])
+if old then
+    redis.call('DEL', KEYS[1])
+end
+return old
+"#;
+
+/// Store that keeps DID documents in a server that speaks the Redis protocol, so that multiple replicas share the same
+/// documents. Each document is stored as JSON at `<prefix><id>`. The id starts with the tenant's namespace, so the
+/// documents of each tenant share a key prefix, e.g. `dws:example.com/alice/did.json`.
+pub struct RedisStore {
+    client: Client,
+    connection: OnceCell<ConnectionManager>,
+    prefix: String,
+}
+
+impl RedisStore {
+    /// Creates the store. The connection is established with the first operation.
+    ///
+    /// - `url` - URL of the server, e.g. `redis://127.0.0.1/`
+    /// - `prefix` - prefix of all keys, e.g. `dws:`
+    pub fn new(url: &str, prefix: &str) -> Result<Self, DIDError> {
+        Ok(RedisStore {
+            client: Client::open(url).map_err(|e| DIDError::ConfigInvalid(e.to_string()))?,
+            connection: OnceCell::new(),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Creates the store from DWS_BACKEND_REDIS_URL and DWS_BACKEND_REDIS_PREFIX, by default `redis://127.0.0.1/` and
+    /// `dws:`.
+    pub fn from_figment(figment: &Figment) -> Result<Self, DIDError> {
+        let settings = Settings::extract(figment)?;
+        RedisStore::new(
+            settings
+                .backend_redis_url
+                .as_deref()
+                .unwrap_or("redis://127.0.0.1/"),
+            settings.backend_redis_prefix.as_deref().unwrap_or("dws:"),
+        )
+    }
+
+    /// Connection to the server. Clones share the same multiplexed connection, which reconnects automatically.
+    async fn connection(&self) -> Result<ConnectionManager, DIDError> {
+        self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .cloned()
+            .map_err(unavailable)
+    }
+
+    /// Key of a reservation. It doesn't end in `did.json`, so it's never listed as DID document.
+    fn reservation_key(&self, key: &str) -> String {
+        format!("{}.dws-reservation:{}", self.prefix, key)
+    }
+
+    fn key(&self, id: &Path) -> String {
+        format!(
+            "{}{}",
+            self.prefix,
+            path_to_string(id.strip_prefix("/").unwrap_or(id), "/")
+        )
+    }
+
+    /// Pattern that matches all keys of the store. Glob characters of the prefix are escaped, so that they match
+    /// literally.
+    fn key_pattern(&self) -> String {
+        let mut pattern = String::new();
+        for c in self.prefix.chars() {
+            if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('*');
+        pattern
+    }
+}
+
+fn unavailable(e: RedisError) -> DIDError {
+    DIDError::StoreUnavailable(e.to_string())
+}
+
+fn to_document(s: String) -> Result<Document, DIDError> {
+    serde_json::from_str::<Document>(&s).map_err(|e| DIDError::ContentConversion(e.to_string()))
+}
+
+fn to_json(doc: &Document) -> Result<String, DIDError> {
+    serde_json::to_string(doc).map_err(|e| DIDError::ContentConversion(e.to_string()))
+}
+
+#[async_trait]
+impl DIDWebStore for RedisStore {
+    async fn get(&self, id: &Path) -> Result<Document, DIDError> {
+        let mut connection = self.connection().await?;
+        connection
+            .get::<_, Option<String>>(self.key(id))
+            .await
+            .map_err(unavailable)?
+            .ok_or_else(|| DIDError::DIDNotFound("DID not found".to_string()))
+            .and_then(to_document)
+    }
+
+    async fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        let mut connection = self.connection().await?;
+        // SET NX fails atomically if the key exists, so concurrent creations can't overwrite each other
+        let created = ::redis::cmd("SET")
+            .arg(self.key(id))
+            .arg(to_json(&doc)?)
+            .arg("NX")
+            .query_async::<_, Option<String>>(&mut connection)
+            .await
+            .map_err(unavailable)?;
+        match created {
+            Some(_) => Ok(doc),
+            None => Err(DIDError::DIDExists(format!(
+                "DID already exists: {}",
+                doc.id
+            ))),
+        }
+    }
+
+    async fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        let mut connection = self.connection().await?;
+        Script::new(UPDATE_SCRIPT)
+            .key(self.key(id))
+            .arg(to_json(&doc)?)
+            .invoke_async::<_, Option<String>>(&mut connection)
+            .await
+            .map_err(unavailable)?
+            .ok_or_else(|| DIDError::DIDNotFound("DID not found".to_string()))
+            .and_then(to_document)
+    }
+
+    async fn remove(&self, id: &Path) -> Result<Document, DIDError> {
+        let mut connection = self.connection().await?;
+        Script::new(REMOVE_SCRIPT)
+            .key(self.key(id))
+            .invoke_async::<_, Option<String>>(&mut connection)
+            .await
+            .map_err(unavailable)?
+            .ok_or_else(|| DIDError::DIDNotFound("DID not found".to_string()))
+            .and_then(to_document)
+    }
+
+    async fn list(&self) -> Result<Vec<PathBuf>, DIDError> {
+        let mut connection = self.connection().await?;
+        let mut keys = connection
+            .scan_match::<_, String>(self.key_pattern())
+            .await
+            .map_err(unavailable)?;
+        let mut ids = vec![];
+        while let Some(key) = keys.next_item().await {
+            // the probe of check isn't a DID document
+            match key.strip_prefix(&self.prefix) {
+                Some(id) if id.ends_with("did.json") => ids.push(PathBuf::from(id)),
+                _ => {}
+            }
+        }
+        Ok(ids)
+    }
+
+    async fn check(&self) -> Result<(), DIDError> {
+        let mut connection = self.connection().await?;
+        let probe = format!("{}.dws-check", self.prefix);
+        connection
+            .set_ex::<_, _, ()>(&probe, "", 60)
+            .await
+            .map_err(unavailable)?;
+        connection
+            .get::<_, Option<String>>(&probe)
+            .await
+            .map_err(unavailable)?;
+        connection.del::<_, ()>(&probe).await.map_err(unavailable)
+    }
+
+    async fn reserve(&self, key: &str, value: &str) -> Result<bool, DIDError> {
+        let mut connection = self.connection().await?;
+        // SET NX fails atomically if the key exists, so all instances that share the server see the reservation
+        ::redis::cmd("SET")
+            .arg(self.reservation_key(key))
+            .arg(value)
+            .arg("NX")
+            .query_async::<_, Option<String>>(&mut connection)
+            .await
+            .map(|reserved| reserved.is_some())
+            .map_err(unavailable)
+    }
+
+    async fn release(&self, key: &str) -> Result<(), DIDError> {
+        let mut connection = self.connection().await?;
+        connection
+            .del::<_, ()>(self.reservation_key(key))
+            .await
+            .map_err(unavailable)
+    }
+
+    async fn next_sequence(&self) -> Result<Option<u64>, DIDError> {
+        let mut connection = self.connection().await?;
+        // INCR is atomic, so all instances that share the server allocate distinct sequence numbers
+        connection
+            .incr::<_, _, u64>(format!("{}.dws-sequence", self.prefix), 1)
+            .await
+            .map(Some)
+            .map_err(unavailable)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::store::redis::*;
+
+    #[test]
+    fn test_key() {
+        let store = RedisStore::new("redis://127.0.0.1/", "dws:").unwrap();
+        assert_eq!(
+            store.key(Path::new("example.com/alice/did.json")),
+            "dws:example.com/alice/did.json",
+            "When a tenant's id is stored, then the key starts with the tenant's namespace"
+        );
+        assert_eq!(
+            store.key(Path::new("/.well-known/did.json")),
+            "dws:.well-known/did.json",
+            "When an absolute id is stored, then the key is relative to the prefix"
+        );
+    }
+
+    #[test]
+    fn test_key_pattern() {
+        let store = RedisStore::new("redis://127.0.0.1/", "dws[a]*?\\:").unwrap();
+        assert_eq!(
+            store.key_pattern(),
+            "dws\\[a\\]\\*\\?\\\\:*",
+            "When the prefix contains glob characters, then they're escaped in the pattern"
+        );
+    }
+
+    /// In-process server that implements the subset of the Redis protocol that the store uses, so that the conformance
+    /// suite runs without a Redis server. The store's scripts are recognised by their hash and executed natively.
+    mod fake {
+        use ::redis::Script;
+        use rocket::tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+        use rocket::tokio::net::tcp::OwnedReadHalf;
+        use rocket::tokio::net::{TcpListener, TcpStream};
+        use std::collections::{HashMap, HashSet};
+        use std::sync::{Arc, Mutex};
+
+        use crate::store::redis::{REMOVE_SCRIPT, UPDATE_SCRIPT};
+
+        /// Values of all keys and hashes of the loaded scripts.
+        #[derive(Default)]
+        struct State {
+            values: HashMap<String, String>,
+            scripts: HashSet<String>,
+        }
+
+        /// Starts the server on a random port.
+        ///
+        /// - returns the URL of the server
+        pub async fn start() -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("redis://{}/", listener.local_addr().unwrap());
+            let state = Arc::new(Mutex::new(State::default()));
+            rocket::tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    rocket::tokio::spawn(serve(stream, state.clone()));
+                }
+            });
+            url
+        }
+
+        /// Executes the commands of a connection in the order in which they arrive.
+        async fn serve(stream: TcpStream, state: Arc<Mutex<State>>) {
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            while let Some(command) = read_command(&mut reader).await {
+                let reply = execute(&mut state.lock().unwrap(), &command);
+                if writer.write_all(reply.as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        /// Reads a command, i.e. an array of bulk strings.
+        async fn read_command(reader: &mut BufReader<OwnedReadHalf>) -> Option<Vec<String>> {
+            let count = read_line(reader)
+                .await?
+                .strip_prefix('*')?
+                .parse::<usize>()
+                .ok()?;
+            let mut command = vec![];
+            for _ in 0..count {
+                let length = read_line(reader)
+                    .await?
+                    .strip_prefix('$')?
+                    .parse::<usize>()
+                    .ok()?;
+                // the bulk string is terminated by CRLF
+                let mut data = vec![0; length + 2];
+                reader.read_exact(&mut data).await.ok()?;
+                data.truncate(length);
+                command.push(String::from_utf8(data).ok()?);
+            }
+            Some(command)
+        }
+
+        /// Reads a line without its terminating CRLF, `None` at the end of the connection.
+        async fn read_line(reader: &mut BufReader<OwnedReadHalf>) -> Option<String> {
+            let mut line = String::new();
+            match reader.read_line(&mut line).await {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line.trim_end().to_string()),
+            }
+        }
+
+        /// Encodes a bulk string, `None` as null.
+        fn bulk(value: Option<&String>) -> String {
+            match value {
+                Some(value) => format!("${}\r\n{}\r\n", value.len(), value),
+                None => "$-1\r\n".to_string(),
+            }
+        }
+
+        /// Matches a key against a glob pattern with `*`, `?` and `\` as escape character.
+        fn matches(pattern: &[char], key: &[char]) -> bool {
+            match pattern {
+                [] => key.is_empty(),
+                ['*', rest @ ..] => (0..=key.len()).any(|i| matches(rest, &key[i..])),
+                ['?', rest @ ..] => !key.is_empty() && matches(rest, &key[1..]),
+                ['\\', c, rest @ ..] | [c, rest @ ..] => {
+                    key.first() == Some(c) && matches(rest, &key[1..])
+                }
+            }
+        }
+
+        /// Executes a command and encodes its reply.
+        fn execute(state: &mut State, command: &[String]) -> String {
+            let args = command.iter().map(String::as_str).collect::<Vec<&str>>();
+            match args.as_slice() {
+                ["CLIENT", ..] => "+OK\r\n".to_string(),
+                ["PING"] => "+PONG\r\n".to_string(),
+                ["GET", key] => bulk(state.values.get(*key)),
+                ["SET", key, _, "NX"] if state.values.contains_key(*key) => bulk(None),
+                ["SET", key, value] | ["SET", key, value, "NX"] | ["SETEX", key, _, value] => {
+                    state.values.insert(key.to_string(), value.to_string());
+                    "+OK\r\n".to_string()
+                }
+                ["DEL", keys @ ..] => {
+                    let removed = keys
+                        .iter()
+                        .filter(|key| state.values.remove(**key).is_some())
+                        .count();
+                    format!(":{}\r\n", removed)
+                }
+                ["INCRBY", key, delta] => {
+                    let value = state
+                        .values
+                        .get(*key)
+                        .map_or(0, |value| value.parse::<i64>().unwrap())
+                        + delta.parse::<i64>().unwrap();
+                    state.values.insert(key.to_string(), value.to_string());
+                    format!(":{}\r\n", value)
+                }
+                ["SCAN", _, "MATCH", pattern, ..] => {
+                    let pattern = pattern.chars().collect::<Vec<char>>();
+                    let keys = state
+                        .values
+                        .keys()
+                        .filter(|key| matches(&pattern, &key.chars().collect::<Vec<char>>()))
+                        .map(|key| bulk(Some(key)))
+                        .collect::<Vec<String>>();
+                    // cursor 0 ends the iteration
+                    format!("*2\r\n$1\r\n0\r\n*{}\r\n{}", keys.len(), keys.concat())
+                }
+                ["SCRIPT", "LOAD", code] => {
+                    let hash = Script::new(code).get_hash().to_string();
+                    state.scripts.insert(hash.clone());
+                    bulk(Some(&hash))
+                }
+                ["EVALSHA", hash, "1", key, args @ ..] if state.scripts.contains(*hash) => {
+                    let old = state.values.get(*key).cloned();
+                    if old.is_some() {
+                        if *hash == Script::new(UPDATE_SCRIPT).get_hash() {
+                            state.values.insert(key.to_string(), args[0].to_string());
+                        } else if *hash == Script::new(REMOVE_SCRIPT).get_hash() {
+                            state.values.remove(*key);
+                        }
+                    }
+                    bulk(old.as_ref())
+                }
+                ["EVALSHA", ..] => "-NOSCRIPT No matching script\r\n".to_string(),
+                _ => format!("-ERR unknown command {:?}\r\n", args),
+            }
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_conformance_fake() {
+        let url = fake::start().await;
+        crate::store::conformance::check_store(&RedisStore::new(&url, "dws[a]:").unwrap()).await;
+    }
+
+    /// Runs the conformance suite against the server at DWS_TEST_REDIS_URL, e.g. a local redis-server. The test
+    /// requires a server and is therefore ignored by default, run it via `cargo test --features redis -- --ignored`.
+    #[rocket::async_test]
+    #[ignore]
+    async fn test_conformance() {
+        let url = std::env::var("DWS_TEST_REDIS_URL").expect("DWS_TEST_REDIS_URL is set");
+        let prefix = format!("dws-test-{}:", std::process::id());
+        crate::store::conformance::check_store(&RedisStore::new(&url, &prefix).unwrap()).await;
+    }
+}
@@ -0,0 +1,738 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use async_trait::async_trait;
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+use aws_sdk_s3::error::{DisplayErrorContext, SdkError};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client;
+use rocket::figment::Figment;
+use ssi::did::Document;
+use std::path::{Path, PathBuf};
+
+use crate::config::Settings;
+use crate::error::DIDError;
+use crate::store::DIDWebStore;
+use crate::utils::path_to_string;
+
+/// Number of attempts of an update or removal whose conditional request fails due to a concurrent update.
+const UPDATE_ATTEMPTS: usize = 3;
+
+/// Prefix of the reservations' object keys, unless DWS_BACKEND_S3_RESERVATION_PREFIX is set. It's relative to the root
+/// of the bucket and therefore outside the DID documents' prefix.
+static RESERVATION_PREFIX: &str = ".dws-reservations/";
+
+/// Store that keeps DID documents in an S3-compatible bucket, e.g. MinIO. The object keys mirror the layout of
+/// [`crate::store::file::FileStore`], e.g. `alice/did.json`, so the bucket can serve as the public origin of the DIDs.
+/// Creations, updates and removals are conditional requests, i.e. `If-None-Match: *` and `If-Match: <ETag>`.
+/// Reservations are kept apart from the DID documents, since they reveal the ids of redeemed invitations.
+pub struct S3Store {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    reservation_bucket: String,
+    reservation_prefix: String,
+}
+
+impl S3Store {
+    /// Creates the store.
+    ///
+    /// - `client` - S3 client, e.g. with a custom endpoint for MinIO
+    /// - `bucket` - name of the bucket
+    /// - `prefix` - prefix of all object keys, e.g. `dids/`
+    /// - `reservation_bucket` - name of the bucket that reservations are written to, e.g. a private bucket
+    /// - `reservation_prefix` - prefix of the reservations' object keys, e.g. `reservations/`
+    pub fn new(
+        client: Client,
+        bucket: &str,
+        prefix: &str,
+        reservation_bucket: &str,
+        reservation_prefix: &str,
+    ) -> Self {
+        S3Store {
+            client,
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            reservation_bucket: reservation_bucket.to_string(),
+            reservation_prefix: reservation_prefix.to_string(),
+        }
+    }
+
+    /// Creates the store from the DWS_BACKEND_S3_* variables. The credentials default to the AWS_ACCESS_KEY_ID and
+    /// AWS_SECRET_ACCESS_KEY variables. Reservations default to the bucket of the DID documents.
+    pub fn from_figment(figment: &Figment) -> Result<Self, DIDError> {
+        let settings = Settings::extract(figment)?;
+        let bucket = settings
+            .backend_s3_bucket
+            .ok_or_else(|| DIDError::ConfigInvalid("S3 bucket not specified".to_string()))?;
+        let access_key_id = settings
+            .backend_s3_access_key_id
+            .or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok())
+            .ok_or_else(|| DIDError::ConfigInvalid("S3 access key not specified".to_string()))?;
+        let secret_access_key = settings
+            .backend_s3_secret_access_key
+            .or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok())
+            .ok_or_else(|| DIDError::ConfigInvalid("S3 secret key not specified".to_string()))?;
+        let mut config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(
+                settings
+                    .backend_s3_region
+                    .unwrap_or_else(|| "us-east-1".to_string()),
+            ))
+            .credentials_provider(Credentials::new(
+                access_key_id,
+                secret_access_key,
+                None,
+                None,
+                "did-web-server",
+            ));
+        if let Some(endpoint) = settings.backend_s3_endpoint {
+            // MinIO and most other S3-compatible servers only support path-style requests
+            config = config.endpoint_url(endpoint).force_path_style(true);
+        }
+        let prefix = settings.backend_s3_prefix.unwrap_or_default();
+        let reservation_bucket = settings
+            .backend_s3_reservation_bucket
+            .unwrap_or_else(|| bucket.clone());
+        let reservation_prefix = settings
+            .backend_s3_reservation_prefix
+            .unwrap_or_else(|| RESERVATION_PREFIX.to_string());
+        if reservation_bucket == bucket && reservation_prefix.starts_with(&prefix) {
+            tracing::warn!(
+                bucket = bucket.as_str(),
+                "S3 reservations are stored next to the DID documents, set DWS_BACKEND_S3_RESERVATION_BUCKET if the bucket is public"
+            );
+        }
+        Ok(S3Store::new(
+            Client::from_conf(config.build()),
+            &bucket,
+            &prefix,
+            &reservation_bucket,
+            &reservation_prefix,
+        ))
+    }
+
+    fn key(&self, id: &Path) -> String {
+        format!(
+            "{}{}",
+            self.prefix,
+            path_to_string(id.strip_prefix("/").unwrap_or(id), "/")
+        )
+    }
+
+    /// Object key of a reservation in the reservation bucket.
+    fn reservation_key(&self, key: &str) -> String {
+        format!("{}{}", self.reservation_prefix, key)
+    }
+
+    /// Object key of the counter that sequence numbers of the change feed are allocated from.
+    fn sequence_key(&self) -> String {
+        format!("{}.dws-sequence", self.prefix)
+    }
+
+    /// Retrieves a DID document together with its ETag.
+    async fn get_with_etag(&self, id: &Path) -> Result<(Document, Option<String>), DIDError> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(id))
+            .send()
+            .await
+            .map_err(|e| match e.as_service_error() {
+                Some(service_error) if service_error.is_no_such_key() => {
+                    DIDError::DIDNotFound("DID not found".to_string())
+                }
+                _ => unavailable(e),
+            })?;
+        let etag = output.e_tag;
+        let body = output
+            .body
+            .collect()
+            .await
+            .map_err(|e| DIDError::StoreUnavailable(e.to_string()))?
+            .into_bytes();
+        serde_json::from_slice::<Document>(&body)
+            .map_err(|e| DIDError::ContentConversion(e.to_string()))
+            .map(|doc| (doc, etag))
+    }
+
+    /// Stores a DID document with a conditional write.
+    ///
+    /// - `id` - id part of the did:web method as specified in https://w3c-ccg.github.io/did-method-web/
+    /// - `doc` - DID Document.
+    /// - `if_none_match` - value of the If-None-Match header, `*` to fail if the object exists
+    /// - `if_match` - value of the If-Match header, the ETag of the object that's replaced
+    /// - returns `Ok(false)` if the precondition failed
+    async fn put(
+        &self,
+        id: &Path,
+        doc: &Document,
+        if_none_match: Option<&str>,
+        if_match: Option<String>,
+    ) -> Result<bool, DIDError> {
+        let body =
+            serde_json::to_vec(doc).map_err(|e| DIDError::ContentConversion(e.to_string()))?;
+        let result = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.key(id))
+            .content_type("application/json")
+            .set_if_none_match(if_none_match.map(str::to_string))
+            .set_if_match(if_match)
+            .body(ByteStream::from(body))
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            // 412 - Precondition Failed, 409 - Conflict if a conditional write is in progress
+            Err(e) if matches!(status(&e), Some(412) | Some(409)) => Ok(false),
+            Err(e) => Err(unavailable(e)),
+        }
+    }
+}
+
+/// HTTP status of a failed request, if the server responded.
+fn status<E>(e: &SdkError<E, HttpResponse>) -> Option<u16> {
+    e.raw_response().map(|response| response.status().as_u16())
+}
+
+fn unavailable<E: std::error::Error + 'static>(e: E) -> DIDError {
+    DIDError::StoreUnavailable(DisplayErrorContext(e).to_string())
+}
+
+#[async_trait]
+impl DIDWebStore for S3Store {
+    async fn get(&self, id: &Path) -> Result<Document, DIDError> {
+        self.get_with_etag(id).await.map(|(doc, _)| doc)
+    }
+
+    async fn create(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        if self.put(id, &doc, Some("*"), None).await? {
+            Ok(doc)
+        } else {
+            Err(DIDError::DIDExists(format!(
+                "DID already exists: {}",
+                doc.id
+            )))
+        }
+    }
+
+    async fn update(&self, id: &Path, doc: Document) -> Result<Document, DIDError> {
+        for _ in 0..UPDATE_ATTEMPTS {
+            let (document, etag) = self.get_with_etag(id).await?;
+            // the object has been replaced in the meantime if the ETag doesn't match anymore
+            if self.put(id, &doc, None, etag).await? {
+                return Ok(document);
+            }
+        }
+        Err(DIDError::StoreUnavailable(
+            "DID document changed concurrently".to_string(),
+        ))
+    }
+
+    async fn remove(&self, id: &Path) -> Result<Document, DIDError> {
+        for _ in 0..UPDATE_ATTEMPTS {
+            let (document, etag) = self.get_with_etag(id).await?;
+            // the object has been replaced in the meantime if the ETag doesn't match anymore
+            let result = self
+                .client
+                .delete_object()
+                .bucket(&self.bucket)
+                .key(self.key(id))
+                .set_if_match(etag)
+                .send()
+                .await;
+            match result {
+                Ok(_) => return Ok(document),
+                Err(e) if matches!(status(&e), Some(412) | Some(409)) => continue,
+                Err(e) => return Err(unavailable(e)),
+            }
+        }
+        Err(DIDError::StoreUnavailable(
+            "DID document changed concurrently".to_string(),
+        ))
+    }
+
+    async fn list(&self) -> Result<Vec<PathBuf>, DIDError> {
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&self.prefix)
+            .into_paginator()
+            .send();
+        let mut ids = vec![];
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(unavailable)?;
+            ids.extend(
+                page.contents()
+                    .iter()
+                    .filter_map(|object| object.key())
+                    .filter_map(|key| key.strip_prefix(&self.prefix))
+                    // the probe of check isn't a DID document
+                    .filter(|id| id.ends_with("did.json"))
+                    .map(PathBuf::from),
+            );
+        }
+        Ok(ids)
+    }
+
+    async fn check(&self) -> Result<(), DIDError> {
+        let probe = format!("{}.dws-check", self.prefix);
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&probe)
+            .body(ByteStream::from_static(b""))
+            .send()
+            .await
+            .map_err(unavailable)?;
+        self.client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&probe)
+            .send()
+            .await
+            .map_err(unavailable)?;
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(&probe)
+            .send()
+            .await
+            .map_err(unavailable)
+            .map(|_| ())
+    }
+
+    async fn reserve(&self, key: &str, value: &str) -> Result<bool, DIDError> {
+        let result = self
+            .client
+            .put_object()
+            .bucket(&self.reservation_bucket)
+            .key(self.reservation_key(key))
+            .content_type("application/json")
+            .if_none_match("*")
+            .body(ByteStream::from(value.as_bytes().to_vec()))
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            // 412 - Precondition Failed, 409 - Conflict if a conditional write is in progress
+            Err(e) if matches!(status(&e), Some(412) | Some(409)) => Ok(false),
+            Err(e) => Err(unavailable(e)),
+        }
+    }
+
+    async fn release(&self, key: &str) -> Result<(), DIDError> {
+        self.client
+            .delete_object()
+            .bucket(&self.reservation_bucket)
+            .key(self.reservation_key(key))
+            .send()
+            .await
+            .map_err(unavailable)
+            .map(|_| ())
+    }
+
+    async fn next_sequence(&self) -> Result<Option<u64>, DIDError> {
+        let key = self.sequence_key();
+        for _ in 0..UPDATE_ATTEMPTS {
+            let (sequence, etag) = match self
+                .client
+                .get_object()
+                .bucket(&self.bucket)
+                .key(&key)
+                .send()
+                .await
+            {
+                Ok(output) => {
+                    let etag = output.e_tag;
+                    let body = output
+                        .body
+                        .collect()
+                        .await
+                        .map_err(|e| DIDError::StoreUnavailable(e.to_string()))?
+                        .into_bytes();
+                    let sequence = std::str::from_utf8(&body)
+                        .ok()
+                        .and_then(|sequence| sequence.trim().parse::<u64>().ok())
+                        .ok_or_else(|| {
+                            DIDError::ContentConversion(format!("Sequence invalid: {}", key))
+                        })?;
+                    (sequence, etag)
+                }
+                Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => (0, None),
+                Err(e) => return Err(unavailable(e)),
+            };
+            // the counter is only advanced if no other instance advanced it in the meantime
+            let result = self
+                .client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(&key)
+                .content_type("text/plain")
+                .set_if_none_match(etag.is_none().then(|| "*".to_string()))
+                .set_if_match(etag)
+                .body(ByteStream::from((sequence + 1).to_string().into_bytes()))
+                .send()
+                .await;
+            match result {
+                Ok(_) => return Ok(Some(sequence + 1)),
+                Err(e) if matches!(status(&e), Some(412) | Some(409)) => continue,
+                Err(e) => return Err(unavailable(e)),
+            }
+        }
+        Err(DIDError::StoreUnavailable(
+            "Sequence number allocated concurrently".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::store::s3::*;
+
+    fn store(prefix: &str) -> S3Store {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .build();
+        S3Store::new(
+            Client::from_conf(config),
+            "dids",
+            prefix,
+            "dids",
+            RESERVATION_PREFIX,
+        )
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(
+            store("").key(Path::new("alice/did.json")),
+            "alice/did.json",
+            "When an id is stored, then the object key mirrors the file store's layout"
+        );
+        assert_eq!(
+            store("dids/").key(Path::new("/.well-known/did.json")),
+            "dids/.well-known/did.json",
+            "When an absolute id is stored, then the object key is relative to the prefix"
+        );
+    }
+
+    #[test]
+    fn test_reservation_key() {
+        assert_eq!(
+            store("dids/").reservation_key("issuer"),
+            ".dws-reservations/issuer",
+            "When the default reservation prefix is used, then reservations are kept outside the DID documents' prefix"
+        );
+        let figment =
+            Figment::new().merge(rocket::figment::providers::Serialized::defaults(Settings {
+                backend_s3_access_key_id: Some("dws".to_string()),
+                backend_s3_bucket: Some("dids".to_string()),
+                backend_s3_reservation_bucket: Some("private".to_string()),
+                backend_s3_reservation_prefix: Some("reservations/".to_string()),
+                backend_s3_secret_access_key: Some("dws".to_string()),
+                ..Settings::default()
+            }));
+        let store = S3Store::from_figment(&figment).unwrap();
+        assert_eq!(
+            (store.reservation_bucket.as_str(), store.reservation_key("issuer")),
+            ("private", "reservations/issuer".to_string()),
+            "When the reservation bucket and prefix are configured, then reservations are written there"
+        );
+    }
+
+    /// In-process server that implements the subset of the S3 REST API that the store uses, so that the conformance
+    /// suite runs without an S3 server. Requests are path-style and aren't authenticated.
+    mod fake {
+        use percent_encoding::percent_decode_str;
+        use rocket::tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+        use rocket::tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+        use rocket::tokio::net::{TcpListener, TcpStream};
+        use std::collections::{BTreeMap, HashMap};
+        use std::sync::{Arc, Mutex};
+
+        /// Objects by bucket and key together with their ETags, and the number of writes that ETags are derived from.
+        #[derive(Default)]
+        struct State {
+            objects: BTreeMap<(String, String), (Vec<u8>, String)>,
+            writes: u64,
+        }
+
+        /// Method, target, lowercase headers and body of a request.
+        struct Request {
+            method: String,
+            target: String,
+            headers: HashMap<String, String>,
+            body: Vec<u8>,
+        }
+
+        /// Status, headers and body of a response.
+        type Response = (u16, Vec<(&'static str, String)>, Vec<u8>);
+
+        /// Starts the server on a random port.
+        ///
+        /// - returns the endpoint of the server
+        pub async fn start() -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            let state = Arc::new(Mutex::new(State::default()));
+            rocket::tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    rocket::tokio::spawn(serve(stream, state.clone()));
+                }
+            });
+            endpoint
+        }
+
+        /// Answers the requests of a connection in the order in which they arrive.
+        async fn serve(stream: TcpStream, state: Arc<Mutex<State>>) {
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            while let Some(request) = read_request(&mut reader, &mut writer).await {
+                let (status, headers, body) = execute(&mut state.lock().unwrap(), &request);
+                let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason(status));
+                // a response without content mustn't declare its length
+                if status != 204 {
+                    head += &format!("content-length: {}\r\n", body.len());
+                }
+                for (name, value) in headers {
+                    head += &format!("{}: {}\r\n", name, value);
+                }
+                head += "\r\n";
+                if writer.write_all(head.as_bytes()).await.is_err()
+                    || writer.write_all(&body).await.is_err()
+                {
+                    return;
+                }
+            }
+        }
+
+        /// Reads a request, `None` at the end of the connection.
+        async fn read_request(
+            reader: &mut BufReader<OwnedReadHalf>,
+            writer: &mut OwnedWriteHalf,
+        ) -> Option<Request> {
+            let line = read_line(reader).await?;
+            let mut parts = line.split(' ');
+            let method = parts.next()?.to_string();
+            let target = parts.next()?.to_string();
+            let mut headers = HashMap::new();
+            loop {
+                let line = read_line(reader).await?;
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line.split_once(':')?;
+                headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+            }
+            if headers
+                .get("expect")
+                .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+            {
+                writer
+                    .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                    .await
+                    .ok()?;
+            }
+            let length = headers
+                .get("content-length")
+                .map_or(Ok(0), |length| length.parse::<usize>())
+                .ok()?;
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.ok()?;
+            if headers
+                .get("content-encoding")
+                .is_some_and(|encoding| encoding.contains("aws-chunked"))
+            {
+                body = decode_chunks(&body)?;
+            }
+            Some(Request {
+                method,
+                target,
+                headers,
+                body,
+            })
+        }
+
+        /// Reads a line without its terminating CRLF, `None` at the end of the connection.
+        async fn read_line(reader: &mut BufReader<OwnedReadHalf>) -> Option<String> {
+            let mut line = String::new();
+            match reader.read_line(&mut line).await {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line.trim_end().to_string()),
+            }
+        }
+
+        /// Decodes a body in aws-chunked encoding, i.e. `<hex size>[;extensions]\r\n<data>\r\n` until a chunk of
+        /// size 0 that's followed by the trailers.
+        fn decode_chunks(mut chunks: &[u8]) -> Option<Vec<u8>> {
+            let mut body = vec![];
+            loop {
+                let end = chunks.windows(2).position(|window| window == b"\r\n")?;
+                let header = std::str::from_utf8(&chunks[..end]).ok()?;
+                let size = usize::from_str_radix(header.split(';').next()?.trim(), 16).ok()?;
+                if size == 0 {
+                    return Some(body);
+                }
+                body.extend_from_slice(chunks.get(end + 2..end + 2 + size)?);
+                chunks = chunks.get(end + 4 + size..)?;
+            }
+        }
+
+        fn reason(status: u16) -> &'static str {
+            match status {
+                200 => "OK",
+                204 => "No Content",
+                404 => "Not Found",
+                412 => "Precondition Failed",
+                _ => "Bad Request",
+            }
+        }
+
+        fn decode(value: &str) -> String {
+            percent_decode_str(value).decode_utf8_lossy().to_string()
+        }
+
+        fn escape(value: &str) -> String {
+            value
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        }
+
+        /// Encodes an S3 error, e.g. `NoSuchKey`.
+        fn error(status: u16, code: &str) -> Response {
+            let body = format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Error><Code>{}</Code><Message>{}</Message></Error>",
+                code, code
+            );
+            (
+                status,
+                vec![("content-type", "application/xml".to_string())],
+                body.into_bytes(),
+            )
+        }
+
+        /// Lists all objects of the bucket whose keys start with the prefix on a single page.
+        fn list(state: &State, bucket: &str, prefix: &str) -> Response {
+            let contents = state
+                .objects
+                .iter()
+                .filter(|((b, key), _)| b == bucket && key.starts_with(prefix))
+                .map(|((_, key), (body, etag))| {
+                    format!(
+                        "<Contents><Key>{}</Key><ETag>{}</ETag><Size>{}</Size></Contents>",
+                        escape(key),
+                        escape(etag),
+                        body.len()
+                    )
+                })
+                .collect::<Vec<String>>();
+            let body = format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                <ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+                <Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>1000</MaxKeys>\
+                <IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                escape(bucket),
+                escape(prefix),
+                contents.len(),
+                contents.concat()
+            );
+            (
+                200,
+                vec![("content-type", "application/xml".to_string())],
+                body.into_bytes(),
+            )
+        }
+
+        /// Executes a request against the objects, the query parameter `x-id` that names the operation is ignored.
+        fn execute(state: &mut State, request: &Request) -> Response {
+            let (path, query) = request
+                .target
+                .split_once('?')
+                .unwrap_or((request.target.as_str(), ""));
+            let query = query
+                .split('&')
+                .filter_map(|parameter| parameter.split_once('='))
+                .map(|(name, value)| (name, decode(value)))
+                .collect::<HashMap<&str, String>>();
+            let path = path.strip_prefix('/').unwrap_or(path);
+            let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+            let bucket = decode(bucket);
+            let object = (bucket.clone(), decode(key));
+            let etag = state.objects.get(&object).map(|(_, etag)| etag.clone());
+            let if_match = request.headers.get("if-match");
+            match request.method.as_str() {
+                "GET" if query.contains_key("list-type") => list(
+                    state,
+                    &bucket,
+                    query.get("prefix").map_or("", String::as_str),
+                ),
+                "GET" => match state.objects.get(&object) {
+                    Some((body, etag)) => (200, vec![("etag", etag.clone())], body.clone()),
+                    None => error(404, "NoSuchKey"),
+                },
+                "PUT" if request.headers.contains_key("if-none-match") && etag.is_some() => {
+                    error(412, "PreconditionFailed")
+                }
+                "PUT" | "DELETE" if if_match.is_some() && if_match != etag.as_ref() => match etag {
+                    Some(_) => error(412, "PreconditionFailed"),
+                    None => error(404, "NoSuchKey"),
+                },
+                "PUT" => {
+                    state.writes += 1;
+                    let etag = format!("\"{}\"", state.writes);
+                    state
+                        .objects
+                        .insert(object, (request.body.clone(), etag.clone()));
+                    (200, vec![("etag", etag)], vec![])
+                }
+                "DELETE" => {
+                    state.objects.remove(&object);
+                    (204, vec![], vec![])
+                }
+                _ => error(400, "InvalidRequest"),
+            }
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_conformance_fake() {
+        let endpoint = fake::start().await;
+        let figment =
+            Figment::new().merge(rocket::figment::providers::Serialized::defaults(Settings {
+                backend_s3_access_key_id: Some("dws".to_string()),
+                backend_s3_bucket: Some("dids".to_string()),
+                backend_s3_endpoint: Some(endpoint),
+                backend_s3_prefix: Some("dws test/".to_string()),
+                backend_s3_reservation_bucket: Some("reservations".to_string()),
+                backend_s3_secret_access_key: Some("dws".to_string()),
+                ..Settings::default()
+            }));
+        crate::store::conformance::check_store(&S3Store::from_figment(&figment).unwrap()).await;
+    }
+
+    /// Runs the conformance suite against the bucket `dids` at DWS_TEST_S3_ENDPOINT, e.g. a local MinIO. The
+    /// credentials are read from AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY. The test requires a server and is
+    /// therefore ignored by default, run it via `cargo test --features s3 -- --ignored`.
+    #[rocket::async_test]
+    #[ignore]
+    async fn test_conformance() {
+        let endpoint = std::env::var("DWS_TEST_S3_ENDPOINT").expect("DWS_TEST_S3_ENDPOINT is set");
+        let figment =
+            Figment::new().merge(rocket::figment::providers::Serialized::defaults(Settings {
+                backend_s3_bucket: Some("dids".to_string()),
+                backend_s3_endpoint: Some(endpoint),
+                backend_s3_prefix: Some(format!("dws-test-{}/", std::process::id())),
+                ..Settings::default()
+            }));
+        crate::store::conformance::check_store(&S3Store::from_figment(&figment).unwrap()).await;
+    }
+}
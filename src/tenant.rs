@@ -0,0 +1,246 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::did::{DIDHost, DIDWeb};
+use crate::error::DIDError;
+
+/// Domain that DIDs are hosted for. Each tenant has its own owners, store namespace and path prefix.
+///
+/// * `hostname` - Hostname for `did:web:<hostname>`, matched against the request's `Host` header, e.g. `example.com`
+/// * `port` - External port, e.g. `8000`. The default port is used if empty.
+/// * `path` - Path prefix of the DIDs `did:web:<hostname>:<path>:<id>`, e.g. `users`
+/// * `owners` - DIDs that are allowed to create and delete DIDs of this tenant
+/// * `namespace` - Directory in the store that the tenant's DID documents are stored in. Defaults to the hostname for
+///   configured tenants.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Tenant {
+    pub hostname: String,
+    #[serde(default)]
+    pub port: String,
+    #[serde(default)]
+    pub path: String,
+    pub owners: Vec<String>,
+    #[serde(default)]
+    pub namespace: String,
+}
+
+impl Tenant {
+    /// Validates a list of tenants, e.g. from the configuration file. The namespace defaults to the ASCII form of the
+    /// hostname.
+    pub fn validate_list(tenants: Vec<Tenant>) -> Result<Vec<Tenant>, DIDError> {
+        tenants
+            .into_iter()
+            .map(|tenant| {
+                let host = tenant.hostname.parse::<DIDHost>()?;
+                let tenant = if tenant.namespace.is_empty() {
+                    Tenant {
+                        namespace: host.to_string(),
+                        ..tenant
+                    }
+                } else {
+                    tenant
+                };
+                if tenant.owners.is_empty() {
+                    return Err(DIDError::OwnerMissing(format!(
+                        "Owner not specified for tenant {}",
+                        tenant.hostname
+                    )));
+                }
+                // the namespace is used as a directory name and must not escape the store
+                if tenant.namespace.starts_with('.')
+                    || !tenant
+                        .namespace
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+                {
+                    return Err(DIDError::IllegalCharacter(format!(
+                        "Namespace of tenant {} contains illegal characters",
+                        tenant.hostname
+                    )));
+                }
+                Ok(tenant)
+            })
+            .collect()
+    }
+
+    /// Computes the id of a DID document in the store.
+    ///
+    /// - `id` - requested id, e.g. `alice/did.json`
+    /// - returns the id prefixed with the tenant's namespace, e.g. `example.com/alice/did.json`
+    pub fn store_id(&self, id: &Path) -> PathBuf {
+        if self.namespace.is_empty() {
+            id.to_path_buf()
+        } else {
+            Path::new(&self.namespace).join(id.strip_prefix("/").unwrap_or(id))
+        }
+    }
+}
+
+impl Config {
+    /// Tenant that is derived from the external hostname, port, path and owner. It's used if no tenants are
+    /// configured.
+    pub(crate) fn default_tenant(&self) -> Tenant {
+        let policy = self.policy.load();
+        Tenant {
+            hostname: self.external_hostname.to_string(),
+            port: self.external_port.to_string(),
+            path: self.external_path.to_string(),
+            owners: vec![policy.owner.to_string()],
+            namespace: String::new(),
+        }
+    }
+
+    /// Computes the id of a DID document in the store for a DID that is hosted by this server.
+    ///
+    /// - `did` - DID that is hosted by one of the tenants
+    /// - returns `None` if the DID isn't hosted by any tenant
+    pub(crate) fn local_store_id(&self, did: &DIDWeb) -> Option<PathBuf> {
+        let policy = self.policy.load();
+        let default_tenant = [self.default_tenant()];
+        let tenants = if policy.tenants.is_empty() {
+            &default_tenant[..]
+        } else {
+            &policy.tenants[..]
+        };
+        tenants.iter().find_map(|tenant| {
+            DIDWeb::from_tenant(tenant, &PathBuf::from("/.well-known/did.json"))
+                .ok()
+                .and_then(|base| did.id_relative_to(&base))
+                .map(|id| tenant.store_id(&id))
+        })
+    }
+
+    /// Finds the tenant for a host.
+    ///
+    /// - `host` - host of the request without port, e.g. `example.com`. Hosts are compared in their ASCII form.
+    /// - returns the default tenant if no tenants are configured, otherwise the matching tenant
+    pub fn tenant(&self, host: Option<&str>) -> Result<Tenant, DIDError> {
+        let policy = self.policy.load();
+        if policy.tenants.is_empty() {
+            return Ok(self.default_tenant());
+        }
+        host.and_then(|host| host.parse::<DIDHost>().ok())
+            .and_then(|host| {
+                policy
+                    .tenants
+                    .iter()
+                    .find(|tenant| tenant.hostname.parse::<DIDHost>().ok().as_ref() == Some(&host))
+            })
+            .cloned()
+            .ok_or_else(|| {
+                DIDError::UnknownTenant(format!(
+                    "No DIDs are hosted for {}",
+                    host.unwrap_or("unknown host")
+                ))
+            })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Tenant {
+    type Error = DIDError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let config = match request.rocket().state::<Config>() {
+            Some(config) => config,
+            None => {
+                return Outcome::Error((
+                    Status::InternalServerError,
+                    DIDError::ContentConversion("Configuration missing".to_string()),
+                ))
+            }
+        };
+        match config.tenant(request.host().map(|host| host.domain().as_str())) {
+            Ok(tenant) => Outcome::Success(tenant),
+            Err(e) => Outcome::Error((e.status(), e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Policy;
+    use crate::tenant::*;
+
+    /// Parses and validates a JSON encoded list of tenants.
+    fn parse_list(tenants: &str) -> Result<Vec<Tenant>, DIDError> {
+        Tenant::validate_list(serde_json::from_str::<Vec<Tenant>>(tenants).unwrap())
+    }
+
+    #[test]
+    fn test_tenant() {
+        let tenants = parse_list(
+            r#"[{"hostname": "a.example.com", "owners": ["did:key:a"]},
+                {"hostname": "b.example.com", "path": "users", "owners": ["did:key:b"], "namespace": "b"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            tenants[0].namespace, "a.example.com",
+            "When no namespace is configured, then the hostname is used"
+        );
+        let tenants = [
+            tenants,
+            parse_list(r#"[{"hostname": "müller.ch", "owners": ["did:key:c"]}]"#).unwrap(),
+        ]
+        .concat();
+        assert_eq!(
+            tenants[2].namespace, "xn--mller-kva.ch",
+            "When the hostname is an internationalized domain name, then the namespace is its ASCII form"
+        );
+        let config = Config {
+            policy: Policy {
+                tenants,
+                ..Policy::default()
+            }
+            .into(),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.tenant(Some("B.example.com")).unwrap().owners,
+            vec!["did:key:b".to_string()],
+            "When the host matches a tenant, then the tenant is used"
+        );
+        assert_eq!(
+            config.tenant(Some("xn--mller-kva.ch")).unwrap().owners,
+            vec!["did:key:c".to_string()],
+            "When the host is punycode encoded, then it matches the tenant's Unicode hostname"
+        );
+        assert!(
+            config.tenant(Some("c.example.com")).is_err(),
+            "When the host doesn't match a tenant, then an error is returned"
+        );
+        assert_eq!(
+            config
+                .tenant(Some("b.example.com"))
+                .unwrap()
+                .store_id(Path::new("/.well-known/did.json")),
+            PathBuf::from("b/.well-known/did.json"),
+            "When a tenant has a namespace, then store ids are prefixed with the namespace"
+        );
+        assert_eq!(
+            Config::default()
+                .tenant(None)
+                .unwrap()
+                .store_id(Path::new("alice/did.json")),
+            PathBuf::from("alice/did.json"),
+            "When no tenants are configured, then store ids aren't prefixed"
+        );
+        assert!(
+            parse_list(
+                r#"[{"hostname": "a.example.com", "owners": ["did:key:a"], "namespace": ".."}]"#
+            )
+            .is_err(),
+            "When the namespace escapes the store, then an error is returned"
+        );
+        assert!(
+            parse_list(r#"[{"hostname": "a.example.com", "owners": []}]"#).is_err(),
+            "When a tenant has no owners, then an error is returned"
+        );
+    }
+}
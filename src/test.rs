@@ -2,12 +2,16 @@
 
 mod utils;
 
-use super::ship;
-use crate::config::Config;
+use super::build_rocket;
+use crate::audit::{verify_chain, AuditEntry, Operation, Outcome};
+use crate::config::{Config, Policy};
+use crate::content_types::DIDContentTypes;
 use crate::did::ProofParameters;
+use crate::health::OwnerValidator;
+use crate::tenant::Tenant;
 use crate::test_resolver::DIDWebTestResolver;
 use lazy_static::lazy_static;
-use rocket::http::Status;
+use rocket::http::{Accept, MediaType, Status};
 use rocket::local::blocking::Client;
 use ssi::did::Document;
 use ssi::did_resolve::SeriesResolver;
@@ -27,8 +31,12 @@ lazy_static! {
 
 #[test]
 fn integration_get() {
-    let client = Client::tracked(ship(Config {
-        owner: OWNER.to_string(),
+    let client = Client::tracked(build_rocket(Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
         ..Config::default()
     }))
     .expect("valid rocket instance");
@@ -43,22 +51,650 @@ fn integration_get() {
     );
 }
 
+#[test]
+fn integration_resolve() {
+    let config = Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        ..Config::default()
+    };
+    let document = serde_json::from_str::<Document>(
+        &utils::read_file("./src/__fixtures__/valid-did.json").unwrap(),
+    )
+    .unwrap();
+    rocket::execute(
+        config
+            .store
+            .create(&PathBuf::from("valid-did/did.json"), document),
+    )
+    .unwrap();
+    let client = Client::tracked(build_rocket(config)).expect("valid rocket instance");
+
+    let response = client
+        .get("/1.0/identifiers/did:web:localhost%3A8000:valid-did")
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When a DID hosted by the server is resolved, then return 200 - ok."
+    );
+    assert_eq!(
+        response.content_type(),
+        Some(DIDContentTypes::DID_RESOLUTION)
+    );
+    let result = response.into_json::<serde_json::Value>().unwrap();
+    assert_eq!(
+        result["didDocument"]["id"], "did:web:localhost%3A8000:valid-did",
+        "When a DID hosted by the server is resolved, then the DID document is returned from the store."
+    );
+
+    let response = client
+        .get("/1.0/identifiers/did:web:localhost%3A8000:valid-did")
+        .header(Accept::from(MediaType::new("application", "did+ld+json")))
+        .dispatch();
+    assert_eq!(
+        response.content_type(),
+        Some(DIDContentTypes::DID_LD_JSON),
+        "When the DID document is requested, then only the DID document is returned."
+    );
+
+    let response = client
+        .get("/1.0/identifiers/did:web:localhost%3A8000:unknown")
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::NotFound,
+        "When a DID hosted by the server doesn't exist, then return 404 - not found."
+    );
+
+    let response = client
+        .get(format!("/1.0/identifiers/{}", *OWNER))
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When a did:key DID is resolved, then the built-in resolver is used and 200 - ok is returned."
+    );
+
+    let response = client
+        .get("/1.0/identifiers/did:web:127.0.0.1%3A6379:alice")
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Forbidden,
+        "When a did:web DID of an internal host is resolved, then return 403 - forbidden."
+    );
+
+    let response = client
+        .get("/1.0/identifiers/did:web:example.org:alice")
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Forbidden,
+        "When a did:web DID of a host that isn't allowed is resolved, then return 403 - forbidden."
+    );
+
+    let response = client.get("/1.0/identifiers/invalid").dispatch();
+    assert_eq!(
+        response.status(),
+        Status::BadRequest,
+        "When an invalid DID is resolved, then return 400 - bad request."
+    );
+}
+
+#[test]
+fn integration_dereference() {
+    let config = Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        ..Config::default()
+    };
+    let mut document = serde_json::from_str::<serde_json::Value>(
+        &utils::read_file("./src/__fixtures__/valid-did.json").unwrap(),
+    )
+    .unwrap();
+    document["service"] = serde_json::json!([{
+        "id": "did:web:localhost%3A8000:valid-did#files",
+        "type": "LinkedDomains",
+        "serviceEndpoint": "https://files.example.com/valid-did/"
+    }]);
+    rocket::execute(config.store.create(
+        &PathBuf::from("valid-did/did.json"),
+        serde_json::from_value(document).unwrap(),
+    ))
+    .unwrap();
+    let client = Client::tracked(build_rocket(config)).expect("valid rocket instance");
+
+    let response = client
+        .get("/1.0/identifiers/did:web:localhost%3A8000:valid-did%23controller")
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When a DID URL with a fragment is dereferenced, then return 200 - ok."
+    );
+    let resource = response.into_json::<serde_json::Value>().unwrap();
+    assert_eq!(
+        resource["id"], "did:web:localhost%3A8000:valid-did#controller",
+        "When a DID URL with a fragment is dereferenced, then the verification method is returned."
+    );
+
+    let response = client
+        .get("/1.0/identifiers/did:web:localhost%3A8000:valid-did?service=files&relativeRef=%2Fdocs%2Fa.pdf")
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::SeeOther,
+        "When a DID URL with a service parameter is dereferenced, then return 303 - see other."
+    );
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("https://files.example.com/docs/a.pdf"),
+        "When a relative reference is given, then it's resolved against the service endpoint."
+    );
+
+    let response = client
+        .get("/1.0/identifiers/did:web:localhost%3A8000:valid-did%23unknown")
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::NotFound,
+        "When the fragment doesn't exist in the DID document, then return 404 - not found."
+    );
+
+    let response = client
+        .get("/1.0/identifiers/did:web:localhost%3A8000:valid-did?relativeRef=a.pdf")
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::BadRequest,
+        "When a relative reference is given without a service, then return 400 - bad request."
+    );
+}
+
+#[test]
+fn integration_owner_validation() {
+    let config = |owner: &str, lenient_startup| Config {
+        lenient_startup,
+        policy: Policy {
+            owner: owner.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        ..Config::default()
+    };
+    assert!(
+        Client::tracked(build_rocket(config(OWNER.as_str(), false)).attach(OwnerValidator)).is_ok(),
+        "When the owner's DID can be resolved and has authentication methods, then the server starts."
+    );
+    assert!(
+        Client::tracked(build_rocket(config("did:invalid:owner", false)).attach(OwnerValidator))
+            .is_err(),
+        "When the owner's DID can't be resolved, then the server doesn't start."
+    );
+    assert!(
+        Client::tracked(build_rocket(config("did:invalid:owner", true)).attach(OwnerValidator))
+            .is_ok(),
+        "When the owner's DID can't be resolved in lenient mode, then the server starts."
+    );
+}
+
+#[test]
+fn integration_health() {
+    let client = Client::tracked(build_rocket(Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        ..Config::default()
+    }))
+    .expect("valid rocket instance");
+
+    let response = client.get(uri!(crate::health::healthz)).dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When the server is running, then the liveness probe returns 200 - ok."
+    );
+
+    let response = client.get(uri!(crate::health::readyz)).dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When the store is accessible and the owner resolves, then the readiness probe returns 200 - ok."
+    );
+
+    let response = client.get(uri!(crate::health::version)).dispatch();
+    let build_info = response.into_json::<serde_json::Value>().unwrap();
+    assert_eq!(
+        build_info["version"],
+        env!("CARGO_PKG_VERSION"),
+        "When the build information is requested, then the crate version is returned."
+    );
+    assert!(
+        build_info["backends"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::Value::from("mem")),
+        "When the build information is requested, then the available backends are returned."
+    );
+
+    let client = Client::tracked(build_rocket(Config {
+        policy: Policy {
+            owner: "did:invalid:owner".to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        ..Config::default()
+    }))
+    .expect("valid rocket instance");
+    let response = client.get(uri!(crate::health::readyz)).dispatch();
+    assert_eq!(
+        response.status(),
+        Status::ServiceUnavailable,
+        "When the owner's DID can't be resolved, then the readiness probe returns 503 - service unavailable."
+    );
+
+    let client = Client::tracked(build_rocket(Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            tenants: vec![Tenant {
+                hostname: "example.com".to_string(),
+                port: String::new(),
+                path: String::new(),
+                owners: vec!["did:invalid:owner".to_string()],
+                namespace: "example.com".to_string(),
+            }],
+            ..Policy::default()
+        }
+        .into(),
+        ..Config::default()
+    }))
+    .expect("valid rocket instance");
+    let response = client.get(uri!(crate::health::readyz)).dispatch();
+    assert_eq!(
+        response.status(),
+        Status::ServiceUnavailable,
+        "When a tenant owner's DID can't be resolved, then the readiness probe returns 503 - service unavailable."
+    );
+}
+
+#[test]
+fn integration_metrics() {
+    let client = Client::tracked(build_rocket(Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        metrics_token: Some("metrics".to_string()),
+        ..Config::default()
+    }))
+    .expect("valid rocket instance");
+
+    let response = client
+        .get(uri!(super::get(id = PathBuf::from("unknown/did.json"))))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client.get(uri!(crate::metrics::get_metrics)).dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Unauthorized,
+        "When metrics are requested without the metrics token, then return 401 - unauthorized."
+    );
+
+    let response = client
+        .get(uri!(crate::metrics::get_metrics))
+        .header(rocket::http::Header::new("Authorization", "Bearer metrics"))
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When metrics are requested with the metrics token, then return 200 - ok."
+    );
+    let metrics = response.into_string().unwrap();
+    assert!(
+        metrics.contains(r#"dws_http_requests_total{method="GET",route="get",status="404"}"#),
+        "When a request has been handled, then it's counted per route and status."
+    );
+    assert!(
+        metrics.contains(r#"dws_errors_total{error="DIDNotFound"}"#),
+        "When an error has been returned, then it's counted per error."
+    );
+    assert!(
+        metrics.contains(
+            r#"dws_store_operation_duration_seconds_count{operation="get",result="error"}"#
+        ),
+        "When the store has been accessed, then the latency is recorded."
+    );
+    assert!(
+        metrics.contains("dws_stored_dids 0"),
+        "When the store is empty, then the number of stored DIDs is 0."
+    );
+}
+
+#[test]
+fn integration_openapi() {
+    let client = Client::tracked(build_rocket(Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        api_explorer: true,
+        ..Config::default()
+    }))
+    .expect("valid rocket instance");
+
+    let response = client.get(uri!(crate::openapi::get_openapi)).dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When the OpenAPI specification is requested, then return 200 - ok."
+    );
+    let spec = response.into_json::<serde_json::Value>().unwrap();
+    for route in client.rocket().routes() {
+        let method = route.method.as_str().to_lowercase();
+        assert!(
+            spec["paths"][crate::openapi::path_template(route)][&method].is_object(),
+            "When a route is mounted, then it's documented in the OpenAPI specification: {}",
+            route
+        );
+    }
+    assert!(
+        spec["paths"]["/{id}/did.json"]["get"]["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|parameter| parameter["name"] == "proofParameters"),
+        "When the DID document and the proof parameters share a path, then the proofParameters query parameter is documented"
+    );
+
+    let response = client
+        .get(uri!(crate::openapi::get_openapi_explorer))
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When the API explorer is enabled, then return 200 - ok."
+    );
+}
+
+/// Route of an application that embeds the server, named like one of the server's routes.
+#[get("/other/<id>")]
+fn get(id: &str) -> String {
+    id.to_string()
+}
+
+#[test]
+fn integration_openapi_mounted() {
+    let rocket = rocket::custom(crate::config::figment()).mount("/", routes![get]);
+    let client = Client::tracked(crate::mount(
+        rocket,
+        "/dids",
+        Config {
+            policy: Policy {
+                owner: OWNER.to_string(),
+                ..Policy::default()
+            }
+            .into(),
+            ..Config::default()
+        },
+    ))
+    .expect("valid rocket instance");
+
+    let response = client.get("/dids/.well-known/openapi.json").dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When the server is mounted under a prefix, then the OpenAPI specification is mounted under the prefix."
+    );
+    let spec = response.into_json::<serde_json::Value>().unwrap();
+    assert!(
+        spec["paths"]["/dids/{id}/did.json"]["get"].is_object(),
+        "When the server is mounted under a prefix, then its routes are documented with the prefix."
+    );
+    assert!(
+        spec["paths"]["/other/{id}"].is_null(),
+        "When the application mounts a route that's named like a route of the server, then it isn't documented."
+    );
+}
+
+#[test]
+fn integration_replication() {
+    let config = Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        replication_token: Some("secret".to_string()),
+        ..Config::default()
+    };
+    let document = serde_json::from_str::<Document>(
+        &utils::read_file("./src/__fixtures__/valid-did.json").unwrap(),
+    )
+    .unwrap();
+    rocket::execute(
+        config
+            .store
+            .create(&PathBuf::from("valid-did/did.json"), document),
+    )
+    .unwrap();
+    let client = Client::tracked(build_rocket(config)).expect("valid rocket instance");
+
+    let response = client.get(uri!(crate::replica::get_snapshot)).dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Unauthorized,
+        "When the replication token is missing, then return 401 - unauthorized."
+    );
+    let response = client
+        .get(uri!(crate::replica::get_snapshot))
+        .header(rocket::http::Header::new("Authorization", "Bearer wrong"))
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Unauthorized,
+        "When the replication token is wrong, then return 401 - unauthorized."
+    );
+    let response = client
+        .get(uri!(crate::replica::get_snapshot))
+        .header(rocket::http::Header::new("Authorization", "Bearer secret"))
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When the replication token is valid, then return 200 - ok."
+    );
+    let entries = response.into_json::<Vec<crate::replica::Entry>>().unwrap();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<PathBuf>>(),
+        vec![PathBuf::from("valid-did/did.json")],
+        "When a DID is stored, then it's part of the snapshot."
+    );
+
+    let client = Client::tracked(build_rocket(Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        replica: Some(crate::replica::Replica {
+            primary: "http://127.0.0.1:9".to_string(),
+            interval: std::time::Duration::from_secs(3600),
+            max_removed: 50,
+        }),
+        ..Config::default()
+    }))
+    .expect("valid rocket instance");
+    let response = client
+        .put("/valid-did")
+        .body(r#"{"@context":["https://www.w3.org/2018/credentials/v1"],"type":["VerifiablePresentation"]}"#)
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::MethodNotAllowed,
+        "When a DID is changed on a replica, then return 405 - method not allowed."
+    );
+    let response = client.get(uri!(crate::replica::get_snapshot)).dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Unauthorized,
+        "When replication is disabled, then return 401 - unauthorized."
+    );
+}
+
+#[test]
+fn integration_change_feed() {
+    let config = Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        // only the most recent change is retained, so the first change is compacted
+        changes: crate::changes::ChangeFeed::new(None, 1).unwrap().into(),
+        change_feed_token: Some("secret".to_string()),
+        replication_token: Some("replication".to_string()),
+        ..Config::default()
+    };
+    for operation in [
+        crate::audit::Operation::Create,
+        crate::audit::Operation::Update,
+    ] {
+        rocket::execute(async {
+            config
+                .changes
+                .lock()
+                .await
+                .publish(
+                    config.store.as_ref(),
+                    operation,
+                    "did:web:localhost:valid-did",
+                    &PathBuf::from("valid-did/did.json"),
+                    None,
+                )
+                .await
+        })
+        .unwrap();
+    }
+    let client = Client::tracked(build_rocket(config)).expect("valid rocket instance");
+
+    let response = client
+        .get(uri!(crate::changes::get_changes(
+            since = Some(0),
+            limit = _
+        )))
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Unauthorized,
+        "When the change feed token is missing, then return 401 - unauthorized."
+    );
+    let response = client
+        .get(uri!(crate::changes::get_changes(
+            since = Some(0),
+            limit = _
+        )))
+        .header(rocket::http::Header::new(
+            "Authorization",
+            "Bearer replication",
+        ))
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Unauthorized,
+        "When the replication token is presented, then return 401 - unauthorized."
+    );
+    let response = client
+        .get(uri!(crate::changes::get_changes(
+            since = Some(1),
+            limit = _
+        )))
+        .header(rocket::http::Header::new("Authorization", "Bearer secret"))
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When the change feed token is valid, then return 200 - ok."
+    );
+    let changes = response.into_json::<Vec<crate::changes::Change>>().unwrap();
+    assert_eq!(
+        changes
+            .iter()
+            .map(|change| (change.sequence, change.operation))
+            .collect::<Vec<(u64, crate::audit::Operation)>>(),
+        vec![(2, crate::audit::Operation::Update)],
+        "When changes are polled since a sequence number, then only later changes are returned."
+    );
+    let response = client
+        .get(uri!(crate::changes::get_changes(since = _, limit = _)))
+        .header(rocket::http::Header::new("Authorization", "Bearer secret"))
+        .dispatch();
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When changes have been compacted and are polled without sequence number, then return 200 - ok."
+    );
+    assert_eq!(
+        response
+            .into_json::<Vec<crate::changes::Change>>()
+            .unwrap()
+            .iter()
+            .map(|change| change.sequence)
+            .collect::<Vec<u64>>(),
+        vec![2],
+        "When changes are polled without sequence number, then they start with the oldest retained change."
+    );
+
+    let response = client
+        .get(uri!(crate::changes::stream_changes(since = Some(0))))
+        .header(rocket::http::Header::new("Authorization", "Bearer secret"))
+        .dispatch();
+    assert_eq!(
+        response.content_type(),
+        Some(rocket::http::ContentType::EventStream),
+        "When changes are streamed, then return Server-Sent Events."
+    );
+}
+
 #[rocket::async_test]
 async fn integration_create() {
     use rocket::local::asynchronous::Client;
     let config = Config {
-        owner: OWNER.to_string(),
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
         ..Config::default()
     };
-    let client = Client::tracked(ship(config))
+    let client = Client::tracked(build_rocket(config))
         .await
         .expect("valid rocket instance");
 
     let resolver_config = Config {
-        owner: OWNER.to_string(),
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
         ..Config::default()
     };
-    let std_resolvers = resolver_config.reslover_options.get_resolver();
+    let policy = resolver_config.policy.load();
+    let std_resolvers = policy.reslover_options.get_resolver();
     let test_resolver = DIDWebTestResolver {
         client: Some(&client),
         ..DIDWebTestResolver::default()
@@ -210,6 +846,32 @@ async fn integration_create() {
         proof_parameters.challenge.unwrap(), "d992a52400965351e261fdcfa47469cb3e0fa06cc658208c3c95bddf577dc29a",
         "When DID is created in store, then the challenge is set to a unique and deterministic value."
     );
+    let changes = client
+        .rocket()
+        .state::<Config>()
+        .unwrap()
+        .changes
+        .since(0, usize::MAX)
+        .unwrap();
+    assert_eq!(
+        changes
+            .iter()
+            .map(|change| (change.operation, change.id.clone()))
+            .collect::<Vec<(crate::audit::Operation, PathBuf)>>(),
+        vec![(
+            crate::audit::Operation::Create,
+            PathBuf::from("valid-did/did.json")
+        )],
+        "When DID is created in store, then the creation is published to the change feed."
+    );
+    assert_eq!(
+        changes[0]
+            .document
+            .as_ref()
+            .map(|document| document.id.as_str()),
+        Some("did:web:localhost%3A8000:valid-did"),
+        "When DID is created in store, then the change carries the created DID document."
+    );
 
     // get
     // ---
@@ -376,22 +1038,273 @@ async fn integration_create() {
     // TODO: create a did document in the wrong location: When a valid presentation and DID document is sent by the owner but the location of the document is wrong, then return 400 - bad request.
 }
 
+/// Submits a presentation with an invitation and the DID document of `valid-did` that's signed by the invitee.
+async fn create_with_invitation(
+    client: &rocket::local::asynchronous::Client,
+    resolver: &SeriesResolver<'_>,
+    invitation: ssi::vc::Credential,
+) -> Status {
+    let response = client
+        .get(uri!(super::get_proof_parameters(
+            id = PathBuf::from("valid-did/did.json"),
+        )))
+        .dispatch()
+        .await;
+    let proof_parameters = response.into_json::<ProofParameters>().await.unwrap();
+    let invitee_key = utils::read_file("./src/__fixtures__/not-owner.jwk").unwrap();
+    let invitee_key = JWK::from(Params::OKP(
+        serde_json::from_str::<OctetParams>(&invitee_key).unwrap(),
+    ));
+    let mut attributes =
+        utils::json_file_to_attributes_or_panic("./src/__fixtures__/valid-did.json");
+    let id = match attributes.remove("id").unwrap() {
+        rocket::serde::json::serde_json::Value::String(id) => Some(id),
+        _ => None,
+    }
+    .unwrap();
+    let credential = utils::create_credential_or_panic(
+        &NOT_OWNER,
+        &id,
+        "https://example.com/vc/123",
+        Some(attributes),
+        None,
+        None,
+        resolver,
+        &NOT_OWNER_VERIFICATION_METHOD,
+        &invitee_key,
+    )
+    .await;
+    let presentation = utils::create_presentation_or_panic(
+        &NOT_OWNER,
+        OneOrMany::Many(vec![
+            ssi::vc::CredentialOrJWT::Credential(invitation),
+            ssi::vc::CredentialOrJWT::Credential(credential),
+        ]),
+        &LinkedDataProofOptions {
+            type_: Some(ProofSuiteType::Ed25519Signature2020),
+            domain: Some(proof_parameters.domain.to_string()),
+            challenge: Some(proof_parameters.challenge.unwrap()),
+            proof_purpose: Some(proof_parameters.proof_purpose.to_owned()),
+            verification_method: Some(URI::String(NOT_OWNER_VERIFICATION_METHOD.to_string())),
+            ..LinkedDataProofOptions::default()
+        },
+        resolver,
+        &invitee_key,
+    )
+    .await;
+    client
+        .post(uri!(super::create(
+            id = PathBuf::from("valid-did/did.json"),
+        )))
+        .body(serde_json::to_string(&presentation).unwrap())
+        .dispatch()
+        .await
+        .status()
+}
+
 #[rocket::async_test]
-async fn integration_update() {
+async fn integration_create_with_invitation() {
     use rocket::local::asynchronous::Client;
     let config = Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        ..Config::default()
+    };
+    let client = Client::tracked(build_rocket(config))
+        .await
+        .expect("valid rocket instance");
+    let resolver_config = Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        ..Config::default()
+    };
+    let policy = resolver_config.policy.load();
+    let std_resolvers = policy.reslover_options.get_resolver();
+    let test_resolver = DIDWebTestResolver {
+        client: Some(&client),
+        ..DIDWebTestResolver::default()
+    };
+    let resolver = SeriesResolver {
+        resolvers: vec![&test_resolver, &std_resolvers],
+    };
+    let owner_key = utils::read_file("./src/__fixtures__/owner.jwk").unwrap();
+    let owner_key = JWK::from(Params::OKP(
+        serde_json::from_str::<OctetParams>(&owner_key).unwrap(),
+    ));
+    let tomorrow = Some(VCDateTime::from(
+        chrono::Utc::now() + chrono::Duration::days(1),
+    ));
+
+    let invitation = utils::create_invitation_or_panic(
+        &OWNER,
+        &NOT_OWNER,
+        ("path", "other-did"),
+        tomorrow.clone(),
+        &resolver,
+        &OWNER_VERIFICATION_METHOD,
+        &owner_key,
+    )
+    .await;
+    assert_eq!(
+        create_with_invitation(&client, &resolver, invitation).await,
+        Status::Unauthorized,
+        "When the invitation has been issued for a different path, then return 401 - Unauthorized."
+    );
+
+    let invitation = utils::create_invitation_or_panic(
+        &OWNER,
+        &NOT_OWNER,
+        ("path", "valid-did"),
+        Some(VCDateTime::from(
+            chrono::Utc::now() - chrono::Duration::days(1),
+        )),
+        &resolver,
+        &OWNER_VERIFICATION_METHOD,
+        &owner_key,
+    )
+    .await;
+    assert_eq!(
+        create_with_invitation(&client, &resolver, invitation).await,
+        Status::Unauthorized,
+        "When the invitation has expired, then return 401 - Unauthorized."
+    );
+
+    let invitation = utils::create_invitation_or_panic(
+        &OWNER,
+        &NOT_OWNER,
+        ("path", "valid-did"),
+        tomorrow,
+        &resolver,
+        &OWNER_VERIFICATION_METHOD,
+        &owner_key,
+    )
+    .await;
+    assert_eq!(
+        create_with_invitation(&client, &resolver, invitation.clone()).await,
+        Status::Created,
+        "When the invitee presents a valid invitation, then the DID is created and 201 - created is returned."
+    );
+    assert_eq!(
+        create_with_invitation(&client, &resolver, invitation.clone()).await,
+        Status::Unauthorized,
+        "When an invitation is presented again, then return 401 - Unauthorized."
+    );
+    // the order of the types isn't covered by the signature, so the copy still verifies
+    let mut reserialised = invitation;
+    if let OneOrMany::Many(types) = &mut reserialised.type_ {
+        types.reverse();
+    }
+    assert_eq!(
+        create_with_invitation(&client, &resolver, reserialised).await,
+        Status::Unauthorized,
+        "When a redeemed invitation is presented in a different serialisation, then return 401 - Unauthorized."
+    );
+}
+
+#[rocket::async_test]
+async fn integration_create_unrecorded() {
+    use rocket::local::asynchronous::Client;
+    let file = std::env::temp_dir().join(format!("dws-unrecorded-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_dir(&file);
+    let config = Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
+        changes: crate::changes::ChangeFeed::new(Some(file.clone()), crate::changes::RETENTION)
+            .unwrap()
+            .into(),
+        ..Config::default()
+    };
+    let store = config.store.clone();
+    let client = Client::tracked(build_rocket(config))
+        .await
+        .expect("valid rocket instance");
+    let policy = Policy {
         owner: OWNER.to_string(),
+        ..Policy::default()
+    };
+    let std_resolvers = policy.reslover_options.get_resolver();
+    let test_resolver = DIDWebTestResolver {
+        client: Some(&client),
+        ..DIDWebTestResolver::default()
+    };
+    let resolver = SeriesResolver {
+        resolvers: vec![&test_resolver, &std_resolvers],
+    };
+    let owner_key = utils::read_file("./src/__fixtures__/owner.jwk").unwrap();
+    let owner_key = JWK::from(Params::OKP(
+        serde_json::from_str::<OctetParams>(&owner_key).unwrap(),
+    ));
+    let invitation = utils::create_invitation_or_panic(
+        &OWNER,
+        &NOT_OWNER,
+        ("path", "valid-did"),
+        Some(VCDateTime::from(
+            chrono::Utc::now() + chrono::Duration::days(1),
+        )),
+        &resolver,
+        &OWNER_VERIFICATION_METHOD,
+        &owner_key,
+    )
+    .await;
+
+    // a directory in place of the feed's file makes appending fail
+    std::fs::create_dir(&file).unwrap();
+    assert_eq!(
+        create_with_invitation(&client, &resolver, invitation.clone()).await,
+        Status::InternalServerError,
+        "When the change can't be recorded in the change feed, then return 500 - internal server error."
+    );
+    assert!(
+        store
+            .get(&PathBuf::from("valid-did/did.json"))
+            .await
+            .is_err(),
+        "When the change can't be recorded in the change feed, then the DID document isn't stored."
+    );
+
+    std::fs::remove_dir(&file).unwrap();
+    assert_eq!(
+        create_with_invitation(&client, &resolver, invitation).await,
+        Status::Created,
+        "When a creation failed because its change wasn't recorded, then the invitation can be presented again."
+    );
+    let _ = std::fs::remove_file(&file);
+}
+
+#[rocket::async_test]
+async fn integration_update() {
+    use rocket::local::asynchronous::Client;
+    let config = Config {
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
         ..Config::default()
     };
-    let client = Client::tracked(ship(config))
+    let client = Client::tracked(build_rocket(config))
         .await
         .expect("valid rocket instance");
 
     let resolver_config = Config {
-        owner: OWNER.to_string(),
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
         ..Config::default()
     };
-    let std_resolvers = resolver_config.reslover_options.get_resolver();
+    let policy = resolver_config.policy.load();
+    let std_resolvers = policy.reslover_options.get_resolver();
     let test_resolver = DIDWebTestResolver {
         client: Some(&client),
         ..DIDWebTestResolver::default()
@@ -860,18 +1773,27 @@ async fn integration_update() {
 async fn integration_delete() {
     use rocket::local::asynchronous::Client;
     let config = Config {
-        owner: OWNER.to_string(),
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
         ..Config::default()
     };
-    let client = Client::tracked(ship(config))
+    let client = Client::tracked(build_rocket(config))
         .await
         .expect("valid rocket instance");
 
     let resolver_config = Config {
-        owner: OWNER.to_string(),
+        policy: Policy {
+            owner: OWNER.to_string(),
+            ..Policy::default()
+        }
+        .into(),
         ..Config::default()
     };
-    let std_resolvers = resolver_config.reslover_options.get_resolver();
+    let policy = resolver_config.policy.load();
+    let std_resolvers = policy.reslover_options.get_resolver();
     let test_resolver = DIDWebTestResolver {
         client: Some(&client),
         ..DIDWebTestResolver::default()
@@ -1141,4 +2063,92 @@ async fn integration_delete() {
         Status::NotFound,
         "When a non-existing DID is attempted to be deleted, then 404 - Not Found is returned."
     );
+
+    // export audit log as server-owner
+    // ------
+    let response = client
+        .get(uri!(crate::audit::get_audit_log_proof_parameters))
+        .dispatch()
+        .await;
+    let proof_parameters = response.into_json::<ProofParameters>().await.unwrap();
+    let presentation = utils::create_presentation_or_panic(
+        &OWNER,
+        OneOrMany::Many(vec![]),
+        &LinkedDataProofOptions {
+            type_: Some(ProofSuiteType::Ed25519Signature2020),
+            domain: Some(proof_parameters.domain.to_string()),
+            challenge: Some(proof_parameters.challenge.unwrap()),
+            proof_purpose: Some(proof_parameters.proof_purpose.to_owned()),
+            verification_method: Some(URI::String(OWNER_VERIFICATION_METHOD.to_string())),
+            ..LinkedDataProofOptions::default()
+        },
+        &resolver,
+        &owner_key,
+    )
+    .await;
+    let presentation_string = serde_json::to_string(&presentation).unwrap();
+    // a rejected export doesn't change the challenge
+    let mut tampered = serde_json::to_value(&presentation).unwrap();
+    tampered["proof"]["challenge"] = serde_json::Value::from("tampered");
+    let response = client
+        .post(uri!(crate::audit::export_audit_log))
+        .body(tampered.to_string())
+        .dispatch()
+        .await;
+    assert_eq!(
+        response.status(),
+        Status::Unauthorized,
+        "When an export presentation has an invalid proof, then 401 - Unauthorized is returned."
+    );
+    let response = client
+        .post(uri!(crate::audit::export_audit_log))
+        .body(presentation_string.to_string())
+        .dispatch()
+        .await;
+    assert_eq!(
+        response.status(),
+        Status::Ok,
+        "When the owner of the server exports the audit log, then 200 - OK is returned."
+    );
+    let entries = response.into_json::<Vec<AuditEntry>>().await.unwrap();
+    assert!(
+        verify_chain(&entries, None).is_ok(),
+        "When the audit log is exported, then the entries form an unbroken chain."
+    );
+    let outcomes: Vec<(Operation, Outcome)> = entries
+        .iter()
+        .map(|entry| (entry.operation, entry.outcome))
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            (Operation::Create, Outcome::Success),
+            (Operation::Delete, Outcome::Rejected),
+            (Operation::Delete, Outcome::Success),
+            (Operation::Delete, Outcome::Rejected),
+            (Operation::Export, Outcome::Rejected),
+            (Operation::Export, Outcome::Success),
+        ],
+        "When operations have been performed, then every successful and rejected operation is recorded."
+    );
+    assert_eq!(
+        entries[2].verification_method.as_deref(),
+        Some(*OWNER_VERIFICATION_METHOD),
+        "When an operation is recorded, then the authorizing verification method is recorded."
+    );
+    assert!(
+        entries[2].old_document_hash.is_some(),
+        "When a DID is deleted, then the hash of the deleted document is recorded."
+    );
+
+    let response = client
+        .post(uri!(crate::audit::export_audit_log))
+        .body(presentation_string)
+        .dispatch()
+        .await;
+    assert_eq!(
+        response.status(),
+        Status::Unauthorized,
+        "When an export presentation is replayed, then 401 - Unauthorized is returned."
+    );
 }
@@ -120,3 +120,67 @@ pub async fn create_presentation_or_panic(
     presentation.add_proof(proof);
     presentation
 }
+
+pub async fn create_invitation_or_panic(
+    issuer: &str,
+    invitee: &str,
+    restriction: (&str, &str),
+    expiration_date: Option<VCDateTime>,
+    resolver: &SeriesResolver<'_>,
+    verification_method: &str,
+    key: &ssi::jwk::JWK,
+) -> Credential {
+    let context = match &*crate::invitation::INVITATION_CONTEXT {
+        rocket::serde::json::serde_json::Value::Object(context) => context.clone(),
+        _ => panic!("invitation context is an object"),
+    };
+    let mut credential = Credential {
+        context: Contexts::Many(vec![
+            Context::URI(URI::String(
+                "https://www.w3.org/2018/credentials/v1".to_string(),
+            )),
+            Context::Object(context),
+        ]),
+        id: Some(StringOrURI::String(
+            "https://example.com/invitations/1".to_string(),
+        )),
+        type_: OneOrMany::Many(vec![
+            "VerifiableCredential".to_string(),
+            crate::invitation::INVITATION_TYPE.to_string(),
+        ]),
+        credential_subject: OneOrMany::One(ssi::vc::CredentialSubject {
+            id: Some(URI::String(invitee.to_string())),
+            property_set: Some(HashMap::from([(
+                restriction.0.to_string(),
+                rocket::serde::json::serde_json::Value::String(restriction.1.to_string()),
+            )])),
+        }),
+        issuer: Some(ssi::vc::Issuer::URI(URI::String(issuer.to_string()))),
+        issuance_date: Some(VCDateTime::from(Utc::now())),
+        proof: None, // added later
+        expiration_date,
+        credential_status: None,
+        terms_of_use: None,
+        evidence: None,
+        credential_schema: None,
+        refresh_service: None,
+        property_set: None,
+    };
+    let mut context_loader = ContextLoader::default();
+    let proof = credential
+        .generate_proof(
+            key,
+            &LinkedDataProofOptions {
+                type_: Some(ProofSuiteType::Ed25519Signature2020),
+                proof_purpose: Some(ssi::vc::ProofPurpose::AssertionMethod),
+                verification_method: Some(URI::String(verification_method.to_string())),
+                ..LinkedDataProofOptions::default()
+            },
+            resolver,
+            &mut context_loader,
+        )
+        .await
+        .unwrap();
+    credential.add_proof(proof);
+    credential
+}
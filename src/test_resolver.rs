@@ -7,40 +7,24 @@
 /// Custom Resolvers that use the did:web Server endpoint methods either directly or through the
 /// rocket client
 ///
+use crate::did::DIDWeb;
 use crate::store::DIDWebStore;
-use crate::utils::path_to_string;
 use async_trait::async_trait;
-use either::{Either, Left, Right};
 use rocket::http::Status;
 use rocket::local::asynchronous::Client;
 use ssi::did::{DIDMethod, Document};
 use ssi::did_resolve::{
-    DIDResolver, DocumentMetadata, ResolutionInputMetadata, ResolutionMetadata,
+    DIDResolver, DocumentMetadata, ResolutionInputMetadata, ResolutionMetadata, ERROR_INVALID_DID,
     ERROR_METHOD_NOT_SUPPORTED, ERROR_NOT_FOUND, TYPE_DID_LD_JSON,
 };
-use std::path::PathBuf;
-
-fn did_web_id(did: &str) -> Result<Either<PathBuf, PathBuf>, ResolutionMetadata> {
-    let mut parts = did.split(':').peekable();
-    let _domain_name = match (parts.next(), parts.next(), parts.next()) {
-        (Some("did"), Some("web"), Some(domain_name)) => domain_name,
-        _ => {
-            return Err(ResolutionMetadata::from_error(ERROR_METHOD_NOT_SUPPORTED));
-        }
-    };
-    let path = match parts.peek() {
-        Some(_) => Right(parts.collect::<Vec<&str>>().join("/")),
-        None => Left(".well-known".to_string()),
-    };
-    let to_did_path = |s| PathBuf::from(format!("{}/did.json", s));
-    Ok(path.map_right(to_did_path).map_left(to_did_path))
-}
+use std::str::FromStr;
+use std::sync::Arc;
 
 /// did:web Method
 ///
 /// [Specification](https://w3c-ccg.github.io/did-method-web/)
 pub struct DIDWebTestResolver<'a> {
-    pub store: Option<&'a Box<dyn DIDWebStore + Sync + Send>>,
+    pub store: Option<&'a Arc<dyn DIDWebStore + Sync + Send>>,
     pub client: Option<&'a Client>,
 }
 
@@ -95,15 +79,26 @@ impl DIDResolver for DIDWebTestResolver<'_> {
         did: &str,
         _input_metadata: &ResolutionInputMetadata,
     ) -> (ResolutionMetadata, Vec<u8>, Option<DocumentMetadata>) {
-        let id = match did_web_id(did) {
-            Err(meta) => return (meta, Vec::new(), None),
-            Ok(id) => id,
+        let did = match DIDWeb::from_str(did) {
+            Ok(did) => did,
+            Err(_) if !did.starts_with("did:web:") => {
+                return (
+                    ResolutionMetadata::from_error(ERROR_METHOD_NOT_SUPPORTED),
+                    Vec::new(),
+                    None,
+                )
+            }
+            Err(_) => {
+                return (
+                    ResolutionMetadata::from_error(ERROR_INVALID_DID),
+                    Vec::new(),
+                    None,
+                )
+            }
         };
 
         if let Some(store) = self.store {
-            // ist die ID falsch?
-            let get_from_store = |id: PathBuf| store.get(&id);
-            let resp = id.either(get_from_store, get_from_store);
+            let resp = store.get(&did.path()).await;
 
             match resp {
                 Ok(doc) => {
@@ -125,14 +120,10 @@ impl DIDResolver for DIDWebTestResolver<'_> {
                 ),
             }
         } else if let Some(client) = self.client {
-            let id_to_url = |id: PathBuf| {
-                let path = path_to_string(&id, "/");
-                format!("/{}", path)
-            };
-            let url = id.map_right(id_to_url).map_left(id_to_url);
-            let resp = match url {
-                Right(url) => client.get(uri!(crate::get(id = PathBuf::from(url)))),
-                Left(_url) => client.get(uri!(crate::get_wellknown())),
+            let resp = if did.path().starts_with(".well-known") {
+                client.get(uri!(crate::get_wellknown()))
+            } else {
+                client.get(uri!(crate::get(id = did.path())))
             }
             .dispatch()
             .await;
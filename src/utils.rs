@@ -1,12 +1,11 @@
 // SPDX-License-Identifier: AGPL-3.0
 
+use std::cmp::Ordering;
 use std::path::Path;
-use std::{cmp::Ordering, fmt};
 
 use chrono::{DateTime, Utc};
 use rocket::serde::json::Json;
 use ssi::did::VerificationRelationship;
-use ssi::did_resolve::SeriesResolver;
 use ssi::vc::{
     Credential, CredentialOrJWT, CredentialSubject, LinkedDataProofOptions, Presentation,
     VCDateTime, VerificationResult,
@@ -17,8 +16,7 @@ use ssi_json_ld::ContextLoader;
 use crate::config::Config;
 use crate::did::ProofParameters;
 use crate::error::DIDError;
-#[cfg(test)]
-use crate::test_resolver::DIDWebTestResolver;
+use crate::metrics;
 
 /// Join a path into a String with separator.
 pub fn path_to_string(path: &Path, sep: &str) -> String {
@@ -28,36 +26,6 @@ pub fn path_to_string(path: &Path, sep: &str) -> String {
         .join(sep)
 }
 
-/// Retrieve value from an environment variable or if unst return the default value.
-pub fn get_env(varname: &str, default: &str) -> String {
-    match std::env::var(varname) {
-        Ok(value) => value,
-        Err(_) => default.to_string(),
-    }
-}
-
-/// Takes a message and returns a function that takes a variable, prints it with the message and
-/// returns the argument.
-///
-/// # Example:
-///
-/// ```rust
-/// Err("an error")
-///   .map_err(log("state of error is"))
-///   .map(|x|
-///     // do something else
-///     x
-///   )
-/// ```
-///
-/// Prints: `state of the error is: an error`
-pub fn log<T: fmt::Display>(msg: &'static str) -> impl Fn(T) -> T {
-    move |o| {
-        println!("{}: {}", msg, o);
-        o
-    }
-}
-
 /// Extract DID document from presentation and ensure that it matches a certain DID
 pub fn get_did_doc_from_presentation(
     presentation: &'_ Presentation,
@@ -75,10 +43,10 @@ pub fn get_did_doc_from_presentation(
                             |acc, credential_subject| {
                                 let id_equals_proof_parameter_did =
                                     credential_subject.id.as_ref().and_then(|id| {
-                                        println!(
-                                            "credential subject DID: {:?}; expected DID: {:?}",
-                                            id.to_string(),
-                                            did
+                                        tracing::debug!(
+                                            credential_subject = %id,
+                                            expected_did = %did,
+                                            "inspecting credential subject"
                                         );
                                         if id.to_string() == did {
                                             Some(true)
@@ -150,28 +118,18 @@ pub fn compare_date(
 /// * `issuer_did` - DID of the isuser
 /// * `verification_relationship` - The verification relationship that's expected to be used
 /// * `presentation` - presentation to be inspected
-/// * returns the true if a mathing relationship was found otherwise an error
+/// * returns the verification method of the first matching proof otherwise an error
 // WARNING: do we need this?
 pub async fn verify_issuer(
     config: &rocket::State<Config>,
     issuer_did: &str,
     verification_relationship: VerificationRelationship,
     presentation: &Presentation,
-) -> Result<bool, DIDError> {
+) -> Result<String, DIDError> {
     // Retrieve all verification methods for the given DID
 
-    // INFO: Not sure how to replace this with a simple function call that integrates the test resolver on demand!
-    let mut resolvers: Vec<&dyn ssi::did_resolve::DIDResolver> = vec![];
-    #[cfg(test)]
-    let test_resolver = DIDWebTestResolver {
-        store: Some(&config.store),
-        ..DIDWebTestResolver::default()
-    };
-    #[cfg(test)]
-    resolvers.push(&test_resolver);
-    let default_resolver = config.reslover_options.get_resolver();
-    resolvers.push(&default_resolver);
-    let resolver = SeriesResolver { resolvers };
+    // locally hosted DIDs are resolved from the store, all other DIDs via the cached network resolvers
+    let resolver = config.get_resolver();
 
     let vmms: Vec<String> =
         get_verification_methods_for_all(&[issuer_did], verification_relationship, &resolver)
@@ -190,30 +148,51 @@ pub async fn verify_issuer(
             DIDError::PresentationInvalid("Presentation invalid, no proof found".to_string())
         })
         .and_then(|proofs| {
-            if proofs.any(|proof| {
-                proof
-                    .verification_method
-                    .as_ref()
-                    .and_then(|verification_method| {
-                        if vmms.contains(verification_method) {
-                            // println!("proof found {}", verification_method);
-                            Some(verification_method)
-                        } else {
-                            // println!("proof not found");
-                            None
-                        }
-                    })
-                    .is_some()
-            }) {
-                Ok(true)
-            } else {
-                Err(DIDError::PresentationInvalid(
-                    "Presentation invalid, no proof has been signed by expected DID".to_string(),
-                ))
-            }
+            proofs
+                .into_iter()
+                .find_map(|proof| {
+                    proof
+                        .verification_method
+                        .as_ref()
+                        .filter(|verification_method| vmms.contains(verification_method))
+                        .cloned()
+                })
+                .ok_or_else(|| {
+                    DIDError::PresentationInvalid(
+                        "Presentation invalid, no proof has been signed by expected DID"
+                            .to_string(),
+                    )
+                })
         })
 }
 
+/// Verification relationship of the methods that owners sign their presentations with.
+pub(crate) const OWNER_RELATIONSHIP: VerificationRelationship =
+    VerificationRelationship::Authentication;
+
+/// verify_owner ensures that the presentation has been signed by one of the owners.
+///
+/// # Arguments
+///
+/// * `config` - Server configuration
+/// * `owners` - DIDs of the owners
+/// * `presentation` - presentation to be inspected
+/// * returns the DID of the owner and the verification method of the first matching proof otherwise an error
+pub async fn verify_owner(
+    config: &rocket::State<Config>,
+    owners: &[String],
+    presentation: &Presentation,
+) -> Result<(String, String), DIDError> {
+    let mut error = DIDError::OwnerMissing("Owner not specified".to_string());
+    for owner in owners {
+        match verify_issuer(config, owner, OWNER_RELATIONSHIP, presentation).await {
+            Ok(verification_method) => return Ok((owner.to_string(), verification_method)),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
 // INFO: is provided by get_did_doc_from_presentation. I'll keep it around in case a separate verification is needed
 // /// verify_did_in_did_doc verifies that the DID is the subject of the DID Document or returns an error.
 // pub fn verify_did_in_did_doc(did: &str, did_doc: CredentialSubject) -> Result<bool, DIDError> {
@@ -235,34 +214,24 @@ pub async fn verify_issuer(
 //         })
 // }
 
-/// verify_presentation verifies the integrity and authenticity of a presentation and its included credentials.
-/// Returns the verification result and the first credential that was issued to the DID specified by id containing the
-/// new DID Document.
-pub async fn verify_presentation(
+/// verify_presentation_proof verifies the integrity and authenticity of a presentation against the proof parameters.
+pub async fn verify_presentation_proof(
     config: &rocket::State<Config>,
-    proof_parameters: ProofParameters,
-    presentation: Json<Presentation>,
-) -> Result<(VerificationResult, Credential, CredentialSubject), DIDError> {
+    proof_parameters: &ProofParameters,
+    presentation: &Presentation,
+) -> Result<VerificationResult, DIDError> {
+    // records the duration of the verification when the timer is dropped
+    let _timer = metrics::PRESENTATION_VERIFICATION.start_timer();
     let opts = LinkedDataProofOptions {
-        challenge: proof_parameters.challenge, // fail if challenge is not present
+        challenge: proof_parameters.challenge.clone(), // fail if challenge is not present
         domain: Some(proof_parameters.domain.to_string()),
-        proof_purpose: Some(proof_parameters.proof_purpose),
+        proof_purpose: Some(proof_parameters.proof_purpose.clone()),
         // created: xx; // TODO this is set to now_ms, not sure if that's correct .. I guess that is should have be created max a minute ago
         ..LinkedDataProofOptions::default()
     };
 
-    // INFO: Not sure how to replace this with a simple function call that integrates the test resolver on demand!
-    let mut resolvers: Vec<&dyn ssi::did_resolve::DIDResolver> = vec![];
-    #[cfg(test)]
-    let test_resolver = DIDWebTestResolver {
-        store: Some(&config.store),
-        ..DIDWebTestResolver::default()
-    };
-    #[cfg(test)]
-    resolvers.push(&test_resolver);
-    let default_resolver = config.reslover_options.get_resolver();
-    resolvers.push(&default_resolver);
-    let resolver = SeriesResolver { resolvers };
+    // locally hosted DIDs are resolved from the store, all other DIDs via the cached network resolvers
+    let resolver = config.get_resolver();
 
     let mut context_loader = ContextLoader::default();
     let result = presentation
@@ -280,6 +249,18 @@ pub async fn verify_presentation(
             "Presentation invalid, verification failed".to_string(),
         ));
     }
+    Ok(result)
+}
+
+/// verify_presentation verifies the integrity and authenticity of a presentation and its included credentials.
+/// Returns the verification result and the first credential that was issued to the DID specified by id containing the
+/// new DID Document.
+pub async fn verify_presentation(
+    config: &rocket::State<Config>,
+    proof_parameters: ProofParameters,
+    presentation: Json<Presentation>,
+) -> Result<(VerificationResult, Credential, CredentialSubject), DIDError> {
+    let result = verify_presentation_proof(config, &proof_parameters, &presentation).await?;
 
     let presentation = presentation.into_inner();
     let (vc, new_did_doc) = get_did_doc_from_presentation(&presentation, proof_parameters.did)?;
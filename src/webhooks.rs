@@ -0,0 +1,655 @@
+// SPDX-License-Identifier: AGPL-3.0
+
+use chrono::Utc;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::tokio::{task, time};
+use rocket::{Orbit, Rocket};
+use serde::{Deserialize, Serialize};
+use sha256::digest;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::audit::{append_line, sign};
+use crate::changes::{Change, ChangeFeed, PAGE_SIZE};
+use crate::config::Config;
+use crate::error::DIDError;
+use crate::metrics;
+
+/// Maximum duration of a single delivery attempt.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Endpoint that receives a POST request for every change of a DID document.
+///
+/// * `url` - URL of the endpoint, e.g. `https://iam.example.com/hooks/dws`
+/// * `secret` - Secret that the HMAC-SHA256 signature of each delivery is computed with
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Webhook {
+    pub url: String,
+    pub secret: String,
+}
+
+/// Record of a delivery that failed permanently.
+///
+/// * `timestamp` - Time of the last attempt in RFC 3339 format
+/// * `url` - URL of the webhook
+/// * `attempts` - Number of attempts
+/// * `error` - Reason of the last failure
+/// * `delivery` - Change that couldn't be delivered
+/// * `skipped` - Changes that have been compacted before they could be delivered, set instead of `delivery`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeadLetter {
+    pub timestamp: String,
+    pub url: String,
+    pub attempts: u32,
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivery: Option<Change>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<SkippedChanges>,
+}
+
+/// Range of sequence numbers of changes that have been skipped.
+///
+/// * `first` - Sequence number of the first skipped change
+/// * `last` - Sequence number of the last skipped change
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SkippedChanges {
+    pub first: u64,
+    pub last: u64,
+}
+
+/// Configured webhooks and their delivery settings.
+///
+/// * `endpoints` - Webhooks that changes are delivered to. Set via DWS_WEBHOOKS variable
+/// * `attempts` - Number of attempts before a delivery fails permanently. Set via DWS_WEBHOOK_ATTEMPTS variable, e.g. `5`
+/// * `backoff` - Delay before the first retry, doubled after every attempt
+/// * `file` - File that dead letters are appended to
+/// * `cursors` - Directory that the sequence number of the last change handled by each webhook is written to
+#[derive(Debug)]
+pub struct Webhooks {
+    pub endpoints: Vec<Webhook>,
+    pub attempts: u32,
+    pub backoff: Duration,
+    file: Option<PathBuf>,
+    cursors: Option<PathBuf>,
+    dead_letters: Mutex<Vec<DeadLetter>>,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self {
+            endpoints: vec![],
+            attempts: 5,
+            backoff: Duration::from_secs(1),
+            file: None,
+            cursors: None,
+            dead_letters: Mutex::new(vec![]),
+        }
+    }
+}
+
+/// Outcome of a single delivery attempt.
+enum Attempt {
+    Delivered,
+    Retry(String),
+    Failed(String),
+}
+
+impl Webhooks {
+    /// Creates the webhooks.
+    ///
+    /// - `endpoints` - webhooks that changes are delivered to
+    /// - `attempts` - number of attempts before a delivery fails permanently
+    /// - `file` - file that dead letters are appended to, dead letters are kept in memory only if not set.
+    /// - `cursors` - directory that the cursors of the webhooks are written to, delivery starts with the changes after
+    ///   the start of the server if not set.
+    pub fn new(
+        endpoints: Vec<Webhook>,
+        attempts: u32,
+        file: Option<PathBuf>,
+        cursors: Option<PathBuf>,
+    ) -> Self {
+        Webhooks {
+            endpoints,
+            attempts: attempts.max(1),
+            file,
+            cursors,
+            ..Webhooks::default()
+        }
+    }
+
+    /// Retrieves all dead letters.
+    pub fn dead_letters(&self) -> Result<Vec<DeadLetter>, DIDError> {
+        match &self.file {
+            Some(file) if file.exists() => read_dead_letters(file),
+            Some(_) => Ok(vec![]),
+            None => Ok(self.dead_letters.lock().unwrap().clone()),
+        }
+    }
+
+    /// Retrieves the sequence number of the last change that has been delivered to a webhook or recorded as dead
+    /// letter.
+    ///
+    /// - `webhook` - receiver of the changes
+    /// - returns the sequence number, or `None` if the webhook hasn't handled a change yet or cursors aren't persisted
+    pub async fn cursor(&self, webhook: &Webhook) -> Result<Option<u64>, DIDError> {
+        let Some(file) = self.cursor_file(webhook) else {
+            return Ok(None);
+        };
+        task::spawn_blocking(move || match fs::read_to_string(&file) {
+            Ok(content) => content
+                .trim()
+                .parse::<u64>()
+                .map(Some)
+                .map_err(|e| DIDError::ContentConversion(e.to_string())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(DIDError::NoFileRead(e.to_string())),
+        })
+        .await
+        .map_err(|e| DIDError::NoFileRead(e.to_string()))?
+    }
+
+    /// Records the sequence number of the last change that has been delivered to a webhook or recorded as dead letter.
+    /// Failures are logged, the changes since the previous cursor are delivered again after a restart.
+    ///
+    /// - `webhook` - receiver of the changes
+    /// - `sequence` - sequence number of the change
+    pub async fn advance(&self, webhook: &Webhook, sequence: u64) {
+        let Some(file) = self.cursor_file(webhook) else {
+            return;
+        };
+        let result = task::spawn_blocking(move || write_cursor(&file, sequence))
+            .await
+            .map_err(|e| DIDError::NoFileWrite(e.to_string()))
+            .and_then(|result| result);
+        if let Err(e) = result {
+            tracing::error!(
+                url = %webhook.url,
+                sequence,
+                error = e.name(),
+                reason = e.message(),
+                "webhook cursor not recorded"
+            );
+        }
+    }
+
+    /// File of a webhook's cursor, named after the hash of the webhook's URL.
+    fn cursor_file(&self, webhook: &Webhook) -> Option<PathBuf> {
+        self.cursors
+            .as_ref()
+            .map(|directory| directory.join(format!("{}.cursor", digest(webhook.url.as_str()))))
+    }
+
+    /// Attempts to deliver a change to a webhook once, so that a failing change doesn't hold up the later changes.
+    /// Deliveries that failed permanently are recorded as dead letter.
+    ///
+    /// - `client` - HTTP client
+    /// - `webhook` - receiver of the change
+    /// - `delivery` - change that's delivered
+    /// - returns `false` if the delivery failed temporarily and has to be retried via [`Webhooks::deliver`]
+    pub async fn try_deliver(
+        &self,
+        client: &reqwest::Client,
+        webhook: &Webhook,
+        delivery: &Change,
+    ) -> bool {
+        let Some(body) = payload(webhook, delivery) else {
+            return true;
+        };
+        match attempt(client, webhook, delivery.sequence, &body).await {
+            Attempt::Delivered => {
+                metrics::WEBHOOK_DELIVERIES
+                    .with_label_values(&["delivered"])
+                    .inc();
+                true
+            }
+            Attempt::Retry(error) if self.attempts > 1 => {
+                metrics::WEBHOOK_DELIVERIES
+                    .with_label_values(&["retry"])
+                    .inc();
+                tracing::warn!(
+                    url = %webhook.url,
+                    sequence = delivery.sequence,
+                    error = %error,
+                    "webhook delivery failed, retrying in the background"
+                );
+                false
+            }
+            Attempt::Retry(error) | Attempt::Failed(error) => {
+                self.fail(webhook, 1, error, delivery.clone()).await;
+                true
+            }
+        }
+    }
+
+    /// Delivers a change to a webhook. Failed attempts are retried with exponential backoff, permanent failures are
+    /// recorded as dead letter.
+    ///
+    /// - `client` - HTTP client
+    /// - `webhook` - receiver of the change
+    /// - `delivery` - change that's delivered
+    /// - `failed` - number of attempts that have already failed, e.g. via [`Webhooks::try_deliver`]
+    pub async fn deliver(
+        &self,
+        client: &reqwest::Client,
+        webhook: &Webhook,
+        delivery: Change,
+        failed: u32,
+    ) {
+        let Some(body) = payload(webhook, &delivery) else {
+            return;
+        };
+        let mut backoff = self.backoff;
+        let mut attempts = failed;
+        let error = loop {
+            if attempts > 0 {
+                time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            attempts += 1;
+            match attempt(client, webhook, delivery.sequence, &body).await {
+                Attempt::Delivered => {
+                    metrics::WEBHOOK_DELIVERIES
+                        .with_label_values(&["delivered"])
+                        .inc();
+                    return;
+                }
+                Attempt::Retry(error) if attempts < self.attempts => {
+                    metrics::WEBHOOK_DELIVERIES
+                        .with_label_values(&["retry"])
+                        .inc();
+                    tracing::warn!(
+                        url = %webhook.url,
+                        sequence = delivery.sequence,
+                        error = %error,
+                        "webhook delivery failed, retrying"
+                    );
+                }
+                Attempt::Retry(error) | Attempt::Failed(error) => break error,
+            }
+        };
+        self.fail(webhook, attempts, error, delivery).await;
+    }
+
+    /// Records a delivery that failed permanently as dead letter.
+    async fn fail(&self, webhook: &Webhook, attempts: u32, error: String, delivery: Change) {
+        metrics::WEBHOOK_DELIVERIES
+            .with_label_values(&["failed"])
+            .inc();
+        tracing::error!(
+            url = %webhook.url,
+            sequence = delivery.sequence,
+            error = %error,
+            "webhook delivery failed permanently"
+        );
+        self.record_or_log(DeadLetter {
+            timestamp: Utc::now().to_rfc3339(),
+            url: webhook.url.clone(),
+            attempts,
+            error,
+            delivery: Some(delivery),
+            skipped: None,
+        })
+        .await;
+    }
+
+    /// Records changes that have been compacted before they could be delivered as dead letter.
+    ///
+    /// - `webhook` - receiver of the changes
+    /// - `skipped` - sequence numbers of the compacted changes
+    pub async fn skip(&self, webhook: &Webhook, skipped: SkippedChanges) {
+        self.record_or_log(DeadLetter {
+            timestamp: Utc::now().to_rfc3339(),
+            url: webhook.url.clone(),
+            attempts: 0,
+            error: "Changes compacted before delivery".to_string(),
+            delivery: None,
+            skipped: Some(skipped),
+        })
+        .await;
+    }
+
+    /// Records a dead letter and logs failures.
+    async fn record_or_log(&self, dead_letter: DeadLetter) {
+        if let Err(e) = self.record(dead_letter).await {
+            tracing::error!(
+                error = e.name(),
+                reason = e.message(),
+                "dead letter not recorded"
+            );
+        }
+    }
+
+    /// Records a dead letter.
+    async fn record(&self, dead_letter: DeadLetter) -> Result<(), DIDError> {
+        match &self.file {
+            Some(file) => {
+                let line = serde_json::to_string(&dead_letter)
+                    .map_err(|e| DIDError::ContentConversion(e.to_string()))?;
+                let file = file.to_path_buf();
+                task::spawn_blocking(move || append_line(&file, &line))
+                    .await
+                    .map_err(|e| DIDError::NoFileWrite(e.to_string()))?
+            }
+            None => {
+                self.dead_letters.lock().unwrap().push(dead_letter);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Computes the value of the `X-DWS-Signature` header: the hex encoded HMAC-SHA256 of `<timestamp>.<body>`, prefixed
+/// with `sha256=`. Receivers recompute it with the shared secret and reject deliveries with an outdated timestamp.
+pub fn signature(secret: &str, timestamp: &str, body: &str) -> String {
+    format!(
+        "sha256={}",
+        sign(secret, &format!("{}.{}", timestamp, body))
+    )
+}
+
+/// Serialises a change into the body of a delivery. Failures are logged.
+fn payload(webhook: &Webhook, delivery: &Change) -> Option<String> {
+    serde_json::to_string(delivery)
+        .map_err(|e| {
+            tracing::error!(url = %webhook.url, error = %e, "webhook payload not serialised");
+        })
+        .ok()
+}
+
+/// Sends a signed delivery once. Client errors other than `408 - Request Timeout` and `429 - Too Many Requests` aren't
+/// retried.
+async fn attempt(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    sequence: u64,
+    body: &str,
+) -> Attempt {
+    let timestamp = Utc::now().timestamp().to_string();
+    let result = client
+        .post(&webhook.url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header("Content-Type", "application/json")
+        .header("X-DWS-Delivery", sequence.to_string())
+        .header("X-DWS-Timestamp", &timestamp)
+        .header(
+            "X-DWS-Signature",
+            signature(&webhook.secret, &timestamp, body),
+        )
+        .body(body.to_string())
+        .send()
+        .await;
+    match result {
+        Ok(response) if response.status().is_success() => Attempt::Delivered,
+        Ok(response)
+            if response.status().is_client_error()
+                && !matches!(response.status().as_u16(), 408 | 429) =>
+        {
+            Attempt::Failed(format!("Webhook rejected delivery: {}", response.status()))
+        }
+        Ok(response) => Attempt::Retry(format!("Webhook failed: {}", response.status())),
+        Err(e) => Attempt::Retry(format!("Webhook unavailable: {}", e)),
+    }
+}
+
+/// Reads the dead letters of a file.
+fn read_dead_letters(file: &Path) -> Result<Vec<DeadLetter>, DIDError> {
+    fs::read_to_string(file)
+        .map_err(|e| DIDError::NoFileRead(e.to_string()))
+        .and_then(|content| {
+            content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    serde_json::from_str::<DeadLetter>(line)
+                        .map_err(|e| DIDError::ContentConversion(e.to_string()))
+                })
+                .collect()
+        })
+}
+
+/// Replaces the content of a cursor file with a sequence number. The sequence number is written to a temporary file
+/// that's moved into place, so that a crash never leaves a partial cursor behind.
+fn write_cursor(file: &Path, sequence: u64) -> Result<(), DIDError> {
+    let temporary = file.with_extension("tmp");
+    file.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| File::create(&temporary))
+        .and_then(|mut f| {
+            writeln!(f, "{}", sequence)?;
+            f.sync_all()
+        })
+        .and_then(|_| fs::rename(&temporary, file))
+        .map_err(|e| DIDError::NoFileWrite(e.to_string()))
+}
+
+/// Retries a delivery in the background and removes it from the retried changes once it's been delivered or recorded
+/// as dead letter.
+async fn retry(
+    webhooks: Arc<Webhooks>,
+    client: reqwest::Client,
+    webhook: Webhook,
+    change: Change,
+    retrying: Arc<Mutex<BTreeSet<u64>>>,
+) {
+    let sequence = change.sequence;
+    webhooks.deliver(&client, &webhook, change, 1).await;
+    retrying.lock().unwrap().remove(&sequence);
+}
+
+/// Delivers all changes to a webhook in the order of their sequence numbers until the server shuts down. A change
+/// whose delivery fails temporarily is retried in the background, so that it doesn't hold up the later changes.
+/// Delivery resumes after the webhook's cursor, so changes that were made while the server was stopped and deliveries
+/// that were interrupted by a shutdown are delivered after a restart.
+async fn run(
+    webhooks: Arc<Webhooks>,
+    webhook: Webhook,
+    changes: Arc<ChangeFeed>,
+    shutdown: rocket::Shutdown,
+) {
+    let client = reqwest::Client::new();
+    let mut receiver = changes.subscribe();
+    let retrying = Arc::new(Mutex::new(BTreeSet::new()));
+    let mut last = match webhooks.cursor(&webhook).await {
+        Ok(Some(sequence)) => sequence,
+        // new webhooks receive the changes from now on
+        Ok(None) => changes.head(),
+        Err(e) => {
+            tracing::error!(
+                url = %webhook.url,
+                error = e.name(),
+                reason = e.message(),
+                "webhook cursor not read, changes aren't delivered"
+            );
+            return;
+        }
+    };
+    loop {
+        let pending = match changes.since(last, PAGE_SIZE) {
+            Ok(pending) => pending,
+            Err(e) => {
+                // the changes are gone, delivery continues with the oldest retained change
+                let oldest = changes.oldest();
+                tracing::error!(
+                    url = %webhook.url,
+                    missed = oldest - last - 1,
+                    error = e.name(),
+                    reason = e.message(),
+                    "changes compacted before delivery"
+                );
+                webhooks
+                    .skip(
+                        &webhook,
+                        SkippedChanges {
+                            first: last + 1,
+                            last: oldest - 1,
+                        },
+                    )
+                    .await;
+                last = oldest - 1;
+                webhooks.advance(&webhook, last).await;
+                continue;
+            }
+        };
+        if pending.is_empty() {
+            select! {
+                _ = shutdown.clone() => return,
+                // the changes are read from the feed, so lagging behind loses nothing
+                received = receiver.recv() => if let Err(RecvError::Closed) = received {
+                    return;
+                },
+            }
+        }
+        for change in pending {
+            let sequence = change.sequence;
+            // an interrupted delivery doesn't advance the cursor, so it's repeated after a restart
+            select! {
+                _ = shutdown.clone() => return,
+                handled = webhooks.try_deliver(&client, &webhook, &change) => if !handled {
+                    retrying.lock().unwrap().insert(sequence);
+                    rocket::tokio::spawn(retry(
+                        webhooks.clone(),
+                        client.clone(),
+                        webhook.clone(),
+                        change,
+                        retrying.clone(),
+                    ));
+                }
+            }
+            last = sequence;
+            // the cursor stays before changes that are still retried, so they're delivered again after a restart
+            let cursor = retrying
+                .lock()
+                .unwrap()
+                .first()
+                .map_or(last, |first| first - 1);
+            webhooks.advance(&webhook, cursor).await;
+        }
+    }
+}
+
+/// Fairing that delivers changes of DID documents to the configured webhooks. It does nothing unless DWS_WEBHOOKS is
+/// set.
+pub struct WebhookDelivery;
+
+#[rocket::async_trait]
+impl Fairing for WebhookDelivery {
+    fn info(&self) -> Info {
+        Info {
+            name: "Webhook delivery",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        if let Some(config) = rocket.state::<Config>() {
+            for webhook in &config.webhooks.endpoints {
+                rocket::tokio::spawn(run(
+                    config.webhooks.clone(),
+                    webhook.clone(),
+                    config.changes.clone(),
+                    rocket.shutdown(),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::audit::Operation;
+    use crate::webhooks::*;
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("secret", "1700000000", "{}"),
+            format!("sha256={}", sign("secret", "1700000000.{}")),
+            "When a delivery is signed, then the timestamp and body are signed"
+        );
+        assert_ne!(
+            signature("secret", "1700000000", "{}"),
+            signature("secret", "1700000001", "{}"),
+            "When a delivery is replayed with a different timestamp, then the signature doesn't match"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_dead_letter() {
+        let webhooks = Webhooks {
+            backoff: Duration::from_millis(1),
+            ..Webhooks::new(vec![], 2, None, None)
+        };
+        let webhook = Webhook {
+            // nothing listens on the discard port
+            url: "http://127.0.0.1:9/".to_string(),
+            secret: "secret".to_string(),
+        };
+        let delivery = Change {
+            sequence: 1,
+            timestamp: Utc::now().to_rfc3339(),
+            operation: Operation::Delete,
+            did: "did:web:localhost:alice".to_string(),
+            id: PathBuf::from("alice/did.json"),
+            document: None,
+        };
+        let client = reqwest::Client::new();
+        assert!(
+            !webhooks.try_deliver(&client, &webhook, &delivery).await,
+            "When a webhook is unavailable, then the delivery has to be retried"
+        );
+        webhooks.deliver(&client, &webhook, delivery, 1).await;
+        webhooks
+            .skip(&webhook, SkippedChanges { first: 2, last: 4 })
+            .await;
+        let dead_letters = webhooks.dead_letters().unwrap();
+        assert_eq!(
+            dead_letters
+                .iter()
+                .map(|dead_letter| (
+                    dead_letter.attempts,
+                    dead_letter.delivery.as_ref().map(|delivery| delivery.sequence)
+                ))
+                .collect::<Vec<(u32, Option<u64>)>>(),
+            vec![(2, Some(1)), (0, None)],
+            "When a webhook is unavailable, then the delivery is retried and recorded as dead letter"
+        );
+        assert_eq!(
+            dead_letters[1].skipped,
+            Some(SkippedChanges { first: 2, last: 4 }),
+            "When changes are compacted before delivery, then the skipped range is recorded as dead letter"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_cursor() {
+        let directory =
+            std::env::temp_dir().join(format!("dws-webhook-cursors-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let webhooks = Webhooks::new(vec![], 1, None, Some(directory.clone()));
+        let webhook = Webhook {
+            url: "https://iam.example.com/hooks/dws".to_string(),
+            secret: "secret".to_string(),
+        };
+        assert_eq!(
+            webhooks.cursor(&webhook).await.unwrap(),
+            None,
+            "When a webhook hasn't handled a change yet, then it has no cursor"
+        );
+        webhooks.advance(&webhook, 2).await;
+        webhooks.advance(&webhook, 3).await;
+        let webhooks = Webhooks::new(vec![], 1, None, Some(directory.clone()));
+        assert_eq!(
+            webhooks.cursor(&webhook).await.unwrap(),
+            Some(3),
+            "When the webhooks are recreated, then delivery resumes after the last handled change"
+        );
+        let _ = fs::remove_dir_all(&directory);
+    }
+}